@@ -0,0 +1,59 @@
+use std::{fmt::Write, sync::Arc};
+
+use axum::{
+    extract::FromRequestParts,
+    http::{header::AUTHORIZATION, request::Parts, StatusCode},
+};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use shared::store::ApiUser;
+
+use crate::routes::{ApiError, AppState};
+
+/// 32 random bytes, hex encoded. shown once when created, only its hash is stored
+pub fn new_token() -> String {
+    let mut bytes = [0; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex(&bytes)
+}
+
+pub fn hash(token: &str) -> String {
+    hex(&Sha256::digest(token.as_bytes()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, b| {
+        let _ = write!(hex, "{b:02x}");
+        hex
+    })
+}
+
+/// the user of the `Authorization: Bearer <token>` header, who must be activated
+pub struct User(pub ApiUser);
+
+impl FromRequestParts<Arc<AppState>> for User {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let token = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or(ApiError(
+                StatusCode::UNAUTHORIZED,
+                "missing bearer token".into(),
+            ))?;
+        match state.store.token_user(&hash(token.trim())).await? {
+            Some(user) if user.activated => Ok(User(user)),
+            Some(user) => Err(ApiError(
+                StatusCode::FORBIDDEN,
+                format!("user {} isn't activated", user.name),
+            )),
+            None => Err(ApiError(StatusCode::UNAUTHORIZED, "invalid token".into())),
+        }
+    }
+}
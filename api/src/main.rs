@@ -0,0 +1,122 @@
+mod auth;
+mod routes;
+
+use std::{process::exit, sync::Arc};
+
+use anyhow::{bail, Result};
+use clap::{Parser, Subcommand};
+use routes::AppState;
+use shared::{
+    config::{ConfigArgs, ConfigCommand},
+    logging, store,
+};
+use tracing::{error, info};
+
+#[derive(Parser, Debug)]
+struct Cli {
+    #[command(flatten)]
+    config: ConfigArgs,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Serve the api, the default
+    Serve,
+    /// Create or revoke the tokens of the api
+    Token {
+        #[command(subcommand)]
+        command: TokenCommand,
+    },
+    /// Allow or forbid a user to use the api
+    User {
+        #[command(subcommand)]
+        command: UserCommand,
+    },
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum TokenCommand {
+    /// Print a new token for this user, who must exist: signed up with surrealdb, or created by
+    /// `user activate` with sqlite
+    Create {
+        user: String,
+        #[arg(long, default_value = "", help = "What the token is for")]
+        label: String,
+    },
+    /// Delete a token
+    Revoke { token: String },
+}
+
+#[derive(Subcommand, Debug)]
+enum UserCommand {
+    Activate { name: String },
+    Deactivate { name: String },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let config = cli.config.loader("api")?.load();
+    let _logging = logging::init("api", "api=info", config.as_ref().ok())?;
+    let config = config.unwrap_or_else(|e| {
+        error!("config: {e:?}");
+        exit(1);
+    });
+    if let Some(Command::Config {
+        command: ConfigCommand::Check,
+    }) = cli.command
+    {
+        print!("{}", config.redacted_toml()?);
+        return Ok(());
+    }
+
+    let store = store::connect(&config).await?;
+    store::ensure_schema(store.as_ref(), &config).await?;
+
+    match cli.command {
+        Some(Command::Token {
+            command: TokenCommand::Create { user, label },
+        }) => {
+            let token = auth::new_token();
+            store
+                .create_token(&user, &auth::hash(&token), &label)
+                .await?;
+            eprintln!("token of {user}, it won't be shown again:");
+            println!("{token}");
+        }
+        Some(Command::Token {
+            command: TokenCommand::Revoke { token },
+        }) => {
+            if !store.delete_token(&auth::hash(&token)).await? {
+                bail!("no such token");
+            }
+            info!("token revoked");
+        }
+        Some(Command::User { command }) => {
+            let (name, activated) = match command {
+                UserCommand::Activate { name } => (name, true),
+                UserCommand::Deactivate { name } => (name, false),
+            };
+            if !store.set_user_activated(&name, activated).await? {
+                bail!("no user {name}, users sign up in the web ui");
+            }
+            info!(user = %name, activated, "user updated");
+        }
+        Some(Command::Serve) | None => {
+            let listen = config.api_listen.as_deref().unwrap_or("127.0.0.1:8091");
+            let app = routes::router(Arc::new(AppState { store }));
+            let listener = tokio::net::TcpListener::bind(listen).await?;
+            info!(listen, "serving the api, see /openapi.json");
+            axum::serve(listener, app).await?;
+        }
+        Some(Command::Config { .. }) => unreachable!("handled before connecting"),
+    }
+    Ok(())
+}
@@ -0,0 +1,389 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use shared::{
+    store::{Cursor, NewsFilter, NewsStore, NewsUpdate},
+    translate::Translation,
+    DbNews,
+};
+use tracing::{error, info};
+
+use crate::auth::User;
+
+/// most news in a page, whatever `limit` asks
+const MAX_LIMIT: usize = 500;
+
+const OPENAPI: &str = include_str!("../openapi.json");
+
+pub struct AppState {
+    pub store: Arc<dyn NewsStore>,
+}
+
+/// an error status with a `{"error": "..."}` body
+#[derive(Debug)]
+pub struct ApiError(pub StatusCode, pub String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+/// a database error, the details are only logged
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        error!(error = %format!("{e:#}"), "request failed");
+        ApiError(StatusCode::INTERNAL_SERVER_ERROR, "internal error".into())
+    }
+}
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/news", get(list_news))
+        .route("/news/{id}", get(get_news).patch(update_news))
+        .route("/openapi.json", get(openapi))
+        .with_state(state)
+}
+
+/// the query string of `GET /news`, see `openapi.json`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NewsQuery {
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    provider: Option<String>,
+    region: Option<String>,
+    /// comma separated, news having all of them
+    tags: Option<String>,
+    min_rating: Option<u8>,
+    max_rating: Option<u8>,
+    min_rating_travel: Option<u8>,
+    max_rating_travel: Option<u8>,
+    used: Option<bool>,
+    lang: Option<String>,
+    q: Option<String>,
+    after: Option<Cursor>,
+    limit: Option<usize>,
+}
+
+impl From<NewsQuery> for NewsFilter {
+    fn from(query: NewsQuery) -> Self {
+        NewsFilter {
+            since: query.since,
+            until: query.until,
+            provider: query.provider,
+            region: query.region,
+            tags: query
+                .tags
+                .iter()
+                .flat_map(|tags| tags.split(','))
+                .map(|tag| tag.trim().to_string())
+                .filter(|tag| !tag.is_empty())
+                .collect(),
+            min_rating: query.min_rating,
+            max_rating: query.max_rating,
+            min_rating_travel: query.min_rating_travel,
+            max_rating_travel: query.max_rating_travel,
+            used: query.used,
+            lang: query.lang,
+            text: query.q.filter(|q| !q.trim().is_empty()),
+            after: query.after,
+            limit: Some(query.limit.unwrap_or(50).clamp(1, MAX_LIMIT)),
+        }
+    }
+}
+
+/// a news as the api shows it. the bodies and translations are only in `GET /news/{id}`
+#[derive(Debug, Serialize)]
+struct ApiNews {
+    id: String,
+    date: DateTime<Utc>,
+    title: String,
+    caption: String,
+    link: String,
+    provider: String,
+    rating: Option<u8>,
+    rating_travel: Option<u8>,
+    tags: Vec<String>,
+    used: bool,
+    note: String,
+    lang: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    html_body: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text_body: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    translations: Option<BTreeMap<String, Translation>>,
+}
+
+impl ApiNews {
+    fn new(news: DbNews, full: bool) -> ApiNews {
+        ApiNews {
+            id: news.id.map(|id| id.to_string()).unwrap_or_default(),
+            date: news.date.0,
+            title: news.title.into(),
+            caption: news.caption.into(),
+            link: news.link.into(),
+            provider: news.provider.into(),
+            rating: news.rating,
+            rating_travel: news.rating_travel,
+            tags: news.tags,
+            used: news.used,
+            note: news.note.into(),
+            lang: news.lang,
+            html_body: full.then(|| news.html_body.into()),
+            text_body: full.then(|| news.text_body.into()),
+            translations: full.then_some(news.translations),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct NewsPage {
+    news: Vec<ApiNews>,
+    /// pass it as `after` to get the next page, `null` on the last one
+    next: Option<String>,
+}
+
+async fn list_news(
+    _: User,
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<NewsQuery>,
+) -> Result<Json<NewsPage>, ApiError> {
+    let filter = NewsFilter::from(query);
+    let news = state.store.news(&filter).await?;
+    let next = match news.last() {
+        Some(last) if Some(news.len()) == filter.limit => Cursor::after(last),
+        _ => None,
+    };
+    Ok(Json(NewsPage {
+        news: news.into_iter().map(|n| ApiNews::new(n, false)).collect(),
+        next: next.map(|c| c.to_string()),
+    }))
+}
+
+async fn get_news(
+    _: User,
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiNews>, ApiError> {
+    match state.store.get_news(&id).await? {
+        Some(news) => Ok(Json(ApiNews::new(news, true))),
+        None => Err(ApiError(StatusCode::NOT_FOUND, format!("no news {id}"))),
+    }
+}
+
+async fn update_news(
+    User(user): User,
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(update): Json<NewsUpdate>,
+) -> Result<Json<ApiNews>, ApiError> {
+    match state.store.update_news(&id, &update).await? {
+        Some(news) => {
+            info!(user = %user.name, id, ?update, "news updated");
+            Ok(Json(ApiNews::new(news, true)))
+        }
+        None => Err(ApiError(StatusCode::NOT_FOUND, format!("no news {id}"))),
+    }
+}
+
+async fn openapi() -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "application/json")], OPENAPI)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::{to_bytes, Body},
+        http::Request,
+    };
+    use chrono::Duration;
+    use shared::store::SqliteStore;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::auth;
+
+    async fn app() -> (Router, Arc<SqliteStore>) {
+        let store = Arc::new(SqliteStore::open_in_memory().unwrap());
+        for (i, (provider, rating)) in [("fr::google", 80), ("fr::google", 30), ("be::rtbf", 90)]
+            .into_iter()
+            .enumerate()
+        {
+            store
+                .insert(&DbNews {
+                    title: format!("title {i}").into(),
+                    caption: "caption".into(),
+                    html_body: "<p>body</p>".into(),
+                    text_body: "body".into(),
+                    link: format!("https://a.fr/{i}").into(),
+                    provider: provider.into(),
+                    date: (Utc::now() - Duration::hours(i as i64)).into(),
+                    tags: vec![provider[..2].to_string()],
+                    rating: Some(rating),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        store.set_user_activated("alice", true).await.unwrap();
+        store
+            .create_token("alice", &auth::hash("secret"), "test")
+            .await
+            .unwrap();
+        (
+            router(Arc::new(AppState {
+                store: store.clone(),
+            })),
+            store,
+        )
+    }
+
+    async fn call(
+        app: &Router,
+        method: &str,
+        uri: &str,
+        token: Option<&str>,
+        body: Option<serde_json::Value>,
+    ) -> (StatusCode, serde_json::Value) {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            request = request.header("authorization", format!("Bearer {token}"));
+        }
+        let body = match body {
+            Some(body) => {
+                request = request.header("content-type", "application/json");
+                Body::from(body.to_string())
+            }
+            None => Body::empty(),
+        };
+        let response = app
+            .clone()
+            .oneshot(request.body(body).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn it_needs_a_token_of_an_activated_user() {
+        let (app, store) = app().await;
+        let (status, _) = call(&app, "GET", "/news", None, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = call(&app, "GET", "/news", Some("wrong"), None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = call(&app, "GET", "/news", Some("secret"), None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        store.set_user_activated("alice", false).await.unwrap();
+        let (status, body) = call(&app, "GET", "/news", Some("secret"), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["error"], "user alice isn't activated");
+
+        let (status, _) = call(&app, "GET", "/openapi.json", None, None).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn it_lists_news_with_filters_and_pages() {
+        let (app, _) = app().await;
+        let token = Some("secret");
+        let (_, body) = call(&app, "GET", "/news?region=fr&min_rating=50", token, None).await;
+        let news = body["news"].as_array().unwrap();
+        assert_eq!(news.len(), 1);
+        assert_eq!(news[0]["title"], "title 0");
+        assert!(news[0].get("html_body").is_none());
+        assert_eq!(body["next"], serde_json::Value::Null);
+
+        let (_, first) = call(&app, "GET", "/news?limit=2", token, None).await;
+        assert_eq!(first["news"].as_array().unwrap().len(), 2);
+        let next = first["next"].as_str().unwrap();
+        let (_, second) = call(
+            &app,
+            "GET",
+            &format!("/news?limit=2&after={next}"),
+            token,
+            None,
+        )
+        .await;
+        let second = second["news"].as_array().unwrap();
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0]["title"], "title 2");
+
+        let (status, _) = call(&app, "GET", "/news?nope=1", token, None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn it_gets_and_updates_a_news() {
+        let (app, _) = app().await;
+        let token = Some("secret");
+        let (status, news) = call(&app, "GET", "/news/1", token, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(news["html_body"], "<p>body</p>");
+
+        let update = serde_json::json!({ "used": true, "note": "posted", "tags": ["fr", "cats"] });
+        let (status, news) = call(&app, "PATCH", "/news/1", token, Some(update)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(news["used"], true);
+        assert_eq!(news["note"], "posted");
+        assert_eq!(news["tags"], serde_json::json!(["fr", "cats"]));
+
+        let (_, body) = call(&app, "GET", "/news?used=true&tags=cats", token, None).await;
+        assert_eq!(body["news"].as_array().unwrap().len(), 1);
+
+        let (status, _) = call(&app, "GET", "/news/42", token, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let update = serde_json::json!({ "rating": 100 });
+        let (status, _) = call(&app, "PATCH", "/news/1", token, Some(update)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[test]
+    fn it_describes_every_route() {
+        let openapi: serde_json::Value = serde_json::from_str(OPENAPI).unwrap();
+        let paths = openapi["paths"].as_object().unwrap();
+        for path in ["/news", "/news/{id}", "/openapi.json"] {
+            assert!(paths.contains_key(path), "{path} missing from openapi.json");
+        }
+        let parameters: Vec<_> = openapi["paths"]["/news"]["get"]["parameters"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["name"].as_str().unwrap().to_string())
+            .collect();
+        // every field of NewsQuery
+        for name in [
+            "since",
+            "until",
+            "provider",
+            "region",
+            "tags",
+            "min_rating",
+            "max_rating",
+            "min_rating_travel",
+            "max_rating_travel",
+            "used",
+            "lang",
+            "q",
+            "after",
+            "limit",
+        ] {
+            assert!(
+                parameters.contains(&name.to_string()),
+                "{name} not documented"
+            );
+        }
+    }
+}
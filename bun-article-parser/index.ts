@@ -1,8 +1,18 @@
 import { Context, Hono } from "hono";
-import { extract, extractFromHtml } from "@extractus/article-extractor";
+import { extractFromHtml } from "@extractus/article-extractor";
 
 const app = new Hono();
 
+// `"isAccessibleForFree": false` in the JSON-LD of the page is how the paywalls tell search
+// engines, null when the page doesn't say
+const accessibleForFree = (html: string): boolean | null => {
+  const match = html.match(/"isAccessibleForFree"\s*:\s*"?(true|false)"?/i);
+  return match ? match[1].toLowerCase() === "true" : null;
+};
+
+const DEFAULT_USER_AGENT =
+  "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/129.0.0.0 Safari/537.3";
+
 app.get("/fetch", async (c: Context) => {
   try {
     const url = c.req.query("url");
@@ -12,14 +22,20 @@ app.get("/fetch", async (c: Context) => {
     }
     const controller = new AbortController();
     setTimeout(() => controller.abort(), 5000);
-    const data = await extract(url, undefined, {
+    const res = await fetch(url, {
       signal: controller.signal,
       headers: {
-        "user-agent":
-          "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/129.0.0.0 Safari/537.3",
+        // the fetcher rotates them when it has a pool
+        "user-agent": c.req.query("user_agent") || DEFAULT_USER_AGENT,
       },
     });
-    return c.json(data);
+    if (!res.ok) {
+      throw new Error(`${url} returned ${res.status} ${res.statusText}`);
+    }
+    // read here rather than by `extract`, the JSON-LD is gone from what it returns
+    const html = await res.text();
+    const data = await extractFromHtml(html, res.url || url);
+    return c.json(data && { ...data, accessible_for_free: accessibleForFree(html) });
   } catch (err) {
     c.status(500);
     console.error(err);
@@ -36,11 +52,12 @@ app.post("/parse", async (c: Context) => {
       return c.json({ message: "no body provided" });
     }
 
-    const data = await extractFromHtml(body);
+    // the page url, when the fetcher downloaded it itself, resolves the relative links
+    const data = await extractFromHtml(body, c.req.query("url"));
     if (data == null) {
       c.status(500);
     }
-    return c.json(data);
+    return c.json(data && { ...data, accessible_for_free: accessibleForFree(body) });
   } catch (err) {
     c.status(500);
     console.error(err);
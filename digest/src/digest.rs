@@ -0,0 +1,239 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use minijinja::Environment;
+use serde::Serialize;
+use shared::DbNews;
+
+const HTML: &str = "digest.html.j2";
+const MARKDOWN: &str = "digest.md.j2";
+const TEXT: &str = "digest.txt.j2";
+
+type Score = fn(&DbNews) -> Option<u8>;
+
+/// the scores a news is ranked on, with the name shown in the digest
+const DIMENSIONS: &[(&str, Score)] = &[
+    ("positivity", |news| news.rating),
+    ("travel", |news| news.rating_travel),
+];
+
+/// what the templates get
+#[derive(Debug, Serialize)]
+pub struct Digest {
+    pub since: String,
+    pub until: String,
+    /// rated news in the period, before keeping the top ones
+    pub total: usize,
+    pub regions: Vec<Region>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Region {
+    /// the prefix of the providers, like `fr` for `fr::google`
+    pub name: String,
+    pub dimensions: Vec<Dimension>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Dimension {
+    pub name: &'static str,
+    /// best first
+    pub news: Vec<Item>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Item {
+    pub title: String,
+    pub caption: String,
+    pub provider: String,
+    pub link: String,
+    pub rating: Option<u8>,
+    pub rating_travel: Option<u8>,
+}
+
+impl From<&DbNews> for Item {
+    fn from(news: &DbNews) -> Self {
+        Item {
+            title: news.title.to_string(),
+            caption: news.caption.to_string(),
+            provider: news.provider.to_string(),
+            link: news.link.to_string(),
+            rating: news.rating,
+            rating_travel: news.rating_travel,
+        }
+    }
+}
+
+impl Digest {
+    /// the `top` best news of each region on each dimension
+    pub fn select(
+        news: &[DbNews],
+        top: usize,
+        since: DateTime<Local>,
+        until: DateTime<Local>,
+    ) -> Digest {
+        let mut regions: BTreeMap<&str, Vec<&DbNews>> = BTreeMap::new();
+        for news in news {
+            let region = news
+                .provider
+                .split_once("::")
+                .map_or(news.provider.as_ref(), |(region, _)| region);
+            regions.entry(region).or_default().push(news);
+        }
+        let regions = regions
+            .into_iter()
+            .map(|(name, news)| Region {
+                name: name.to_string(),
+                dimensions: DIMENSIONS
+                    .iter()
+                    .filter_map(|(dimension, score)| {
+                        let mut scored: Vec<_> =
+                            news.iter().filter_map(|n| Some((score(n)?, *n))).collect();
+                        // stable, so equal scores keep the newest first
+                        scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+                        let news: Vec<Item> = scored
+                            .into_iter()
+                            .take(top)
+                            .map(|(_, n)| n.into())
+                            .collect();
+                        (!news.is_empty()).then_some(Dimension {
+                            name: dimension,
+                            news,
+                        })
+                    })
+                    .collect(),
+            })
+            .collect();
+        Digest {
+            since: since.format("%d/%m/%Y %H:%M").to_string(),
+            until: until.format("%d/%m/%Y %H:%M").to_string(),
+            total: news.len(),
+            regions,
+        }
+    }
+}
+
+/// the HTML (email), Markdown (`--out`) and plain text (telegram and the other channels)
+/// templates, embedded in the binary unless `DIGEST_TEMPLATES` is a directory with its own
+/// `digest.html.j2`, `digest.md.j2` or `digest.txt.j2`
+pub struct Templates {
+    env: Environment<'static>,
+}
+
+impl Templates {
+    pub fn load(dir: Option<&Path>) -> Result<Templates> {
+        let mut env = Environment::new();
+        env.set_trim_blocks(true);
+        env.set_lstrip_blocks(true);
+        for (name, embedded) in [
+            (HTML, include_str!("../templates/digest.html.j2")),
+            (MARKDOWN, include_str!("../templates/digest.md.j2")),
+            (TEXT, include_str!("../templates/digest.txt.j2")),
+        ] {
+            let source = match dir.map(|dir| dir.join(name)).filter(|p| p.is_file()) {
+                Some(path) => fs::read_to_string(&path).with_context(|| format!("{path:?}"))?,
+                None => embedded.to_string(),
+            };
+            env.add_template_owned(name, source)
+                .with_context(|| format!("template {name}"))?;
+        }
+        Ok(Templates { env })
+    }
+
+    /// the html, markdown and plain text versions
+    pub fn render(&self, digest: &Digest) -> Result<(String, String, String)> {
+        let render = |name| -> Result<String> {
+            self.env
+                .get_template(name)?
+                .render(digest)
+                .with_context(|| format!("rendering {name}"))
+        };
+        Ok((render(HTML)?, render(MARKDOWN)?, render(TEXT)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn news(provider: &str, title: &str, rating: u8, rating_travel: Option<u8>) -> DbNews {
+        DbNews {
+            title: title.to_string().into(),
+            caption: "caption".into(),
+            provider: provider.to_string().into(),
+            link: format!("https://a.fr/{title}").into(),
+            rating: Some(rating),
+            rating_travel,
+            ..Default::default()
+        }
+    }
+
+    fn digest() -> Digest {
+        let news = [
+            news("fr::google", "meh", 20, Some(90)),
+            news("fr::positivr", "great", 95, None),
+            news("fr::google", "good", 70, Some(10)),
+            news("be::rtbf", "<b>bold</b>", 60, None),
+        ];
+        Digest::select(&news, 2, Local::now(), Local::now())
+    }
+
+    #[test]
+    fn it_keeps_the_top_news_per_region_and_dimension() {
+        let digest = digest();
+        assert_eq!(digest.total, 4);
+        let regions: Vec<_> = digest.regions.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(regions, ["be", "fr"]);
+
+        let fr = &digest.regions[1];
+        let titles = |d: &Dimension| d.news.iter().map(|n| n.title.clone()).collect::<Vec<_>>();
+        assert_eq!(fr.dimensions[0].name, "positivity");
+        assert_eq!(titles(&fr.dimensions[0]), ["great", "good"]);
+        assert_eq!(fr.dimensions[1].name, "travel");
+        assert_eq!(titles(&fr.dimensions[1]), ["meh", "good"]);
+        // no travel rating in belgium
+        assert_eq!(digest.regions[0].dimensions.len(), 1);
+    }
+
+    #[test]
+    fn it_renders_html_markdown_and_text() {
+        let (html, markdown, text) = Templates::load(None).unwrap().render(&digest()).unwrap();
+        assert!(html.contains("<h2>fr</h2>"));
+        assert!(html.contains("&lt;b&gt;bold&lt;&#x2f;b&gt;"), "{html}");
+        assert!(
+            html.contains(r#"<a href="https:&#x2f;&#x2f;a.fr&#x2f;great">"#),
+            "{html}"
+        );
+        assert!(markdown.contains("# fr\n"));
+        assert!(
+            markdown.contains(
+                "- **great** (fr::positivr, positivity 95)\n  caption\n  https://a.fr/great\n"
+            ),
+            "{markdown}"
+        );
+        // shown as is by telegram, no markup
+        assert!(text.contains("\nFR\n"), "{text}");
+        assert!(
+            text.contains(
+                "- great (fr::positivr, positivity 95)\n  caption\n  https://a.fr/great\n"
+            ),
+            "{text}"
+        );
+        assert!(!text.contains('#') && !text.contains("**"), "{text}");
+    }
+
+    #[test]
+    fn it_overrides_templates_from_a_directory() {
+        let dir = std::env::temp_dir().join(format!("digest-templates-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(MARKDOWN), "{{ total }} news").unwrap();
+        let (html, markdown, _) = Templates::load(Some(&dir))
+            .unwrap()
+            .render(&digest())
+            .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(markdown, "4 news");
+        assert!(html.starts_with("<!DOCTYPE html>"));
+    }
+}
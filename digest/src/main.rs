@@ -0,0 +1,105 @@
+mod digest;
+
+use std::{fs, path::PathBuf, process::exit};
+
+use anyhow::{Context, Result};
+use chrono::{Duration, Local};
+use clap::{Parser, Subcommand};
+use digest::{Digest, Templates};
+use shared::{
+    config::{ConfigArgs, ConfigCommand},
+    logging,
+    notify::{Message, Router, Severity},
+    store,
+};
+use tracing::{error, info};
+
+#[derive(Parser, Debug)]
+struct Cli {
+    #[command(flatten)]
+    config: ConfigArgs,
+    #[arg(
+        long,
+        help = "Write digest.html, digest.md and digest.txt in this directory instead of sending them"
+    )]
+    out: Option<PathBuf>,
+    #[arg(
+        long,
+        default_value = "24",
+        help = "How many hours of news to go through"
+    )]
+    hours: i64,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let config = cli.config.loader("digest")?.load();
+    let _logging = logging::init("digest", "digest=info", config.as_ref().ok())?;
+    let config = config.unwrap_or_else(|e| {
+        error!("config: {e:?}");
+        exit(1);
+    });
+    if let Some(Command::Config {
+        command: ConfigCommand::Check,
+    }) = cli.command
+    {
+        print!("{}", config.redacted_toml()?);
+        return Ok(());
+    }
+
+    let store = store::connect(&config).await?;
+    store::ensure_schema(store.as_ref(), &config).await?;
+
+    let until = Local::now();
+    let since = until - Duration::hours(cli.hours);
+    let news = store.rated_since(since.into()).await?;
+    let digest = Digest::select(&news, config.digest_top.unwrap_or(5), since, until);
+    info!(
+        total = digest.total,
+        since = %digest.since,
+        regions = digest.regions.len(),
+        "rated news selected"
+    );
+    let templates = Templates::load(config.digest_templates.as_deref())?;
+    let (html, markdown, text) = templates.render(&digest)?;
+
+    if let Some(dir) = cli.out {
+        fs::create_dir_all(&dir).with_context(|| format!("creating {dir:?}"))?;
+        for (name, content) in [
+            ("digest.html", &html),
+            ("digest.md", &markdown),
+            ("digest.txt", &text),
+        ] {
+            let path = dir.join(name);
+            fs::write(&path, content).with_context(|| format!("writing {path:?}"))?;
+            info!(?path, "wrote the digest");
+        }
+        return Ok(());
+    }
+    if digest.regions.is_empty() {
+        info!("nothing to send");
+        return Ok(());
+    }
+    // not through `Notify`: there is a single message, sent right away
+    Router::from_config(&config)?
+        .dispatch(&Message {
+            severity: Severity::Info,
+            binary: "digest".to_string(),
+            text,
+            html: Some(html),
+        })
+        .await;
+    Ok(())
+}
@@ -0,0 +1,315 @@
+mod writer;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::exit,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use shared::{
+    config::{ConfigArgs, ConfigCommand},
+    logging,
+    store::{self, Cursor, NewsFilter, NewsStore},
+};
+use tracing::{error, info};
+use writer::{Column, Compression, Format, Writer};
+
+#[derive(Parser, Debug)]
+#[command(subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(flatten)]
+    config: ConfigArgs,
+    #[arg(
+        long,
+        short,
+        required = true,
+        help = "File to write, its extension gives the format and compression (news.jsonl, news.csv.gz, news.parquet)"
+    )]
+    out: Option<PathBuf>,
+    #[arg(long, help = "Instead of the extension of --out")]
+    format: Option<Format>,
+    #[arg(long, help = "Instead of the extension of --out")]
+    compression: Option<Compression>,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "Columns to export, in this order, all by default"
+    )]
+    columns: Vec<Column>,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "Columns to leave out, like html_body"
+    )]
+    exclude: Vec<Column>,
+    #[command(flatten)]
+    filter: FilterArgs,
+    #[arg(
+        long,
+        default_value = "1000",
+        help = "News read from the database at once"
+    )]
+    batch: usize,
+    #[arg(
+        long,
+        help = "Continue a stopped export after the cursor saved in <out>.cursor, appending to --out. A parquet file is only readable after a clean stop (ctrl-c), continue it in another file with --after"
+    )]
+    resume: bool,
+    #[arg(
+        long,
+        conflicts_with = "resume",
+        help = "Only export the news after this cursor"
+    )]
+    after: Option<Cursor>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Args, Debug)]
+struct FilterArgs {
+    #[arg(long, help = "Only news published after this date (RFC 3339)")]
+    since: Option<DateTime<Utc>>,
+    #[arg(long, help = "Only news published before this date (RFC 3339)")]
+    until: Option<DateTime<Utc>>,
+    #[arg(long)]
+    provider: Option<String>,
+    #[arg(long, help = "A region tag, like fr")]
+    region: Option<String>,
+    #[arg(long, value_delimiter = ',', help = "News having all of these tags")]
+    tags: Vec<String>,
+    #[arg(long)]
+    min_rating: Option<u8>,
+    #[arg(long)]
+    max_rating: Option<u8>,
+    #[arg(long)]
+    min_rating_travel: Option<u8>,
+    #[arg(long)]
+    max_rating_travel: Option<u8>,
+    #[arg(long)]
+    used: Option<bool>,
+    #[arg(long)]
+    lang: Option<String>,
+    #[arg(long, help = "Case insensitive search in the title, caption and text")]
+    text: Option<String>,
+}
+
+impl From<FilterArgs> for NewsFilter {
+    fn from(args: FilterArgs) -> Self {
+        NewsFilter {
+            since: args.since,
+            until: args.until,
+            provider: args.provider,
+            region: args.region,
+            tags: args.tags,
+            min_rating: args.min_rating,
+            max_rating: args.max_rating,
+            min_rating_travel: args.min_rating_travel,
+            max_rating_travel: args.max_rating_travel,
+            used: args.used,
+            lang: args.lang,
+            text: args.text,
+            ..Default::default()
+        }
+    }
+}
+
+/// where the position of an export is saved after each batch
+fn cursor_path(out: &Path) -> PathBuf {
+    let mut path = out.as_os_str().to_owned();
+    path.push(".cursor");
+    path.into()
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let config = cli.config.loader("export")?.load();
+    let _logging = logging::init("export", "export=info", config.as_ref().ok())?;
+    let config = config.unwrap_or_else(|e| {
+        error!("config: {e:?}");
+        exit(1);
+    });
+    if let Some(Command::Config {
+        command: ConfigCommand::Check,
+    }) = cli.command
+    {
+        print!("{}", config.redacted_toml()?);
+        return Ok(());
+    }
+
+    let out = cli.out.expect("--out is required without a subcommand");
+    let (format, compression) = match (Format::from_path(&out), cli.format) {
+        (Some((format, compression)), None) => (format, cli.compression.unwrap_or(compression)),
+        (guess, Some(format)) => (
+            format,
+            cli.compression
+                .or(guess.map(|(_, c)| c))
+                .unwrap_or(Compression::None),
+        ),
+        (None, None) => bail!("can't tell the format from {out:?}, pass --format"),
+    };
+    let columns: Vec<Column> = match cli.columns.as_slice() {
+        [] => Column::value_variants().to_vec(),
+        columns => columns.to_vec(),
+    };
+    let columns: Vec<Column> = columns
+        .into_iter()
+        .filter(|c| !cli.exclude.contains(c))
+        .collect();
+
+    let mut filter = NewsFilter::from(cli.filter);
+    filter.limit = Some(cli.batch.max(1));
+    let cursor_path = cursor_path(&out);
+    filter.after = if cli.resume {
+        let cursor = fs::read_to_string(&cursor_path)
+            .with_context(|| format!("reading {cursor_path:?}, nothing to resume"))?;
+        Some(cursor.trim().parse()?)
+    } else {
+        cli.after
+    };
+
+    let store = store::connect(&config).await?;
+    store::ensure_schema(store.as_ref(), &config).await?;
+
+    let writer = Writer::create(&out, format, compression, &columns, cli.resume)?;
+    let stop = Arc::new(AtomicBool::new(false));
+    tokio::spawn({
+        let stop = stop.clone();
+        async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                info!("stopping after this batch");
+                stop.store(true, Ordering::Relaxed);
+            }
+        }
+    });
+    let (exported, done) = export(store.as_ref(), filter, writer, &cursor_path, &stop).await?;
+    if done {
+        info!(exported, ?out, "export done");
+    } else {
+        info!(exported, "stopped, continue with --resume");
+    }
+    Ok(())
+}
+
+/// page through the news and write them, saving the cursor of each written batch. returns how
+/// many were written, and false if `stop` interrupted it
+async fn export(
+    store: &dyn NewsStore,
+    mut filter: NewsFilter,
+    mut writer: Writer,
+    cursor_path: &Path,
+    stop: &AtomicBool,
+) -> Result<(usize, bool)> {
+    let mut exported = 0;
+    loop {
+        let news = store.news(&filter).await?;
+        if !news.is_empty() {
+            writer.write(&news)?;
+            exported += news.len();
+            let cursor = news
+                .last()
+                .and_then(Cursor::after)
+                .ok_or_else(|| anyhow!("news without an id"))?;
+            fs::write(cursor_path, cursor.to_string())
+                .with_context(|| format!("writing {cursor_path:?}"))?;
+            filter.after = Some(cursor);
+        }
+        if Some(news.len()) < filter.limit {
+            writer.finish()?;
+            // nothing left to resume
+            let _ = fs::remove_file(cursor_path);
+            return Ok((exported, true));
+        }
+        if stop.load(Ordering::Relaxed) {
+            writer.finish()?;
+            return Ok((exported, false));
+        }
+        info!(exported, "news exported");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+
+    use chrono::Duration;
+    use shared::{store::SqliteStore, DbNews};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn it_resumes_a_stopped_export() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        for i in 0..5 {
+            store
+                .insert(&DbNews {
+                    title: format!("title {i}").into(),
+                    link: format!("https://a.fr/{i}").into(),
+                    provider: "fr::google".into(),
+                    date: (Utc::now() - Duration::hours(i)).into(),
+                    tags: vec!["fr".into()],
+                    rating: Some(50),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        let out = std::env::temp_dir().join(format!("export-{}-resume.jsonl", std::process::id()));
+        let cursor = cursor_path(&out);
+        let filter = NewsFilter {
+            region: Some("fr".into()),
+            limit: Some(2),
+            ..Default::default()
+        };
+        let columns = [Column::Id, Column::Title];
+
+        // stopped after the first batch
+        let writer =
+            Writer::create(&out, Format::Jsonl, Compression::None, &columns, false).unwrap();
+        let stop = AtomicBool::new(true);
+        let (exported, done) = export(&store, filter.clone(), writer, &cursor, &stop)
+            .await
+            .unwrap();
+        assert_eq!((exported, done), (2, false));
+
+        let mut resumed = filter.clone();
+        resumed.after = Some(fs::read_to_string(&cursor).unwrap().parse().unwrap());
+        let writer =
+            Writer::create(&out, Format::Jsonl, Compression::None, &columns, true).unwrap();
+        let stop = AtomicBool::new(false);
+        let (exported, done) = export(&store, resumed, writer, &cursor, &stop)
+            .await
+            .unwrap();
+        assert_eq!((exported, done), (3, true));
+        assert!(!cursor.exists());
+
+        let titles: Vec<String> = BufReader::new(fs::File::open(&out).unwrap())
+            .lines()
+            .map(|l| {
+                let row: serde_json::Value = serde_json::from_str(&l.unwrap()).unwrap();
+                row["title"].as_str().unwrap().to_string()
+            })
+            .collect();
+        fs::remove_file(&out).unwrap();
+        assert_eq!(
+            titles,
+            ["title 0", "title 1", "title 2", "title 3", "title 4"]
+        );
+    }
+}
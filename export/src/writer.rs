@@ -0,0 +1,582 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::Arc,
+};
+
+use anyhow::{bail, Context, Result};
+use arrow_array::{
+    builder::{ListBuilder, StringBuilder},
+    ArrayRef, BooleanArray, Float64Array, RecordBatch, StringArray, TimestampMicrosecondArray,
+    UInt8Array,
+};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use clap::ValueEnum;
+use flate2::write::GzEncoder;
+use parquet::{
+    arrow::ArrowWriter,
+    basic::{Compression as ParquetCompression, GzipLevel, ZstdLevel},
+    file::properties::WriterProperties,
+};
+use serde_json::Value;
+use shared::DbNews;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Jsonl,
+    Csv,
+    Parquet,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Format {
+    /// from the name of the output, like `news.csv.gz`
+    pub fn from_path(path: &Path) -> Option<(Format, Compression)> {
+        let name = path.file_name()?.to_str()?;
+        let (name, compression) = if let Some(name) = name.strip_suffix(".gz") {
+            (name, Compression::Gzip)
+        } else if let Some(name) = name.strip_suffix(".zst") {
+            (name, Compression::Zstd)
+        } else {
+            (name, Compression::None)
+        };
+        let format = match name.rsplit_once('.')?.1 {
+            "jsonl" | "ndjson" => Format::Jsonl,
+            "csv" => Format::Csv,
+            "parquet" => Format::Parquet,
+            _ => return None,
+        };
+        Some((format, compression))
+    }
+}
+
+/// the exported fields of a news, in this order by default
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[value(rename_all = "snake_case")]
+pub enum Column {
+    Id,
+    Date,
+    Title,
+    Caption,
+    Link,
+    Provider,
+    Rating,
+    RatingTravel,
+    Tags,
+    Used,
+    Note,
+    Paywalled,
+    QualityFlags,
+    Lang,
+    LangConfidence,
+    TextBody,
+    HtmlBody,
+    Translations,
+}
+
+impl Column {
+    pub fn name(self) -> &'static str {
+        match self {
+            Column::Id => "id",
+            Column::Date => "date",
+            Column::Title => "title",
+            Column::Caption => "caption",
+            Column::Link => "link",
+            Column::Provider => "provider",
+            Column::Rating => "rating",
+            Column::RatingTravel => "rating_travel",
+            Column::Tags => "tags",
+            Column::Used => "used",
+            Column::Note => "note",
+            Column::Paywalled => "paywalled",
+            Column::QualityFlags => "quality_flags",
+            Column::Lang => "lang",
+            Column::LangConfidence => "lang_confidence",
+            Column::TextBody => "text_body",
+            Column::HtmlBody => "html_body",
+            Column::Translations => "translations",
+        }
+    }
+
+    fn value(self, news: &DbNews) -> Value {
+        match self {
+            Column::Id => id(news).into(),
+            Column::Date => news.date.0.to_rfc3339().into(),
+            Column::Title => news.title.as_ref().into(),
+            Column::Caption => news.caption.as_ref().into(),
+            Column::Link => news.link.as_ref().into(),
+            Column::Provider => news.provider.as_ref().into(),
+            Column::Rating => news.rating.into(),
+            Column::RatingTravel => news.rating_travel.into(),
+            Column::Tags => news.tags.clone().into(),
+            Column::Used => news.used.into(),
+            Column::Note => news.note.as_ref().into(),
+            Column::Paywalled => news.paywalled.into(),
+            Column::QualityFlags => news.quality_flags.clone().into(),
+            Column::Lang => news.lang.clone().into(),
+            Column::LangConfidence => news.lang_confidence.into(),
+            Column::TextBody => news.text_body.as_ref().into(),
+            Column::HtmlBody => news.html_body.as_ref().into(),
+            Column::Translations => serde_json::to_value(&news.translations).unwrap_or_default(),
+        }
+    }
+
+    /// a csv cell: strings as is, lists and maps as json, empty for null
+    fn cell(self, news: &DbNews) -> String {
+        match self.value(news) {
+            Value::Null => String::new(),
+            Value::String(s) => s,
+            value => value.to_string(),
+        }
+    }
+
+    fn field(self) -> Field {
+        let data_type = match self {
+            Column::Date => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+            Column::Rating | Column::RatingTravel => DataType::UInt8,
+            Column::Used | Column::Paywalled => DataType::Boolean,
+            Column::LangConfidence => DataType::Float64,
+            Column::Tags | Column::QualityFlags => {
+                DataType::List(Arc::new(Field::new("item", DataType::Utf8, true)))
+            }
+            _ => DataType::Utf8,
+        };
+        let nullable = matches!(
+            self,
+            Column::Rating | Column::RatingTravel | Column::Lang | Column::LangConfidence
+        );
+        Field::new(self.name(), data_type, nullable)
+    }
+
+    fn array(self, news: &[DbNews]) -> ArrayRef {
+        let strings = |f: fn(&DbNews) -> &str| -> ArrayRef {
+            Arc::new(StringArray::from_iter_values(news.iter().map(f)))
+        };
+        let lists = |f: fn(&DbNews) -> &[String]| -> ArrayRef {
+            let mut lists = ListBuilder::new(StringBuilder::new());
+            for news in news {
+                lists.append_value(f(news).iter().map(Some));
+            }
+            Arc::new(lists.finish())
+        };
+        match self {
+            Column::Id => Arc::new(StringArray::from_iter_values(news.iter().map(id))),
+            Column::Date => Arc::new(
+                TimestampMicrosecondArray::from_iter_values(
+                    news.iter().map(|n| n.date.0.timestamp_micros()),
+                )
+                .with_timezone("UTC"),
+            ),
+            Column::Title => strings(|n| &n.title),
+            Column::Caption => strings(|n| &n.caption),
+            Column::Link => strings(|n| &n.link),
+            Column::Provider => strings(|n| &n.provider),
+            Column::Rating => Arc::new(news.iter().map(|n| n.rating).collect::<UInt8Array>()),
+            Column::RatingTravel => {
+                Arc::new(news.iter().map(|n| n.rating_travel).collect::<UInt8Array>())
+            }
+            Column::Tags => lists(|n| &n.tags),
+            Column::Used => Arc::new(news.iter().map(|n| Some(n.used)).collect::<BooleanArray>()),
+            Column::Note => strings(|n| &n.note),
+            Column::Paywalled => Arc::new(
+                news.iter()
+                    .map(|n| Some(n.paywalled))
+                    .collect::<BooleanArray>(),
+            ),
+            Column::QualityFlags => lists(|n| &n.quality_flags),
+            Column::Lang => Arc::new(
+                news.iter()
+                    .map(|n| n.lang.as_deref())
+                    .collect::<StringArray>(),
+            ),
+            Column::LangConfidence => Arc::new(
+                news.iter()
+                    .map(|n| n.lang_confidence)
+                    .collect::<Float64Array>(),
+            ),
+            Column::TextBody => strings(|n| &n.text_body),
+            Column::HtmlBody => strings(|n| &n.html_body),
+            Column::Translations => {
+                Arc::new(StringArray::from_iter_values(news.iter().map(|n| {
+                    serde_json::to_string(&n.translations).unwrap_or_default()
+                })))
+            }
+        }
+    }
+}
+
+fn id(news: &DbNews) -> String {
+    news.id
+        .as_ref()
+        .map(|id| id.to_string())
+        .unwrap_or_default()
+}
+
+/// the file, through the compression
+enum Sink {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Sink {
+    fn new(file: File, compression: Compression) -> Result<Sink> {
+        let file = BufWriter::new(file);
+        Ok(match compression {
+            Compression::None => Sink::Plain(file),
+            Compression::Gzip => Sink::Gzip(GzEncoder::new(file, flate2::Compression::default())),
+            Compression::Zstd => Sink::Zstd(zstd::Encoder::new(file, 0)?),
+        })
+    }
+
+    /// end the gzip or zstd stream of the batch and sync the file, the next batch starts a new
+    /// stream. an export killed after it leaves whole gzip members or zstd frames behind
+    fn checkpoint(&mut self) -> Result<()> {
+        let (file, compression) = match self {
+            Sink::Plain(file) => (file, Compression::None),
+            Sink::Gzip(gzip) => {
+                gzip.try_finish()?;
+                (gzip.get_mut(), Compression::Gzip)
+            }
+            Sink::Zstd(zstd) => {
+                zstd.do_finish()?;
+                (zstd.get_mut(), Compression::Zstd)
+            }
+        };
+        file.flush()?;
+        file.get_ref().sync_data()?;
+        if compression != Compression::None {
+            let file = file.get_ref().try_clone()?;
+            *self = Sink::new(file, compression)?;
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Sink::Plain(mut file) => file.flush(),
+            Sink::Gzip(gzip) => gzip.finish()?.flush(),
+            Sink::Zstd(zstd) => zstd.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Sink::Plain(w) => w.write(buf),
+            Sink::Gzip(w) => w.write(buf),
+            Sink::Zstd(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Sink::Plain(w) => w.flush(),
+            Sink::Gzip(w) => w.flush(),
+            Sink::Zstd(w) => w.flush(),
+        }
+    }
+}
+
+enum Inner {
+    Jsonl(Sink),
+    Csv(Sink),
+    Parquet(ArrowWriter<File>, Arc<Schema>),
+}
+
+/// writes batches of news, so the whole export never sits in memory
+pub struct Writer {
+    inner: Inner,
+    columns: Vec<Column>,
+}
+
+impl Writer {
+    /// with `append`, continue an existing jsonl or csv file, whose csv header is already written.
+    /// a compressed file gets a new gzip or zstd stream, which their decoders read as one
+    pub fn create(
+        path: &Path,
+        format: Format,
+        compression: Compression,
+        columns: &[Column],
+        append: bool,
+    ) -> Result<Writer> {
+        if columns.is_empty() {
+            bail!("no column to export");
+        }
+        if append && format == Format::Parquet {
+            bail!("a parquet file can't be appended to, export the rest to another file");
+        }
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)
+            .with_context(|| format!("opening {path:?}"))?;
+        let inner = match format {
+            Format::Jsonl => Inner::Jsonl(Sink::new(file, compression)?),
+            Format::Csv => {
+                let mut sink = Sink::new(file, compression)?;
+                if !append {
+                    let mut csv = csv::Writer::from_writer(&mut sink);
+                    csv.write_record(columns.iter().map(|c| c.name()))?;
+                    csv.flush()?;
+                }
+                Inner::Csv(sink)
+            }
+            Format::Parquet => {
+                let schema = Arc::new(Schema::new(
+                    columns.iter().map(|c| c.field()).collect::<Vec<_>>(),
+                ));
+                let compression = match compression {
+                    Compression::None => ParquetCompression::UNCOMPRESSED,
+                    Compression::Gzip => ParquetCompression::GZIP(GzipLevel::default()),
+                    Compression::Zstd => ParquetCompression::ZSTD(ZstdLevel::default()),
+                };
+                let properties = WriterProperties::builder()
+                    .set_compression(compression)
+                    .build();
+                let writer = ArrowWriter::try_new(file, schema.clone(), Some(properties))?;
+                Inner::Parquet(writer, schema)
+            }
+        };
+        Ok(Writer {
+            inner,
+            columns: columns.to_vec(),
+        })
+    }
+
+    /// write a batch and sync it to disk, so what is written stays valid if the export is killed
+    /// after it. a parquet file only gets its footer in [`Writer::finish`], it's unreadable until
+    /// then
+    pub fn write(&mut self, news: &[DbNews]) -> Result<()> {
+        match &mut self.inner {
+            Inner::Jsonl(sink) => {
+                for news in news {
+                    let row: serde_json::Map<_, _> = self
+                        .columns
+                        .iter()
+                        .map(|c| (c.name().to_string(), c.value(news)))
+                        .collect();
+                    serde_json::to_writer(&mut *sink, &row)?;
+                    sink.write_all(b"\n")?;
+                }
+                sink.checkpoint()?;
+            }
+            Inner::Csv(sink) => {
+                let mut csv = csv::Writer::from_writer(&mut *sink);
+                for news in news {
+                    csv.write_record(self.columns.iter().map(|c| c.cell(news)))?;
+                }
+                csv.flush()?;
+                drop(csv);
+                sink.checkpoint()?;
+            }
+            Inner::Parquet(writer, schema) => {
+                let arrays = self.columns.iter().map(|c| c.array(news)).collect();
+                writer.write(&RecordBatch::try_new(schema.clone(), arrays)?)?;
+                // one row group per batch, instead of buffering up to a million rows
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<()> {
+        match self.inner {
+            Inner::Jsonl(sink) | Inner::Csv(sink) => sink.finish()?,
+            Inner::Parquet(writer, _) => {
+                writer.close()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        io::{BufRead, BufReader, Read},
+        path::PathBuf,
+    };
+
+    use arrow_array::{cast::AsArray, types::UInt8Type, Array};
+    use chrono::{TimeZone, Utc};
+    use flate2::read::MultiGzDecoder;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    use super::*;
+
+    fn news(i: u8) -> DbNews {
+        DbNews {
+            title: format!("title, \"{i}\"").into(),
+            link: format!("https://a.fr/{i}").into(),
+            provider: "fr::google".into(),
+            html_body: "<p>body</p>".into(),
+            date: Utc
+                .with_ymd_and_hms(2024, 5, i.into(), 8, 0, 0)
+                .unwrap()
+                .into(),
+            tags: vec!["fr".into(), "cats".into()],
+            rating: i.is_multiple_of(2).then_some(i * 10),
+            paywalled: i == 3,
+            quality_flags: if i == 3 {
+                vec!["short_body".into()]
+            } else {
+                vec![]
+            },
+            ..Default::default()
+        }
+    }
+
+    fn temp(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("export-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn it_reads_the_format_from_the_path() {
+        let format = |p: &str| Format::from_path(Path::new(p));
+        assert_eq!(
+            format("a/news.jsonl"),
+            Some((Format::Jsonl, Compression::None))
+        );
+        assert_eq!(
+            format("news.csv.gz"),
+            Some((Format::Csv, Compression::Gzip))
+        );
+        assert_eq!(
+            format("news.jsonl.zst"),
+            Some((Format::Jsonl, Compression::Zstd))
+        );
+        assert_eq!(
+            format("news.parquet"),
+            Some((Format::Parquet, Compression::None))
+        );
+        assert_eq!(format("news.gz"), None);
+        assert_eq!(format("news"), None);
+    }
+
+    #[test]
+    fn it_appends_gzipped_jsonl() {
+        let path = temp("news.jsonl.gz");
+        let columns = [
+            Column::Title,
+            Column::Rating,
+            Column::Tags,
+            Column::Paywalled,
+            Column::QualityFlags,
+        ];
+        for (append, i) in [(false, 1), (true, 3)] {
+            let mut writer =
+                Writer::create(&path, Format::Jsonl, Compression::Gzip, &columns, append).unwrap();
+            writer.write(&[news(i)]).unwrap();
+            writer.finish().unwrap();
+        }
+        let lines: Vec<serde_json::Value> =
+            BufReader::new(MultiGzDecoder::new(File::open(&path).unwrap()))
+                .lines()
+                .map(|l| serde_json::from_str(&l.unwrap()).unwrap())
+                .collect();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["title"], "title, \"1\"");
+        assert_eq!(lines[0]["rating"], serde_json::Value::Null);
+        assert_eq!(lines[1]["rating"], serde_json::Value::Null);
+        assert_eq!(lines[1]["tags"], serde_json::json!(["fr", "cats"]));
+        assert_eq!(lines[0]["paywalled"], false);
+        assert_eq!(lines[1]["quality_flags"], serde_json::json!(["short_body"]));
+        assert!(lines[1].get("html_body").is_none());
+    }
+
+    #[test]
+    fn it_leaves_whole_streams_when_killed() {
+        for (name, compression) in [("gz", Compression::Gzip), ("zst", Compression::Zstd)] {
+            let path = temp(&format!("killed.csv.{name}"));
+            let mut writer =
+                Writer::create(&path, Format::Csv, compression, &[Column::Link], false).unwrap();
+            writer.write(&[news(1)]).unwrap();
+            writer.write(&[news(2)]).unwrap();
+            // killed before `finish`
+            std::mem::forget(writer);
+            let file = File::open(&path).unwrap();
+            let mut csv = String::new();
+            match compression {
+                Compression::Gzip => MultiGzDecoder::new(file).read_to_string(&mut csv),
+                _ => zstd::Decoder::new(file).unwrap().read_to_string(&mut csv),
+            }
+            .unwrap();
+            fs::remove_file(&path).unwrap();
+            assert_eq!(csv, "link\nhttps://a.fr/1\nhttps://a.fr/2\n", "{name}");
+        }
+    }
+
+    #[test]
+    fn it_writes_the_csv_header_once() {
+        let path = temp("news.csv.zst");
+        let columns = [Column::Title, Column::Tags, Column::HtmlBody];
+        for (append, i) in [(false, 1), (true, 2)] {
+            let mut writer =
+                Writer::create(&path, Format::Csv, Compression::Zstd, &columns, append).unwrap();
+            writer.write(&[news(i)]).unwrap();
+            writer.finish().unwrap();
+        }
+        let mut csv = String::new();
+        zstd::Decoder::new(File::open(&path).unwrap())
+            .unwrap()
+            .read_to_string(&mut csv)
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            csv,
+            "title,tags,html_body\n\
+            \"title, \"\"1\"\"\",\"[\"\"fr\"\",\"\"cats\"\"]\",<p>body</p>\n\
+            \"title, \"\"2\"\"\",\"[\"\"fr\"\",\"\"cats\"\"]\",<p>body</p>\n"
+        );
+    }
+
+    #[test]
+    fn it_writes_typed_parquet() {
+        let path = temp("news.parquet");
+        let columns = Column::value_variants();
+        let mut writer =
+            Writer::create(&path, Format::Parquet, Compression::Zstd, columns, false).unwrap();
+        writer.write(&[news(1), news(2)]).unwrap();
+        writer.write(&[news(3)]).unwrap();
+        writer.finish().unwrap();
+        assert!(Writer::create(&path, Format::Parquet, Compression::None, columns, true).is_err());
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<_> = reader.map(|b| b.unwrap()).collect();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 3);
+        let batch = &batches[0];
+        assert_eq!(batch.schema().field(0).name(), "id");
+        let ratings = batch
+            .column_by_name("rating")
+            .unwrap()
+            .as_primitive::<UInt8Type>();
+        assert!(ratings.is_null(0));
+        assert_eq!(ratings.value(1), 20);
+        let tags = batch.column_by_name("tags").unwrap().as_list::<i32>();
+        assert_eq!(tags.value(0).as_string::<i32>().value(1), "cats");
+        let paywalled = batch.column_by_name("paywalled").unwrap().as_boolean();
+        assert!(!paywalled.value(0) && paywalled.value(2));
+        let flags = batch
+            .column_by_name("quality_flags")
+            .unwrap()
+            .as_list::<i32>();
+        assert_eq!(flags.value(2).as_string::<i32>().value(0), "short_body");
+    }
+}
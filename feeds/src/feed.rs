@@ -0,0 +1,264 @@
+use std::fmt::Write;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use shared::DbNews;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Rss,
+    Atom,
+    Json,
+}
+
+impl Format {
+    /// from the extension of the requested file
+    pub fn from_extension(extension: &str) -> Result<Format> {
+        Ok(match extension {
+            "rss" | "xml" => Format::Rss,
+            "atom" => Format::Atom,
+            "json" => Format::Json,
+            other => bail!("unknown feed format `{other}`, expected rss, atom or json"),
+        })
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Rss => "application/rss+xml; charset=utf-8",
+            Format::Atom => "application/atom+xml; charset=utf-8",
+            Format::Json => "application/feed+json; charset=utf-8",
+        }
+    }
+}
+
+/// what describes the feed itself
+pub struct Meta {
+    pub title: String,
+    /// the url of this feed, also used as its id
+    pub url: String,
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // not allowed in xml 1.0
+            c if c.is_control() && !matches!(c, '\n' | '\r' | '\t') => (),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// the caption, followed by the ratings since feed readers have nowhere else to show them
+fn summary(news: &DbNews) -> String {
+    let mut summary = news.caption.to_string();
+    if let Some(rating) = news.rating {
+        let _ = write!(summary, "\n\nrating {rating}");
+        if let Some(travel) = news.rating_travel {
+            let _ = write!(summary, ", travel {travel}");
+        }
+    }
+    summary.trim_start().to_string()
+}
+
+/// the newest news, or now if there is none
+fn updated(news: &[DbNews]) -> DateTime<Utc> {
+    news.iter().map(|n| n.date.0).max().unwrap_or_else(Utc::now)
+}
+
+pub fn render(format: Format, meta: &Meta, news: &[DbNews]) -> Result<String> {
+    Ok(match format {
+        Format::Rss => rss(meta, news),
+        Format::Atom => atom(meta, news),
+        Format::Json => json(meta, news)?,
+    })
+}
+
+fn rss(meta: &Meta, news: &[DbNews]) -> String {
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+    xml.push('\n');
+    let _ = write!(
+        xml,
+        r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom"><channel><title>{title}</title><link>{url}</link><description>{title}</description><atom:link href="{url}" rel="self" type="application/rss+xml"/><lastBuildDate>{updated}</lastBuildDate>"#,
+        title = escape(&meta.title),
+        url = escape(&meta.url),
+        updated = updated(news).to_rfc2822(),
+    );
+    for news in news {
+        let _ = write!(
+            xml,
+            r#"<item><title>{}</title><link>{link}</link><guid isPermaLink="true">{link}</guid><description>{}</description><pubDate>{}</pubDate>"#,
+            escape(&news.title),
+            escape(&summary(news)),
+            news.date.0.to_rfc2822(),
+            link = escape(&news.link),
+        );
+        for tag in &news.tags {
+            let _ = write!(xml, "<category>{}</category>", escape(tag));
+        }
+        xml.push_str("</item>");
+    }
+    xml.push_str("</channel></rss>\n");
+    xml
+}
+
+fn atom(meta: &Meta, news: &[DbNews]) -> String {
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+    xml.push('\n');
+    let _ = write!(
+        xml,
+        r#"<feed xmlns="http://www.w3.org/2005/Atom"><title>{}</title><id>{url}</id><link href="{url}" rel="self"/><updated>{}</updated><author><name>news-scraper</name></author>"#,
+        escape(&meta.title),
+        updated(news).to_rfc3339_opts(SecondsFormat::Secs, true),
+        url = escape(&meta.url),
+    );
+    for news in news {
+        let _ = write!(
+            xml,
+            r#"<entry><title>{}</title><id>{link}</id><link href="{link}"/><updated>{date}</updated><published>{date}</published><summary>{}</summary>"#,
+            escape(&news.title),
+            escape(&summary(news)),
+            link = escape(&news.link),
+            date = news.date.0.to_rfc3339_opts(SecondsFormat::Secs, true),
+        );
+        for tag in &news.tags {
+            let _ = write!(xml, r#"<category term="{}"/>"#, escape(tag));
+        }
+        xml.push_str("</entry>");
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+/// https://www.jsonfeed.org/version/1.1/
+#[derive(Serialize)]
+struct JsonFeed<'a> {
+    version: &'static str,
+    title: &'a str,
+    feed_url: &'a str,
+    items: Vec<JsonItem<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonItem<'a> {
+    id: &'a str,
+    url: &'a str,
+    title: &'a str,
+    summary: &'a str,
+    content_text: String,
+    date_published: String,
+    tags: &'a [String],
+    /// extensions start with an underscore
+    _ratings: Ratings,
+}
+
+#[derive(Serialize)]
+struct Ratings {
+    rating: Option<u8>,
+    rating_travel: Option<u8>,
+}
+
+fn json(meta: &Meta, news: &[DbNews]) -> Result<String> {
+    let feed = JsonFeed {
+        version: "https://jsonfeed.org/version/1.1",
+        title: &meta.title,
+        feed_url: &meta.url,
+        items: news
+            .iter()
+            .map(|news| JsonItem {
+                id: &news.link,
+                url: &news.link,
+                title: &news.title,
+                summary: &news.caption,
+                content_text: summary(news),
+                date_published: news.date.0.to_rfc3339_opts(SecondsFormat::Secs, true),
+                tags: &news.tags,
+                _ratings: Ratings {
+                    rating: news.rating,
+                    rating_travel: news.rating_travel,
+                },
+            })
+            .collect(),
+    };
+    Ok(serde_json::to_string_pretty(&feed)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn meta() -> Meta {
+        Meta {
+            title: "news: fr".into(),
+            url: "http://localhost/fr.rss?min_rating=50&limit=2".into(),
+        }
+    }
+
+    fn news() -> Vec<DbNews> {
+        vec![DbNews {
+            title: "Cats & <dogs>".into(),
+            caption: "they get along".into(),
+            link: "https://a.fr/1?a=1&b=2".into(),
+            date: Utc.with_ymd_and_hms(2024, 5, 1, 8, 30, 0).unwrap().into(),
+            tags: vec!["fr".into(), "animals".into()],
+            rating: Some(82),
+            rating_travel: Some(15),
+            ..Default::default()
+        }]
+    }
+
+    #[test]
+    fn it_renders_rss() {
+        let xml = render(Format::Rss, &meta(), &news()).unwrap();
+        assert!(
+            xml.contains("<title>Cats &amp; &lt;dogs&gt;</title>"),
+            "{xml}"
+        );
+        assert!(xml.contains("<link>https://a.fr/1?a=1&amp;b=2</link>"));
+        assert!(xml.contains("<pubDate>Wed, 1 May 2024 08:30:00 +0000</pubDate>"));
+        assert!(xml.contains("<category>animals</category>"));
+        assert!(xml.contains("<description>they get along\n\nrating 82, travel 15</description>"));
+        assert!(xml.contains(r#"href="http://localhost/fr.rss?min_rating=50&amp;limit=2""#));
+    }
+
+    #[test]
+    fn it_renders_atom() {
+        let xml = render(Format::Atom, &meta(), &news()).unwrap();
+        assert!(
+            xml.contains("<updated>2024-05-01T08:30:00Z</updated>"),
+            "{xml}"
+        );
+        assert!(xml.contains(r#"<link href="https://a.fr/1?a=1&amp;b=2"/>"#));
+        assert!(xml.contains(r#"<category term="fr"/>"#));
+        assert!(xml.ends_with("</entry></feed>\n"));
+    }
+
+    #[test]
+    fn it_renders_json_feed() {
+        let json = render(Format::Json, &meta(), &news()).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json["version"], "https://jsonfeed.org/version/1.1");
+        let item = &json["items"][0];
+        assert_eq!(item["title"], "Cats & <dogs>");
+        assert_eq!(item["date_published"], "2024-05-01T08:30:00Z");
+        assert_eq!(item["tags"], serde_json::json!(["fr", "animals"]));
+        assert_eq!(item["_ratings"]["rating"], 82);
+    }
+
+    #[test]
+    fn it_renders_an_empty_feed() {
+        for format in [Format::Rss, Format::Atom, Format::Json] {
+            assert!(render(format, &meta(), &[]).is_ok());
+        }
+        assert!(Format::from_extension("html").is_err());
+    }
+}
@@ -0,0 +1,160 @@
+mod feed;
+
+use std::{process::exit, sync::Arc};
+
+use anyhow::Result;
+use axum::{
+    extract::{Path, Query, RawQuery, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, Duration, Utc};
+use clap::{Parser, Subcommand};
+use feed::{Format, Meta};
+use serde::Deserialize;
+use shared::{
+    config::{ConfigArgs, ConfigCommand},
+    logging,
+    store::{self, NewsFilter, NewsStore},
+};
+use tracing::{error, info};
+
+/// most news in a feed, whatever `limit` asks
+const MAX_LIMIT: usize = 500;
+/// how far back a feed goes at most, whatever `days` asks
+const MAX_DAYS: i64 = 365;
+
+#[derive(Parser, Debug)]
+struct Cli {
+    #[command(flatten)]
+    config: ConfigArgs,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+struct AppState {
+    store: Arc<dyn NewsStore>,
+    /// prefix of the feed urls written in the feeds
+    base_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct FeedQuery {
+    min_rating: Option<u8>,
+    min_rating_travel: Option<u8>,
+    /// how far back the feed goes, 7 days by default
+    days: Option<i64>,
+    limit: Option<usize>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let config = cli.config.loader("feeds")?.load();
+    let _logging = logging::init("feeds", "feeds=info", config.as_ref().ok())?;
+    let config = config.unwrap_or_else(|e| {
+        error!("config: {e:?}");
+        exit(1);
+    });
+    if let Some(Command::Config {
+        command: ConfigCommand::Check,
+    }) = cli.command
+    {
+        print!("{}", config.redacted_toml()?);
+        return Ok(());
+    }
+
+    let store = store::connect(&config).await?;
+    store::ensure_schema(store.as_ref(), &config).await?;
+
+    let listen = config.feeds_listen.as_deref().unwrap_or("127.0.0.1:8090");
+    let base_url = match &config.feeds_base_url {
+        Some(url) => url.trim_end_matches('/').to_string(),
+        None => format!("http://{listen}"),
+    };
+    let state = Arc::new(AppState { store, base_url });
+    let app = Router::new().route("/{file}", get(feed)).with_state(state);
+    let listener = tokio::net::TcpListener::bind(listen).await?;
+    info!(listen, "serving feeds, like /fr.rss?min_rating=70");
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+/// `days` ago, clamped: a huge one would overflow the date
+fn since(days: Option<i64>) -> DateTime<Utc> {
+    Utc::now() - Duration::days(days.unwrap_or(7).clamp(0, MAX_DAYS))
+}
+
+/// `/<region>.<rss|atom|json>`, or `all` instead of a region
+async fn feed(
+    State(state): State<Arc<AppState>>,
+    Path(file): Path<String>,
+    Query(query): Query<FeedQuery>,
+    RawQuery(raw_query): RawQuery,
+) -> Response {
+    let Some((region, extension)) = file.rsplit_once('.') else {
+        return (StatusCode::NOT_FOUND, "expected /<region>.<rss|atom|json>").into_response();
+    };
+    let format = match Format::from_extension(extension) {
+        Ok(format) => format,
+        Err(e) => return (StatusCode::NOT_FOUND, e.to_string()).into_response(),
+    };
+    let filter = NewsFilter {
+        since: Some(since(query.days)),
+        region: (region != "all").then(|| region.to_string()),
+        min_rating: query.min_rating,
+        min_rating_travel: query.min_rating_travel,
+        limit: Some(query.limit.unwrap_or(100).clamp(1, MAX_LIMIT)),
+        ..Default::default()
+    };
+    let news = match state.store.news(&filter).await {
+        Ok(news) => news,
+        Err(e) => {
+            error!(file, error = %format!("{e:#}"), "rendering the feed failed");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let mut title = format!("news-scraper: {region}");
+    if let Some(rating) = query.min_rating {
+        title += &format!(", rating ≥ {rating}");
+    }
+    if let Some(rating) = query.min_rating_travel {
+        title += &format!(", travel ≥ {rating}");
+    }
+    let mut url = format!("{}/{file}", state.base_url);
+    if let Some(raw_query) = raw_query {
+        url = format!("{url}?{raw_query}");
+    }
+    match feed::render(format, &Meta { title, url }, &news) {
+        Ok(body) => ([(header::CONTENT_TYPE, format.content_type())], body).into_response(),
+        Err(e) => {
+            error!(file, error = %format!("{e:#}"), "rendering the feed failed");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_clamps_the_days() {
+        let days = |since: DateTime<Utc>| (Utc::now() - since).num_days();
+        assert_eq!(days(since(None)), 7);
+        assert_eq!(days(since(Some(i64::MAX))), MAX_DAYS);
+        assert_eq!(days(since(Some(-3))), 0);
+    }
+}
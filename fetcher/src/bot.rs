@@ -0,0 +1,362 @@
+use std::{
+    fmt::Write,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{Local, Utc};
+use shared::{store::NewsStore, Telegram};
+use tokio::task::spawn_blocking;
+use tracing::{debug, error, info, warn};
+
+use crate::sources::SOURCES;
+
+/// how long a `getUpdates` call waits for a message
+const POLL_TIMEOUT: Duration = Duration::from_secs(30);
+
+const HELP: &str = "/status - last run of each source and rater backlog
+/top [n] - today's best rated news
+/rerate <id> - rate a news again
+/used <id> - mark a news as used
+/disable <source> - stop fetching a source
+/enable <source> - fetch a source again";
+
+/// answers the commands sent by `TELEGRAM_ID`, everyone else is ignored
+pub struct Bot {
+    telegram: Telegram,
+    store: Arc<dyn NewsStore>,
+    /// `RATER_LANGUAGES`, the backlog is what the rater will rate
+    rater_languages: Option<Vec<String>>,
+}
+
+impl Bot {
+    pub fn new(telegram: Telegram, store: Arc<dyn NewsStore>) -> Result<Bot> {
+        if telegram.chat_id().is_none() {
+            return Err(anyhow!("the bot needs TELEGRAM_TOKEN and TELEGRAM_ID"));
+        }
+        Ok(Bot {
+            telegram,
+            store,
+            rater_languages: None,
+        })
+    }
+
+    pub fn rater_languages(mut self, languages: Option<Vec<String>>) -> Bot {
+        self.rater_languages = languages;
+        self
+    }
+
+    /// long poll for commands until `cancel` is set, which is checked between two polls
+    pub async fn run(&self, cancel: Arc<AtomicBool>) {
+        info!("telegram bot started");
+        let mut offset = 0;
+        while !cancel.load(Ordering::Relaxed) {
+            let telegram = self.telegram.clone();
+            let updates =
+                match spawn_blocking(move || telegram.get_updates(offset, POLL_TIMEOUT)).await {
+                    Ok(Ok(updates)) => updates,
+                    Ok(Err(e)) => {
+                        error!(error = %format!("{e:#}"), "telegram getUpdates failed");
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                        continue;
+                    }
+                    Err(e) => {
+                        error!(error = %e, "telegram getUpdates panicked");
+                        continue;
+                    }
+                };
+            for update in updates {
+                offset = offset.max(update.update_id + 1);
+                let Some(message) = update.message else {
+                    continue;
+                };
+                if Some(message.chat.id) != self.telegram.chat_id() {
+                    warn!(
+                        chat = message.chat.id,
+                        "ignoring a message from another chat"
+                    );
+                    continue;
+                }
+                let Some(text) = message.text else {
+                    continue;
+                };
+                debug!(command = %text, "bot command");
+                let reply = self
+                    .handle(&text)
+                    .await
+                    .unwrap_or_else(|e| format!("error: {e:#}"));
+                let telegram = self.telegram.clone();
+                match spawn_blocking(move || telegram.send(reply)).await {
+                    Ok(Ok(())) => (),
+                    Ok(Err(e)) => error!(error = %format!("{e:#}"), "telegram reply failed"),
+                    Err(e) => error!(error = %e, "telegram reply panicked"),
+                }
+            }
+        }
+        info!("telegram bot stopped");
+    }
+
+    /// the reply to a message
+    pub async fn handle(&self, text: &str) -> Result<String> {
+        let mut words = text.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        // `/status@news_bot` in groups
+        let command = command.split_once('@').map_or(command, |(c, _)| c);
+        let arg = words.next();
+        match (command, arg) {
+            ("/status", _) => self.status().await,
+            ("/top", n) => {
+                let n = match n {
+                    Some(n) => n.parse().map_err(|_| anyhow!("`{n}` is not a number"))?,
+                    None => 10,
+                };
+                self.top(n).await
+            }
+            ("/rerate", Some(id)) => Ok(match self.store.reset_rating(id).await? {
+                true => format!("{id} will be rated again"),
+                false => format!("no news {id}"),
+            }),
+            ("/used", Some(id)) => Ok(match self.store.set_used(id, true).await? {
+                true => format!("{id} marked as used"),
+                false => format!("no news {id}"),
+            }),
+            ("/disable" | "/enable", Some(source)) => {
+                if !SOURCES.iter().any(|(name, _)| *name == source) {
+                    return Ok(format!("unknown source {source}"));
+                }
+                let enabled = command == "/enable";
+                self.store.set_source_enabled(source, enabled).await?;
+                Ok(format!(
+                    "{source} {}",
+                    if enabled { "enabled" } else { "disabled" }
+                ))
+            }
+            _ => Ok(HELP.to_string()),
+        }
+    }
+
+    async fn status(&self) -> Result<String> {
+        let backlog = self.store.backlog(self.rater_languages.as_deref()).await?;
+        let mut reply = format!("rater backlog: {backlog} news\n");
+        let runs = self.store.last_runs().await?;
+        if runs.is_empty() {
+            reply.push_str("no fetch run yet\n");
+        }
+        for run in runs {
+            let finished = run.finished.0.with_timezone(&Local);
+            let _ = write!(
+                reply,
+                "{}: {} ({}s), {} inserted, {} merged, {} failed",
+                run.source,
+                finished.format("%d/%m %H:%M"),
+                (run.finished.0 - run.started.0).num_seconds(),
+                run.inserted,
+                run.merged,
+                run.failed
+            );
+            if let Some(error) = run.error {
+                let _ = write!(reply, ", error: {error}");
+            }
+            reply.push('\n');
+        }
+        let disabled = self.store.disabled_sources().await?;
+        if !disabled.is_empty() {
+            let _ = writeln!(reply, "disabled: {}", disabled.join(", "));
+        }
+        Ok(reply)
+    }
+
+    async fn top(&self, n: usize) -> Result<String> {
+        let midnight = Local::now()
+            .date_naive()
+            .and_hms_opt(0, 0, 0)
+            .and_then(|d| d.and_local_timezone(Local).earliest())
+            .map(|d| d.with_timezone(&Utc))
+            .unwrap_or_else(|| Utc::now() - chrono::Duration::days(1));
+        let news = self.store.top(midnight, n).await?;
+        if news.is_empty() {
+            return Ok("no rated news today".to_string());
+        }
+        let mut reply = String::new();
+        for news in news {
+            let id = news.id.map(|id| id.to_string()).unwrap_or_default();
+            let _ = writeln!(
+                reply,
+                "{} {} {}\n{id}",
+                news.rating.unwrap_or_default(),
+                news.title,
+                news.link
+            );
+        }
+        Ok(reply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::Mutex,
+        thread,
+    };
+
+    use super::*;
+    use shared::{
+        store::{SourceRun, SqliteStore},
+        DbNews,
+    };
+
+    fn bot(api: &str) -> (Bot, Arc<SqliteStore>) {
+        let store = Arc::new(SqliteStore::open_in_memory().unwrap());
+        let telegram = Telegram::new("token", 42).api(api);
+        (Bot::new(telegram, store.clone()).unwrap(), store)
+    }
+
+    #[tokio::test]
+    async fn it_answers_commands() {
+        let (bot, store) = bot("http://127.0.0.1:1");
+        store
+            .insert(&DbNews {
+                title: "good news".into(),
+                link: "https://a.fr/1".into(),
+                provider: "fr::google".into(),
+                date: Utc::now().into(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let mut news = store.unrated(None).await.unwrap().remove(0);
+        news.rating = Some(90);
+        store.save_rating(&news).await.unwrap();
+        store
+            .record_run(&SourceRun {
+                source: "fr::google".into(),
+                started: Utc::now().into(),
+                finished: Utc::now().into(),
+                inserted: 1,
+                merged: 0,
+                failed: 0,
+                error: None,
+            })
+            .await
+            .unwrap();
+
+        let top = bot.handle("/top").await.unwrap();
+        assert!(
+            top.starts_with("90 good news https://a.fr/1\nnews:1"),
+            "{top}"
+        );
+        assert!(bot
+            .handle("/status")
+            .await
+            .unwrap()
+            .contains("rater backlog: 0 news"));
+
+        assert_eq!(
+            bot.handle("/rerate news:1").await.unwrap(),
+            "news:1 will be rated again"
+        );
+        assert_eq!(
+            bot.handle("/rerate news:2").await.unwrap(),
+            "no news news:2"
+        );
+        assert_eq!(store.backlog(None).await.unwrap(), 1);
+        assert_eq!(bot.handle("/used 1").await.unwrap(), "1 marked as used");
+
+        assert_eq!(
+            bot.handle("/disable fr::nope").await.unwrap(),
+            "unknown source fr::nope"
+        );
+        bot.handle("/disable@news_bot fr::google").await.unwrap();
+        assert_eq!(store.disabled_sources().await.unwrap(), vec!["fr::google"]);
+        let status = bot.handle("/status").await.unwrap();
+        assert!(status.contains("fr::google: "), "{status}");
+        assert!(status.contains("disabled: fr::google"), "{status}");
+        bot.handle("/enable fr::google").await.unwrap();
+        assert!(store.disabled_sources().await.unwrap().is_empty());
+
+        assert_eq!(bot.handle("hello").await.unwrap(), HELP);
+    }
+
+    /// a Bot API that hands out `updates` once, and records the text of every sendMessage
+    fn mock_bot_api(updates: serde_json::Value, sent: Arc<Mutex<Vec<String>>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut updates = Some(updates);
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some(v) = line.to_lowercase().strip_prefix("content-length:") {
+                        length = v.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let result = if request_line.contains("/bottoken/getUpdates") {
+                    updates.take().unwrap_or_default()
+                } else if request_line.contains("/bottoken/sendMessage") {
+                    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                    assert_eq!(body["chat_id"], 42);
+                    sent.lock()
+                        .unwrap()
+                        .push(body["text"].as_str().unwrap().to_string());
+                    serde_json::json!({})
+                } else {
+                    panic!("unexpected request {request_line}");
+                };
+                let response = serde_json::json!({ "ok": true, "result": result }).to_string();
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{response}",
+                    response.len()
+                )
+                .unwrap();
+            }
+        });
+        format!("http://{addr}")
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn it_only_answers_the_configured_chat() {
+        let updates = serde_json::json!([
+            { "update_id": 1, "message": { "chat": { "id": 7 }, "text": "/disable fr::google" } },
+            { "update_id": 2, "message": { "chat": { "id": 42 }, "text": "/status" } },
+        ]);
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let (bot, store) = bot(&mock_bot_api(updates, sent.clone()));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let run = {
+            let cancel = cancel.clone();
+            async move { bot.run(cancel).await }
+        };
+        let handle = tokio::spawn(run);
+        for _ in 0..100 {
+            if !sent.lock().unwrap().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        cancel.store(true, Ordering::Relaxed);
+        handle.await.unwrap();
+
+        assert!(store.disabled_sources().await.unwrap().is_empty());
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0].starts_with("rater backlog: 0 news"), "{}", sent[0]);
+    }
+}
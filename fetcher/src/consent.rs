@@ -0,0 +1,427 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use headless_chrome::{protocol::cdp::Network::CookieParam, Element, Tab};
+use serde::Deserialize;
+use shared::config::load_json;
+use tracing::{debug, trace};
+use url::Url;
+
+use crate::network::most_specific_domain;
+
+/// how long a page gets to show its banner, they're often injected after the load
+const BANNER_WAIT: Duration = Duration::from_secs(3);
+/// how long a host checked in a browser context is remembered, longer than a source runs
+const CHECKED_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// a generic consent button must be in an element whose id or class has one of theses, or in a
+/// dialog, so that an `Accepter` elsewhere in the page, like a form of the article, isn't clicked
+const CONTAINER_WORDS: &[&str] = &["consent", "cookie", "gdpr", "rgpd"];
+
+/// the answer given to the consent banners
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Choice {
+    Accept,
+    /// falls back to accepting when the banner has no way to refuse, like a cookie wall
+    Reject,
+}
+
+/// consent file, how the cookie banners are answered:
+/// ```json
+/// { "choice": "accept", "domains": { "rtbf.be": { "choice": "reject" },
+///   "nationalgeographic.com": { "cookies": { "OptanonAlertBoxClosed": "2024-01-01T00:00:00.000Z" } } } }
+/// ```
+/// the `cookies` of `rtbf.be` are set before opening a page of `www.rtbf.be` or `auvio.rtbf.be`
+/// too: a consent already given (`euconsent-v2` for the TCF banners) means no banner at all.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConsentFile {
+    #[serde(default = "default_choice")]
+    pub choice: Choice,
+    #[serde(default)]
+    pub domains: BTreeMap<String, DomainConsent>,
+}
+
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DomainConsent {
+    pub choice: Option<Choice>,
+    #[serde(default)]
+    pub cookies: BTreeMap<String, String>,
+}
+
+fn default_choice() -> Choice {
+    Choice::Accept
+}
+
+impl Default for ConsentFile {
+    fn default() -> ConsentFile {
+        let reject = DomainConsent {
+            choice: Some(Choice::Reject),
+            ..Default::default()
+        };
+        ConsentFile {
+            choice: default_choice(),
+            domains: ["google.com", "rtbf.be", "tv5monde.com"]
+                .into_iter()
+                .map(|d| (d.to_string(), reject.clone()))
+                .collect(),
+        }
+    }
+}
+
+impl ConsentFile {
+    pub fn load(path: impl AsRef<Path>) -> Result<ConsentFile> {
+        let path = path.as_ref();
+        let file: ConsentFile = load_json(path)?;
+        file.validate().with_context(|| format!("in {path:?}"))?;
+        Ok(file)
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (domain, consent) in &self.domains {
+            if consent.cookies.keys().any(|name| name.trim().is_empty()) {
+                bail!("{domain}: empty cookie name");
+            }
+        }
+        Ok(())
+    }
+
+    fn domain(&self, host: &str) -> Option<(&str, &DomainConsent)> {
+        most_specific_domain(&self.domains, host)
+    }
+
+    fn choice_for(&self, host: &str) -> Choice {
+        self.domain(host)
+            .and_then(|(_, consent)| consent.choice)
+            .unwrap_or(self.choice)
+    }
+}
+
+/// a button of a banner
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Button {
+    Css(&'static str),
+    /// a button of a consent container whose text contains this one, see [`CONTAINER_WORDS`]
+    Text(&'static str),
+}
+
+/// a consent management platform, recognized by its buttons
+struct Cmp {
+    name: &'static str,
+    accept: &'static [Button],
+    reject: &'static [Button],
+}
+
+/// the first ones are recognized by their markup, the last one by the usual wording of the
+/// buttons, for the TCF banners made in-house
+const CMPS: &[Cmp] = &[
+    Cmp {
+        name: "didomi",
+        accept: &[Button::Css("#didomi-notice-agree-button")],
+        reject: &[
+            Button::Css("#didomi-notice-disagree-button"),
+            Button::Css(".didomi-continue-without-agreeing"),
+        ],
+    },
+    // google's funding choices, the quebec sources
+    Cmp {
+        name: "funding-choices",
+        accept: &[Button::Css(".fc-button.fc-cta-consent")],
+        reject: &[Button::Css(".fc-button.fc-cta-do-not-consent")],
+    },
+    Cmp {
+        name: "quantcast",
+        accept: &[Button::Css(
+            ".qc-cmp2-summary-buttons button[mode='primary']",
+        )],
+        reject: &[Button::Css(
+            ".qc-cmp2-summary-buttons button[mode='secondary']",
+        )],
+    },
+    Cmp {
+        name: "onetrust",
+        accept: &[Button::Css("#onetrust-accept-btn-handler")],
+        reject: &[Button::Css("#onetrust-reject-all-handler")],
+    },
+    Cmp {
+        name: "lemonde",
+        accept: &[Button::Css("button.gdpr-lmd-button")],
+        reject: &[],
+    },
+    Cmp {
+        name: "generic",
+        accept: &[
+            Button::Text("Tout accepter"),
+            Button::Text("Accepter"),
+            Button::Text("J'accepte"),
+            Button::Text("Accept all"),
+            Button::Text("I agree"),
+        ],
+        reject: &[
+            Button::Text("Tout refuser"),
+            Button::Text("Continuer sans accepter"),
+            Button::Text("Refuser"),
+            Button::Text("Reject all"),
+        ],
+    },
+];
+
+/// the buttons to try in order for `choice`: the ones of the choice first, of every CMP, then
+/// the others
+fn buttons(choice: Choice) -> Vec<(&'static str, Button)> {
+    let ordered = |cmp: &Cmp| match choice {
+        Choice::Accept => [cmp.accept, cmp.reject],
+        Choice::Reject => [cmp.reject, cmp.accept],
+    };
+    let first = CMPS.iter().flat_map(|cmp| {
+        ordered(cmp)[0]
+            .iter()
+            .map(move |button| (cmp.name, *button))
+    });
+    let then = CMPS.iter().flat_map(|cmp| {
+        ordered(cmp)[1]
+            .iter()
+            .map(move |button| (cmp.name, *button))
+    });
+    first.chain(then).collect()
+}
+
+/// removes what covers the page without a button to click: sourcepoint's banner lives in an
+/// iframe, and piano's paywall modal or the adblock wall of funding choices block the scroll
+const REMOVE_OVERLAYS: &str = r#"(() => {
+    const overlays = document.querySelectorAll(
+        "[id^='sp_message_container'], .tp-modal, .tp-backdrop, .fc-ab-root"
+    );
+    overlays.forEach((e) => e.remove());
+    for (const e of [document.documentElement, document.body]) {
+        e.classList.remove("sp-message-open", "tp-modal-open");
+        e.style.removeProperty("overflow");
+    }
+    return overlays.length;
+})()"#;
+
+/// answers the consent banners of the sources, shared by their threads
+pub struct Consent {
+    file: ConsentFile,
+    /// when a host was first checked for a banner in a browser context, `(context, host)`. the
+    /// answer is in the cookies of the context from then on, and a host without a banner
+    /// won't show one on its next page
+    checked: Mutex<HashMap<(String, String), Instant>>,
+}
+
+impl Consent {
+    pub fn new(file: ConsentFile) -> Consent {
+        Consent {
+            file,
+            checked: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// whether it's the first page of `host` checked in `context`
+    fn first_check(&self, context: &str, host: &str) -> bool {
+        let mut checked = self.checked.lock().unwrap();
+        // the contexts are disposed with their lease
+        checked.retain(|_, at| at.elapsed() < CHECKED_TTL);
+        checked
+            .insert((context.to_string(), host.to_string()), Instant::now())
+            .is_none()
+    }
+
+    /// the cookies of the domain of `url` in the file, to set before opening it
+    pub fn cookies_for(&self, url: &str) -> Result<Vec<CookieParam>> {
+        let url = Url::parse(url).with_context(|| format!("invalid url {url}"))?;
+        let Some((domain, consent)) = url.host_str().and_then(|h| self.file.domain(h)) else {
+            return Ok(Vec::new());
+        };
+        consent
+            .cookies
+            .iter()
+            .map(|(name, value)| {
+                // through serde, the fields of the protocol change with chrome's versions
+                serde_json::from_value(serde_json::json!({
+                    "name": name,
+                    "value": value,
+                    "domain": format!(".{domain}"),
+                    "path": "/",
+                }))
+                .context("consent cookie")
+            })
+            .collect()
+    }
+
+    /// sets the cookies of the domain of `url` in the browser context of `tab`
+    pub fn seed(&self, tab: &Tab, url: &str) -> Result<()> {
+        let cookies = self.cookies_for(url)?;
+        if !cookies.is_empty() {
+            trace!(url, cookies = cookies.len(), "setting consent cookies");
+            tab.set_cookies(cookies)
+                .context("setting consent cookies")?;
+        }
+        Ok(())
+    }
+
+    /// answers the consent banner of the page open in `tab`, and removes the overlays left. it
+    /// waits a bit for the banner to show on the first page of a host in the browser context
+    /// of `tab`, and only looks once on the next ones. the name of the CMP whose button was
+    /// clicked, if any
+    pub fn dismiss(&self, tab: &Tab) -> Result<Option<&'static str>> {
+        let url = tab.get_url();
+        let host = Url::parse(&url)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_string))
+            .unwrap_or_default();
+        let buttons = buttons(self.file.choice_for(&host));
+        let context = tab
+            .get_target_info()
+            .ok()
+            .and_then(|info| info.browser_context_id)
+            .unwrap_or_default();
+        let wait = if self.first_check(&context, &host) {
+            BANNER_WAIT
+        } else {
+            Duration::ZERO
+        };
+        let deadline = Instant::now() + wait;
+        let clicked = loop {
+            let found = buttons
+                .iter()
+                .find_map(|(cmp, b)| find(tab, b).map(|button| (*cmp, button)));
+            if let Some((cmp, button)) = found {
+                button
+                    .click()
+                    .with_context(|| format!("clicking on the {cmp} consent"))?;
+                trace!(cmp, %url, "answered the consent");
+                // some banners reload the page
+                tab.wait_until_navigated()?;
+                thread::sleep(Duration::from_secs(1));
+                break Some(cmp);
+            }
+            if Instant::now() >= deadline {
+                break None;
+            }
+            thread::sleep(Duration::from_millis(500));
+        };
+        match tab.evaluate(REMOVE_OVERLAYS, false) {
+            Ok(removed) => {
+                if let Some(n) = removed.value.and_then(|v| v.as_u64()).filter(|n| *n > 0) {
+                    trace!(%url, removed = n, "removed overlays");
+                }
+            }
+            Err(e) => debug!(%url, error = %e, "removing the overlays"),
+        }
+        Ok(clicked)
+    }
+}
+
+fn find<'a>(tab: &'a Tab, button: &Button) -> Option<Element<'a>> {
+    match button {
+        Button::Css(selector) => tab.find_element(selector).ok(),
+        Button::Text(text) => tab.find_element_by_xpath(&text_xpath(text)).ok(),
+    }
+}
+
+fn text_xpath(text: &str) -> String {
+    let names = "translate(concat(@id, ' ', @class), 'ABCDEFGHIJKLMNOPQRSTUVWXYZ', 'abcdefghijklmnopqrstuvwxyz')";
+    let words: Vec<String> = CONTAINER_WORDS
+        .iter()
+        .map(|word| format!("contains({names}, '{word}')"))
+        .collect();
+    format!(
+        "//*[@role='dialog' or @aria-modal='true' or {}]//button[contains(normalize-space(.), \"{text}\")]",
+        words.join(" or ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_answers_per_domain() {
+        let file: ConsentFile = serde_json::from_str(
+            r#"{
+                "choice": "reject",
+                "domains": {
+                    "lemonde.fr": { "choice": "accept", "cookies": { "euconsent-v2": "CPx" } },
+                    "www.lemonde.fr": { "choice": "reject" }
+                }
+            }"#,
+        )
+        .unwrap();
+        file.validate().unwrap();
+        assert_eq!(file.choice_for("a.fr"), Choice::Reject);
+        assert_eq!(file.choice_for("afrique.lemonde.fr"), Choice::Accept);
+        assert_eq!(file.choice_for("www.lemonde.fr"), Choice::Reject);
+        assert_eq!(ConsentFile::default().choice_for("a.fr"), Choice::Accept);
+        assert_eq!(
+            ConsentFile::default().choice_for("www.rtbf.be"),
+            Choice::Reject
+        );
+
+        let consent = Consent::new(file);
+        let cookies = consent.cookies_for("https://afrique.lemonde.fr/a").unwrap();
+        assert_eq!(cookies.len(), 1);
+        assert_eq!(
+            (cookies[0].name.as_str(), cookies[0].value.as_str()),
+            ("euconsent-v2", "CPx")
+        );
+        assert_eq!(cookies[0].domain.as_deref(), Some(".lemonde.fr"));
+        assert!(consent.cookies_for("https://a.fr/").unwrap().is_empty());
+
+        let invalid: ConsentFile =
+            serde_json::from_str(r#"{ "domains": { "a.fr": { "cookies": { "": "1" } } } }"#)
+                .unwrap();
+        assert!(invalid.validate().is_err());
+        assert!(serde_json::from_str::<ConsentFile>(r#"{ "choice": "ignore" }"#).is_err());
+    }
+
+    #[test]
+    fn it_tries_the_buttons_of_the_choice_first() {
+        let accept = buttons(Choice::Accept);
+        assert_eq!(
+            accept[0],
+            ("didomi", Button::Css("#didomi-notice-agree-button"))
+        );
+        let reject = buttons(Choice::Reject);
+        assert_eq!(
+            reject[0],
+            ("didomi", Button::Css("#didomi-notice-disagree-button"))
+        );
+        // refusing is tried everywhere before accepting anywhere
+        let first_accept = reject
+            .iter()
+            .position(|b| b.1 == Button::Css("#didomi-notice-agree-button"))
+            .unwrap();
+        let last_reject = reject
+            .iter()
+            .rposition(|b| b.1 == Button::Text("Reject all"))
+            .unwrap();
+        assert!(last_reject < first_accept);
+        // a cookie wall without a refuse button is still accepted
+        assert!(reject.contains(&("lemonde", Button::Css("button.gdpr-lmd-button"))));
+        assert_eq!(accept.len(), reject.len());
+    }
+
+    #[test]
+    fn it_waits_for_a_banner_once_per_context_and_host() {
+        let consent = Consent::new(ConsentFile::default());
+        assert!(consent.first_check("a", "www.rtbf.be"));
+        assert!(!consent.first_check("a", "www.rtbf.be"));
+        assert!(consent.first_check("a", "auvio.rtbf.be"));
+        assert!(consent.first_check("b", "www.rtbf.be"));
+
+        // only the buttons of a banner, not any `Accepter` of the page
+        let xpath = text_xpath("Accepter");
+        assert!(xpath.starts_with("//*[@role='dialog' or @aria-modal='true' or contains("));
+        assert!(xpath.contains("'cookie')"));
+        assert!(xpath.ends_with("]//button[contains(normalize-space(.), \"Accepter\")]"));
+    }
+}
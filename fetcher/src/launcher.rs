@@ -1,94 +1,151 @@
-use std::{
-    ffi::OsStr,
-    sync::{Arc, RwLock},
-    time::Duration,
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex, RwLock,
 };
 
-use crate::sources::{GetNewsOpts, SourceFn};
+use crate::{
+    pool::Pool,
+    sources::{self, GetNewsOpts, SourceFn},
+};
 use anyhow::Context;
+use chrono::{DateTime, Utc};
 use futures::{stream::FuturesUnordered, StreamExt};
-use headless_chrome::{Browser, LaunchOptionsBuilder};
-use log::{error, info};
-use shared::{config::Config, News, Telegram};
+use headless_chrome::Browser;
+use shared::{config::Config, metrics, News, Notify};
 use tokio::{
     sync::mpsc::{channel, Receiver},
-    task::{spawn_blocking, JoinHandle},
+    task::spawn_blocking,
 };
+use tracing::{error, info, info_span};
 
-/// this is because the headless chrome crate doesn't support using multiple tab at the same time
-/// if we want some parallelism, we need to spawn a whole new browser for each source
-fn new_browser(headless: bool) -> Browser {
-    let browser = Browser::new(
-        LaunchOptionsBuilder::default()
-            .window_size(Some((1920, 1080)))
-            .headless(headless)
-            .devtools(false)
-            .args(vec![OsStr::new("--blink-settings=imagesEnabled=false")])
-            .idle_browser_timeout(Duration::from_secs(120))
-            .sandbox(false)
-            .build()
-            .unwrap(),
-    )
-    .unwrap();
-    browser
+/// a source that stopped, successfully or not
+#[derive(Debug)]
+pub struct Finished {
+    pub source: String,
+    pub started: DateTime<Utc>,
+    pub finished: DateTime<Utc>,
+    pub error: Option<String>,
 }
 
-pub fn init(
-    config: &Config,
-    sources: Vec<&'static (&'static str, SourceFn)>,
-    seen_links: Arc<RwLock<Vec<String>>>,
-    telegram: Arc<Telegram>,
-) -> Receiver<anyhow::Result<News>> {
-    let config = Arc::new(config.clone());
-    let (tx, rx) = channel(500);
-    let mut futures: FuturesUnordered<JoinHandle<anyhow::Result<()>>> = FuturesUnordered::new();
-    let mut sources: Vec<_> = sources
-        .into_iter()
-        .map(|(s, f)| ((*s).to_string(), f))
-        .collect();
+pub struct Launcher {
+    config: Arc<Config>,
+    notify: Notify,
+    pool: Arc<Pool<Browser>>,
+    cancel: Arc<AtomicBool>,
+}
 
-    while futures.len() < config.chrome_concurrent.unwrap_or(4) {
-        match sources.pop() {
-            Some(source) => {
-                info!("spawning {}", source.0);
-                let opts = GetNewsOpts {
-                    browser: new_browser(config.chrome_headless.unwrap_or(true)),
-                    tx: tx.clone(),
-                    seen_links: seen_links.clone(),
-                    provider: source.0.to_string(),
-                };
-                futures.push(spawn_blocking(move || source.1(opts).context(source.0)));
-            }
-            None => break,
+impl Launcher {
+    pub fn new(config: &Config, notify: Notify, cancel: Arc<AtomicBool>) -> Launcher {
+        Launcher {
+            config: Arc::new(config.clone()),
+            notify,
+            pool: Arc::new(Pool::browsers(
+                config.chrome_pool_size.unwrap_or(2),
+                config.chrome_max_uses.unwrap_or(20),
+                config.chrome_headless.unwrap_or(true),
+            )),
+            cancel,
         }
     }
-    tokio::spawn(async move {
-        while let Some(result) = futures.next().await {
-            match result {
-                Ok(Err(e)) => tx.send(Err(e)).await.unwrap(),
-                Err(e) => {
-                    error!("thread panicked: {e}");
-                    if let Err(e) = telegram.send(format!("fetcher: thread panicked: {e}")) {
-                        error!("TelegramError: {}", e);
-                    }
-                    continue;
-                }
-                _ => (),
-            };
-            match sources.pop() {
-                Some(source) => {
-                    info!("spawning {}", source.0);
-                    let opts = GetNewsOpts {
-                        browser: new_browser(config.chrome_headless.unwrap_or(true)),
-                        tx: tx.clone(),
-                        seen_links: seen_links.clone(),
+
+    pub fn close(&self) {
+        self.pool.close_idle();
+    }
+
+    pub fn launch(
+        &self,
+        sources: Vec<&'static (&'static str, SourceFn)>,
+        seen_links: Arc<RwLock<Vec<String>>>,
+    ) -> (Receiver<anyhow::Result<News>>, Arc<Mutex<Vec<Finished>>>) {
+        let notify = self.notify.clone();
+        let pool = self.pool.clone();
+        let cancel = self.cancel.clone();
+        let source_cancel = self.cancel.clone();
+        let (tx, rx) = channel(500);
+        let error_tx = tx.clone();
+        // filled before the news channel closes, so it's complete once `rx` returns None
+        let finished = Arc::new(Mutex::new(Vec::new()));
+        let finished_tx = finished.clone();
+        let mut futures = FuturesUnordered::new();
+        let mut sources: Vec<_> = sources
+            .into_iter()
+            .map(|(s, f)| ((*s).to_string(), f))
+            .collect();
+        let spawn = move |source: (String, &'static SourceFn)| {
+            let span = info_span!("source", source = %source.0);
+            info!(parent: &span, "spawning");
+            let args = sources::network().chrome_args(&source.0);
+            let pool = pool.clone();
+            let tx = tx.clone();
+            let seen_links = seen_links.clone();
+            let cancel = source_cancel.clone();
+            let name = source.0.clone();
+            let started = Utc::now();
+            let source_span = span.clone();
+            let handle = spawn_blocking(move || {
+                // the news and the logs of the source belong to its span
+                let _entered = source_span.enter();
+                // waits for a browser when the pool is full
+                let result = pool.lease(&args).and_then(|browser| {
+                    source.1(GetNewsOpts {
+                        browser,
+                        tx,
+                        seen_links,
                         provider: source.0.to_string(),
-                    };
-                    futures.push(spawn_blocking(move || source.1(opts).context(source.0)));
-                }
+                        cancel,
+                    })
+                });
+                result.context(source.0)
+            });
+            // keep the name around, a panic doesn't say which source it was
+            async move { (name, started, span, handle.await) }
+        };
+
+        while futures.len() < self.config.chrome_concurrent.unwrap_or(4) {
+            match sources.pop() {
+                Some(source) => futures.push(spawn(source)),
                 None => break,
             }
         }
-    });
-    rx
+        tokio::spawn(async move {
+            while let Some((source, started, span, result)) = futures.next().await {
+                let error = match result {
+                    Ok(Err(e)) => {
+                        let error = format!("{e:#}");
+                        error_tx.send(Err(e)).await.unwrap();
+                        Some(error)
+                    }
+                    Err(e) => {
+                        error!(parent: &span, "thread panicked: {e}");
+                        notify.error(format!("{source} thread panicked: {e}"));
+                        Some(format!("panicked: {e}"))
+                    }
+                    Ok(Ok(())) => None,
+                };
+                if error.is_some() {
+                    metrics::SOURCE_ERRORS.with_label_values(&[&source]).inc();
+                }
+                let duration = (Utc::now() - started).to_std().unwrap_or_default();
+                info!(parent: &span, ?duration, failed = error.is_some(), "finished");
+                metrics::SOURCE_DURATION
+                    .with_label_values(&[&source])
+                    .observe(duration.as_secs_f64());
+                finished_tx.lock().unwrap().push(Finished {
+                    source,
+                    started,
+                    finished: Utc::now(),
+                    error,
+                });
+                if cancel.load(Ordering::Relaxed) {
+                    if !sources.is_empty() {
+                        info!("cancelled, not starting {} sources", sources.len());
+                        sources.clear();
+                    }
+                } else if let Some(source) = sources.pop() {
+                    futures.push(spawn(source));
+                }
+            }
+        });
+        (rx, finished)
+    }
 }
@@ -1,26 +1,45 @@
+mod bot;
+mod consent;
 mod launcher;
+mod network;
+mod politeness;
+mod pool;
+mod scheduler;
 mod sources;
+mod validation;
 use anyhow::Result;
-use chrono::DateTime;
+use bot::Bot;
+use chrono::Local;
 use clap::builder::PossibleValuesParser;
-use clap::Parser;
-use env_logger::Builder;
-use log::{debug, error, info, trace};
-use shared::{config::Config, db_news::DbNews, extract_clean_text, sanitize_html, Telegram};
-use sources::{extract_prefix_from_provider, SOURCES};
+use clap::{Parser, Subcommand};
+use consent::{Consent, ConsentFile};
+use launcher::Launcher;
+use network::{Network, NetworkFile};
+use politeness::{Politeness, PolitenessFile};
+use scheduler::{ScheduleFile, Scheduler};
+use shared::{
+    config::{Config, ConfigArgs, ConfigCommand},
+    ingest::{ingest_checked, Ingested},
+    logging,
+    metrics::{self, Exporter},
+    retention::Policy,
+    store::{self, NewsStore, SourceRun},
+    Notify, Telegram,
+};
+use sources::{SourceFn, SOURCES};
 use std::{
-    borrow::Cow,
-    convert::Into,
-    env,
+    collections::BTreeMap,
+    path::PathBuf,
     process::{self, exit},
     sync::{
-        atomic::{AtomicU16, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
         Arc, RwLock,
     },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
-use surrealdb::{engine::remote::http::Http, opt::auth::Root, Surreal};
+use tracing::{debug, error, info, info_span, trace, Instrument};
+use validation::{ValidationFile, Validator};
 
 #[derive(Parser, Debug)]
 struct Cli {
@@ -33,8 +52,8 @@ struct Cli {
     list: bool,
     #[arg(long, short, value_delimiter = ',', num_args = 1.., help = "Enable ONLY specified news sources", value_parser = PossibleValuesParser::new(SOURCES.iter().map(|(name, _)| *name)))]
     enable: Option<Vec<String>>,
-    #[arg(long, default_value = ".env")]
-    env_file: String,
+    #[command(flatten)]
+    config: ConfigArgs,
     #[arg(long, help = "Run chrome in headless mode")]
     headless: Option<bool>,
     #[arg(
@@ -43,14 +62,47 @@ struct Cli {
         default_value = "false"
     )]
     ignore_empty_db: bool,
+    #[arg(
+        long,
+        help = "Print how many news each retention rule would wipe, then exit",
+        default_value = "false"
+    )]
+    retention_dry_run: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Keep running and fetch each source on its own schedule, instead of once
+    Daemon {
+        #[arg(
+            long,
+            help = "JSON file with a cron expression or an interval per source (see schedule.example.json)"
+        )]
+        schedule: Option<PathBuf>,
+        #[arg(
+            long,
+            help = "Also answer the telegram bot commands, see the `bot` command"
+        )]
+        bot: bool,
+    },
+    /// Answer the commands sent to the telegram bot by TELEGRAM_ID (/status, /top, /rerate, /used, /disable, /enable)
+    Bot,
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Apply the pending database migrations, then exit
+    Migrate {
+        #[arg(long, help = "Only check that the schema is up to date")]
+        check: bool,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    Builder::new()
-        .parse_filters(&env::var("RUST_LOG").unwrap_or("fetcher=debug".into()))
-        .init();
-
     let cli = Cli::parse();
     if cli.list {
         println!("Available sources:");
@@ -60,45 +112,97 @@ async fn main() -> Result<()> {
         return Ok(());
     }
 
-    let mut config = Config::load(&cli.env_file).unwrap_or_else(|e| {
-        error!("{}: {:?}", cli.env_file, e);
+    let mut loader = cli.config.loader("fetcher")?;
+    if let Some(value) = cli.headless {
+        loader = loader.set("chrome_headless", value);
+    }
+    let config = loader.load();
+    let _logging = logging::init("fetcher", "fetcher=debug", config.as_ref().ok())?;
+    let config = config.unwrap_or_else(|e| {
+        error!("config: {e:?}");
         exit(1);
     });
-    if let Some(value) = cli.headless {
-        config.chrome_headless = Some(value);
+    if let Some(Command::Config {
+        command: ConfigCommand::Check,
+    }) = cli.command
+    {
+        print!("{}", config.redacted_toml()?);
+        return Ok(());
     }
+    sources::ARTICLE_PARSER_URL
+        .set(config.article_parser_url.clone())
+        .unwrap();
+    let network = match config.network_file {
+        Some(ref path) => NetworkFile::load(path)?,
+        None => NetworkFile::default(),
+    };
+    sources::NETWORK
+        .set(Network::new(network))
+        .unwrap_or_else(|_| unreachable!("set once"));
+    let politeness = match config.politeness_file {
+        Some(ref path) => PolitenessFile::load(path)?,
+        None => PolitenessFile::default(),
+    };
+    sources::POLITENESS
+        .set(Politeness::new(politeness).with_network(sources::network()))
+        .unwrap_or_else(|_| unreachable!("set once"));
+    let consent = match config.consent_file {
+        Some(ref path) => ConsentFile::load(path)?,
+        None => ConsentFile::default(),
+    };
+    sources::CONSENT
+        .set(Consent::new(consent))
+        .unwrap_or_else(|_| unreachable!("set once"));
+    let validator = Validator::new(match config.validation_file {
+        Some(ref path) => ValidationFile::load(path)?,
+        None => ValidationFile::default(),
+    });
 
-    let telegram = Telegram::new(config.telegram_token.clone(), config.telegram_id);
-    let telegram = Arc::new(telegram);
+    let notify = Notify::from_config(&config, "fetcher")?;
 
-    let db = Surreal::new::<Http>(&config.surrealdb_host).await?;
-    db.signin(Root {
-        username: &config.db_user,
-        password: &config.db_password,
-    })
-    .await?;
-    db.use_ns("news").use_db("news").await?;
+    let store = store::connect(&config).await?;
+    if let Some(Command::Migrate { check }) = cli.command {
+        if check {
+            store.check_schema().await?;
+            info!("schema is up to date");
+        } else {
+            let applied = store.migrate().await?;
+            info!("applied {} migrations: {applied:?}", applied.len());
+        }
+        return Ok(());
+    }
+    let applied = store::ensure_schema(store.as_ref(), &config).await?;
+    if !applied.is_empty() {
+        info!("applied migrations {applied:?}");
+    }
 
-    let counter = Arc::new(AtomicU16::default());
+    let counter = Arc::new(AtomicUsize::default());
+    let cancel = Arc::new(AtomicBool::new(false));
     {
         let counter = counter.clone();
+        let cancel = cancel.clone();
         ctrlc::set_handler(move || {
-            info!("ctrl-c received, exiting. ");
+            if !cancel.swap(true, Ordering::Relaxed) {
+                info!("ctrl-c received, finishing the current articles. send it again to exit now");
+                return;
+            }
+            info!("ctrl-c received again, exiting. ");
             info!("Total news recorded: {}", counter.load(Ordering::Relaxed));
-            process::exit(0);
+            process::exit(1);
         })
         .unwrap();
     }
-    {
-        let counter = counter.clone();
-        std::panic::set_hook(Box::new(move |panic_info| {
-            println!(
-                "Exiting due to panic. Number of tasks done: {}",
-                counter.load(Ordering::SeqCst)
-            );
-            println!("Panic info: {panic_info}");
-            process::exit(1);
-        }));
+    // a panic in a source only kills its thread, the launcher reports it when joining
+    std::panic::set_hook(Box::new(|panic_info| {
+        error!("panic: {panic_info}");
+    }));
+
+    if let Some(Command::Bot) = cli.command {
+        Bot::new(Telegram::from_config(&config), store.clone())?
+            .rater_languages(config.rater_languages.clone())
+            .run(cancel)
+            .await;
+        return Ok(());
     }
 
     let sources: Vec<_> = match cli.enable {
@@ -109,30 +213,13 @@ async fn main() -> Result<()> {
         None => SOURCES.iter().collect(),
     };
 
-    // Clean up old news content (older than 90 days)
-    let cleanup_result = db
-        .query("UPDATE news SET html_body = '', text_body = '' WHERE date < time::now() - 60d && string::len(html_body) > 0 RETURN id")
-        .await;
-    match cleanup_result.and_then(|mut r| r.take::<Vec<surrealdb::sql::Thing>>((0, "id"))) {
-        Ok(values) => {
-            info!(
-                "Cleaned up old news content (older than 60 days): {} rows affected",
-                values.len()
-            );
-        }
-        Err(e) => {
-            error!("Failed to clean up old news: {e:#?}");
-            if let Err(te) = telegram.send(format!("fetcher: cleanup failed: {e:#?}")) {
-                error!("telegram.send: {:#?}", te);
-            }
-        }
+    apply_retention(&config, store.as_ref(), &notify, cli.retention_dry_run).await;
+    if cli.retention_dry_run {
+        return Ok(());
     }
 
-    let seen_news: Vec<String> = db
-        .query("select value link from news parallel")
-        .await?
-        .take(0)
-        .unwrap_or_default();
+    let exporter = Exporter::start(&config, "fetcher")?;
+    let seen_news = store.seen_links().await?;
 
     assert!(!seen_news.is_empty() || cli.ignore_empty_db);
     info!(
@@ -144,70 +231,310 @@ async fn main() -> Result<()> {
     );
 
     let seen_news = Arc::new(RwLock::new(seen_news));
-    let mut rx = launcher::init(&config, sources, seen_news.clone(), telegram.clone());
+    let launcher = Launcher::new(&config, notify.clone(), cancel.clone());
+    let mut summary = Summary {
+        recorded: counter.clone(),
+        ..Default::default()
+    };
+    match cli.command {
+        Some(Command::Daemon { ref schedule, bot }) => {
+            let schedule = match schedule {
+                Some(path) => ScheduleFile::load(path)?,
+                None => ScheduleFile::default(),
+            };
+            if bot {
+                let bot = Bot::new(Telegram::from_config(&config), store.clone())?;
+                let cancel = cancel.clone();
+                tokio::spawn(async move { bot.run(cancel).await });
+            }
+            let mut scheduler =
+                Scheduler::new(&schedule, sources.iter().map(|s| s.0), Local::now())?;
+            let mut last_retention = Instant::now();
+            while !cancel.load(Ordering::Relaxed) {
+                let due = scheduler.due(Local::now());
+                if due.is_empty() {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+                if last_retention.elapsed() > Duration::from_secs(24 * 60 * 60) {
+                    apply_retention(&config, store.as_ref(), &notify, false).await;
+                    last_retention = Instant::now();
+                }
+                info!("running {due:?}");
+                let due = sources.iter().filter(|s| due.contains(&s.0)).copied();
+                fetch(
+                    &launcher,
+                    due.collect(),
+                    &seen_news,
+                    store.as_ref(),
+                    &notify,
+                    &validator,
+                    &mut summary,
+                )
+                .await;
+                if let Some(next) = scheduler.next_run() {
+                    info!(
+                        "Total news recorded: {}, next run at {next}",
+                        counter.load(Ordering::Relaxed)
+                    );
+                }
+            }
+        }
+        Some(Command::Migrate { .. } | Command::Config { .. } | Command::Bot) => {
+            unreachable!("handled before")
+        }
+        None => {
+            fetch(
+                &launcher,
+                sources,
+                &seen_news,
+                store.as_ref(),
+                &notify,
+                &validator,
+                &mut summary,
+            )
+            .await;
+        }
+    }
+    launcher.close();
+    notify.flush().await;
+    exporter.flush();
+    summary.log();
+    info!("Total news recorded: {}", counter.load(Ordering::Relaxed));
+    Ok(())
+}
+
+#[derive(Default, Debug)]
+struct ProviderSummary {
+    inserted: usize,
+    merged: usize,
+    failed: usize,
+    /// inserted with only the teaser of a paywall
+    paywalled: usize,
+    /// not saved, see [`Validator`]
+    rejected: usize,
+}
+
+/// what happened per provider, logged when the fetcher exits
+#[derive(Default)]
+struct Summary {
+    providers: BTreeMap<String, ProviderSummary>,
+    source_errors: usize,
+    started: Option<Instant>,
+    /// news saved, also read by the ctrl-c handler
+    recorded: Arc<AtomicUsize>,
+}
+
+impl Summary {
+    fn provider(&mut self, provider: &str) -> &mut ProviderSummary {
+        self.providers.entry(provider.to_string()).or_default()
+    }
+    fn log(&self) {
+        for (provider, s) in &self.providers {
+            info!(
+                "{provider}: {} inserted ({} paywalled), {} merged, {} rejected, {} failed",
+                s.inserted, s.paywalled, s.merged, s.rejected, s.failed
+            );
+        }
+        let total =
+            |f: fn(&ProviderSummary) -> usize| self.providers.values().map(f).sum::<usize>();
+        info!(
+            "summary: {} inserted ({} paywalled), {} merged, {} rejected, {} failed to save, {} source errors, in {:?}",
+            total(|s| s.inserted),
+            total(|s| s.paywalled),
+            total(|s| s.merged),
+            total(|s| s.rejected),
+            total(|s| s.failed),
+            self.source_errors,
+            self.started.map(|s| s.elapsed()).unwrap_or_default()
+        );
+    }
+}
+
+async fn apply_retention(config: &Config, store: &dyn NewsStore, notify: &Notify, dry_run: bool) {
+    let retention = match config.retention_file {
+        Some(ref path) => Policy::load(path),
+        None => Ok(Policy::default()),
+    };
+    let result = match retention {
+        Ok(retention) => store.apply_retention(&retention, dry_run).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(reports) => {
+            for r in &reports {
+                info!(
+                    "retention rule {}: {} news with body, {} {}, {} archived",
+                    r.rule,
+                    r.matched,
+                    r.wiped,
+                    if dry_run { "would be wiped" } else { "wiped" },
+                    r.archived
+                );
+            }
+        }
+        Err(e) => {
+            error!(error = %format!("{e:#}"), "failed to apply the retention policy");
+            notify.error(format!("retention failed: {e:#}"));
+        }
+    }
+}
+
+/// run the sources once, saving what they find until they are all done
+async fn fetch(
+    launcher: &Launcher,
+    sources: Vec<&'static (&'static str, SourceFn)>,
+    seen_news: &Arc<RwLock<Vec<String>>>,
+    store: &dyn NewsStore,
+    notify: &Notify,
+    validator: &Validator,
+    summary: &mut Summary,
+) {
+    summary.started.get_or_insert_with(Instant::now);
+    let sources = match store.disabled_sources().await {
+        Ok(disabled) => sources
+            .into_iter()
+            .filter(|s| {
+                let enabled = !disabled.iter().any(|d| d == s.0);
+                if !enabled {
+                    info!("{} is disabled, skipping it", s.0);
+                }
+                enabled
+            })
+            .collect(),
+        Err(e) => {
+            error!(error = %format!("{e:#}"), "getting the disabled sources");
+            sources
+        }
+    };
+    // counts of this run only, `summary` is for the whole process
+    let mut run = BTreeMap::<String, ProviderSummary>::new();
+    let (mut rx, finished) = launcher.launch(sources, seen_news.clone());
     while let Some(recved) = rx.recv().await {
-        let mut news = match recved {
+        let news = match recved {
             Ok(news) => news,
             Err(err) => {
-                error!("recv: {:#?}", err);
-                if let Err(e) = telegram.send(format!("fetcher: recv: {err:#?}")) {
-                    error!("telegram.send: {:#?}", e);
-                }
+                summary.source_errors += 1;
+                error!(error = %format!("{err:#}"), "recv");
+                notify.error(format!("recv: {err:#}"));
                 continue;
             }
         };
-        trace!(
-            "recv news: {}: {:.20?}..., link: {:?}",
-            news.provider,
-            news.title,
-            news.link
-        );
-        news.tags.push(extract_prefix_from_provider(&news.provider));
-        let error: Option<anyhow::Error>;
-        if seen_news.read().unwrap().contains(&news.link) {
-            debug!(
-                "news already seen with different provider, merging: tags: {:?}, link: {}",
-                news.tags, news.link
-            );
-            let result: Result<_, surrealdb::Error> = db
-                .query("update news set tags = array::union(tags, $newtags) where link = $link return none")
-                .bind(("newtags", news.tags.clone()))
-                .bind(("link", news.link.clone()))
-                .await;
-            error = match result {
-                Ok(result) => result.check().err().map(Into::into),
-                Err(e) => Some(e.into()),
-            };
-        } else {
-            debug!("new news: {}", news.link);
-            let html_body = sanitize_html(&news.body);
-            let text_body = extract_clean_text(&html_body);
-            let result: Result<Vec<DbNews>, surrealdb::Error> = db
-                .create("news")
-                .content(DbNews {
-                    title: news.title.into(),
-                    link: Cow::Owned(news.link.clone()),
-                    tags: news.tags.clone(),
-                    html_body: html_body.into(),
-                    text_body: text_body.into(),
-                    provider: news.provider.into(),
-                    date: DateTime::from(news.date).into(),
-                    caption: news.caption.into(),
-                    ..Default::default()
-                })
-                .await;
-            error = result.err().map(Into::into);
+        let span = info_span!("article", provider = %news.provider, link = %news.link);
+        trace!(parent: &span, title = %news.title, "recv news");
+        metrics::NEWS_FETCHED
+            .with_label_values(&[&news.provider])
+            .inc();
+        let provider = news.provider.clone();
+        let mut rejected = Vec::new();
+        let mut paywalled = false;
+        // only the new news, a link already saved gets the tags of this source whatever its page
+        let ingested = ingest_checked(store, seen_news, news, |n| {
+            let verdict = validator.validate(n);
+            let flags = verdict.flagged.iter().map(|r| r.name().to_string());
+            n.quality_flags.extend(flags);
+            paywalled = n.paywalled;
+            rejected = verdict.rejected;
+            rejected.is_empty()
+        })
+        .instrument(span.clone())
+        .await;
+        let _entered = span.enter();
+        match ingested {
+            Ok(Ingested::Rejected) => {
+                let rules: Vec<_> = rejected.iter().map(|r| r.name()).collect();
+                debug!(?rules, "rejected");
+                for rule in rules {
+                    metrics::NEWS_REJECTED
+                        .with_label_values(&[&provider, rule])
+                        .inc();
+                }
+                summary.provider(&provider).rejected += 1;
+            }
+            Ok(ingested) => {
+                debug!(?ingested, "saved");
+                summary.recorded.fetch_add(1, Ordering::Relaxed);
+                let inserted = ingested == Ingested::Inserted;
+                let result = if inserted { "inserted" } else { "merged" };
+                metrics::NEWS_SAVED
+                    .with_label_values(&[&provider, result])
+                    .inc();
+                if paywalled {
+                    debug!("paywalled");
+                    metrics::NEWS_PAYWALLED
+                        .with_label_values(&[&provider])
+                        .inc();
+                    summary.provider(&provider).paywalled += 1;
+                }
+                for s in [
+                    summary.provider(&provider),
+                    run.entry(provider).or_default(),
+                ] {
+                    if inserted {
+                        s.inserted += 1;
+                    } else {
+                        s.merged += 1;
+                    }
+                }
+            }
+            Err(e) => {
+                metrics::NEWS_SAVED
+                    .with_label_values(&[&provider, "failed"])
+                    .inc();
+                summary.provider(&provider).failed += 1;
+                run.entry(provider).or_default().failed += 1;
+                error!(error = %format!("{e:#}"), "saving the news");
+                notify.error(format!("db: {e:#}"));
+                thread::sleep(Duration::from_secs(1));
+            }
         }
-        seen_news.write().unwrap().push(news.link);
-        if let Some(e) = error {
-            error!("db: {e:#?}");
-            telegram.send(format!("fetcher: db: {e:#?}")).ok();
-            thread::sleep(Duration::from_secs(1));
-            continue;
-        } else {
-            counter.fetch_add(1, Ordering::Relaxed);
+    }
+    let finished = std::mem::take(&mut *finished.lock().unwrap());
+    for f in finished {
+        let counts = run.remove(&f.source).unwrap_or_default();
+        let source_run = SourceRun {
+            source: f.source,
+            started: f.started.into(),
+            finished: f.finished.into(),
+            inserted: counts.inserted,
+            merged: counts.merged,
+            failed: counts.failed,
+            error: f.error,
+        };
+        if let Err(e) = store.record_run(&source_run).await {
+            error!(source = %source_run.source, error = %format!("{e:#}"), "recording the run");
         }
     }
-    info!("Total news recorded: {}", counter.load(Ordering::Relaxed));
-    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::{
+        notify::{Recorder, Route, Router, Severity},
+        store::SqliteStore,
+    };
+
+    #[tokio::test]
+    async fn it_notifies_when_retention_fails() {
+        let recorder = Recorder::default();
+        let router = Router::new(vec![Route::new(
+            Arc::new(recorder.clone()),
+            Severity::Info,
+            None,
+        )]);
+        let notify = Notify::spawn(router, "fetcher", Duration::from_secs(60));
+        let config = Config {
+            retention_file: Some("does-not-exist.json".into()),
+            ..Default::default()
+        };
+        let store = SqliteStore::open_in_memory().unwrap();
+        apply_retention(&config, &store, &notify, true).await;
+        notify.flush().await;
+
+        let messages = recorder.messages();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].severity, Severity::Error);
+        assert!(messages[0].text.starts_with("retention failed"));
+    }
 }
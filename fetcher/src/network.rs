@@ -0,0 +1,269 @@
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use shared::config::load_json;
+use url::Url;
+
+/// a proxy rule meaning no proxy, to exempt a source or a domain from a wider one
+const DIRECT: &str = "direct";
+
+/// what the fetcher downloads with when the pool is empty, the same as the article parser's
+pub const DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/129.0.0.0 Safari/537.3";
+
+/// network file, routing the requests of the fetcher through proxies and rotating user agents:
+/// ```json
+/// { "proxy": "http://10.0.0.2:3128", "sources": { "quebec::*": "socks5://10.0.0.3:1080" },
+///   "domains": { "lemonde.fr": "direct" }, "user_agents": ["Mozilla/5.0 ..."] }
+/// ```
+/// a proxy is `http://`, `socks4://` or `socks5://host:port`, or `direct`. a domain (and its
+/// subdomains) takes precedence over a source, which takes precedence over `proxy`. a source
+/// name takes precedence over a `region::*` pattern.
+#[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkFile {
+    pub proxy: Option<String>,
+    #[serde(default)]
+    pub sources: BTreeMap<String, String>,
+    #[serde(default)]
+    pub domains: BTreeMap<String, String>,
+    /// used in turn by the browser tabs and the article fetches. the browser's own one when empty
+    #[serde(default)]
+    pub user_agents: Vec<String>,
+}
+
+impl NetworkFile {
+    pub fn load(path: impl AsRef<Path>) -> Result<NetworkFile> {
+        let path = path.as_ref();
+        let file: NetworkFile = load_json(path)?;
+        file.validate().with_context(|| format!("in {path:?}"))?;
+        Ok(file)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let proxies = self.proxy.iter().chain(self.sources.values());
+        for proxy in proxies.chain(self.domains.values()) {
+            if proxy != DIRECT {
+                parse_proxy(proxy)?;
+            }
+        }
+        if self.user_agents.iter().any(|ua| ua.trim().is_empty()) {
+            bail!("empty user agent");
+        }
+        Ok(())
+    }
+}
+
+/// the entry of `domains` for `host`: its own, or the one of its closest parent domain
+pub fn most_specific_domain<'a, V>(
+    domains: &'a BTreeMap<String, V>,
+    host: &str,
+) -> Option<(&'a str, &'a V)> {
+    domains
+        .iter()
+        .filter(|(d, _)| host == *d || host.ends_with(&format!(".{d}")))
+        .max_by_key(|(d, _)| d.len())
+        .map(|(d, v)| (d.as_str(), v))
+}
+
+/// `(scheme, host:port)` of a proxy
+fn parse_proxy(proxy: &str) -> Result<(String, String)> {
+    let url = Url::parse(proxy).with_context(|| format!("invalid proxy `{proxy}`"))?;
+    let scheme = url.scheme();
+    if !matches!(scheme, "http" | "socks4" | "socks5") {
+        bail!("proxy `{proxy}`: expected http://, socks4:// or socks5://");
+    }
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        bail!("proxy `{proxy}`: expected host:port");
+    };
+    Ok((scheme.to_string(), format!("{host}:{port}")))
+}
+
+/// routes the requests of the sources, shared by their threads
+pub struct Network {
+    file: NetworkFile,
+    next_agent: AtomicUsize,
+}
+
+impl Network {
+    pub fn new(file: NetworkFile) -> Network {
+        Network {
+            file,
+            next_agent: AtomicUsize::new(0),
+        }
+    }
+
+    /// the proxy of the source, without looking at the domains
+    fn source_proxy(&self, source: &str) -> Option<&str> {
+        let region = source.split_once("::").map(|(r, _)| format!("{r}::*"));
+        self.file
+            .sources
+            .get(source)
+            .or_else(|| region.and_then(|r| self.file.sources.get(&r)))
+            .or(self.file.proxy.as_ref())
+            .map(String::as_str)
+            .filter(|p| *p != DIRECT)
+    }
+
+    /// the proxy of the domain of `url`, `Some(None)` when it's `direct`
+    fn domain_proxy(&self, url: &str) -> Option<Option<&str>> {
+        let url = Url::parse(url).ok()?;
+        let (_, proxy) = most_specific_domain(&self.file.domains, url.host_str()?)?;
+        Some(Some(proxy.as_str()).filter(|p| *p != DIRECT))
+    }
+
+    /// the proxy `source` uses for `url`, `None` to connect directly
+    pub fn proxy_for(&self, source: &str, url: &str) -> Option<&str> {
+        self.domain_proxy(url)
+            .unwrap_or_else(|| self.source_proxy(source))
+    }
+
+    /// an agent for a request of no source in particular, like a robots.txt: through the proxy
+    /// of the domain of `url`, or the top one
+    pub fn agent(&self, url: &str) -> Result<ureq::Agent> {
+        let proxy = self
+            .domain_proxy(url)
+            .unwrap_or_else(|| self.file.proxy.as_deref().filter(|p| *p != DIRECT));
+        let mut agent = ureq::AgentBuilder::new();
+        if let Some(proxy) = proxy {
+            agent = agent.proxy(ureq::Proxy::new(proxy)?);
+        }
+        Ok(agent.build())
+    }
+
+    /// the chrome flags routing a browser of `source`: the proxy of the source, and a PAC script
+    /// when some domains are routed differently
+    pub fn chrome_args(&self, source: &str) -> Vec<String> {
+        let proxy = self.source_proxy(source);
+        if self.file.domains.is_empty() {
+            return proxy
+                .map(|p| vec![format!("--proxy-server={p}")])
+                .unwrap_or_default();
+        }
+        let pac_proxy = |proxy: Option<&str>| match proxy.and_then(|p| parse_proxy(p).ok()) {
+            Some((scheme, addr)) => {
+                let kind = match scheme.as_str() {
+                    "socks4" => "SOCKS",
+                    "socks5" => "SOCKS5",
+                    _ => "PROXY",
+                };
+                format!("{kind} {addr}")
+            }
+            None => "DIRECT".into(),
+        };
+        // longest domains first, the most specific one wins
+        let mut domains: Vec<_> = self.file.domains.iter().collect();
+        domains.sort_by_key(|(d, _)| std::cmp::Reverse(d.len()));
+        let mut pac = String::from("function FindProxyForURL(url, host) {\n");
+        for (domain, proxy) in domains {
+            let proxy = Some(proxy.as_str()).filter(|p| *p != DIRECT);
+            pac += &format!(
+                "  if (host == \"{domain}\" || dnsDomainIs(host, \".{domain}\")) return \"{}\";\n",
+                pac_proxy(proxy)
+            );
+        }
+        pac += &format!("  return \"{}\";\n}}\n", pac_proxy(proxy));
+        vec![format!(
+            "--proxy-pac-url=data:application/x-ns-proxy-autoconfig;base64,{}",
+            STANDARD.encode(pac)
+        )]
+    }
+
+    /// the next user agent of the pool, `None` without one
+    pub fn user_agent(&self) -> Option<&str> {
+        if self.file.user_agents.is_empty() {
+            return None;
+        }
+        let i = self.next_agent.fetch_add(1, Ordering::Relaxed) % self.file.user_agents.len();
+        Some(&self.file.user_agents[i])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network() -> Network {
+        Network::new(
+            serde_json::from_str(
+                r#"{
+                    "proxy": "http://10.0.0.2:3128",
+                    "sources": { "quebec::*": "socks5://10.0.0.3:1080", "quebec::lapresse": "direct" },
+                    "domains": { "lemonde.fr": "direct", "www.lemonde.fr": "socks4://10.0.0.4:1080" },
+                    "user_agents": ["a", "b"]
+                }"#,
+            )
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn it_routes_by_domain_then_source() {
+        let network = network();
+        network.file.validate().unwrap();
+        let proxy = |source, url| network.proxy_for(source, url);
+        assert_eq!(
+            proxy("fr::google", "https://a.fr/1"),
+            Some("http://10.0.0.2:3128")
+        );
+        assert_eq!(
+            proxy("quebec::24heures", "https://a.ca/1"),
+            Some("socks5://10.0.0.3:1080")
+        );
+        assert_eq!(proxy("quebec::lapresse", "https://a.ca/1"), None);
+        assert_eq!(proxy("quebec::24heures", "https://lemonde.fr/a"), None);
+        assert_eq!(proxy("fr::google", "https://api.lemonde.fr/a"), None);
+        assert_eq!(
+            proxy("quebec::lapresse", "https://www.lemonde.fr/a"),
+            Some("socks4://10.0.0.4:1080")
+        );
+
+        assert_eq!(network.user_agent(), Some("a"));
+        assert_eq!(network.user_agent(), Some("b"));
+        assert_eq!(network.user_agent(), Some("a"));
+        assert_eq!(Network::new(NetworkFile::default()).user_agent(), None);
+
+        for invalid in [
+            r#"{ "proxy": "https://10.0.0.2:3128" }"#,
+            r#"{ "domains": { "a.fr": "10.0.0.2:3128" } }"#,
+            r#"{ "user_agents": [""] }"#,
+        ] {
+            let file: NetworkFile = serde_json::from_str(invalid).unwrap();
+            assert!(file.validate().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn it_gives_chrome_a_pac_script_for_the_domains() {
+        let plain = Network::new(NetworkFile {
+            proxy: Some("socks5://10.0.0.3:1080".into()),
+            ..Default::default()
+        });
+        assert_eq!(
+            plain.chrome_args("fr::google"),
+            ["--proxy-server=socks5://10.0.0.3:1080"]
+        );
+        assert!(Network::new(NetworkFile::default())
+            .chrome_args("fr::google")
+            .is_empty());
+
+        let args = network().chrome_args("quebec::24heures");
+        let (_, pac) = args[0].split_once("base64,").unwrap();
+        let pac = String::from_utf8(STANDARD.decode(pac).unwrap()).unwrap();
+        assert_eq!(
+            pac,
+            concat!(
+                "function FindProxyForURL(url, host) {\n",
+                "  if (host == \"www.lemonde.fr\" || dnsDomainIs(host, \".www.lemonde.fr\")) return \"SOCKS 10.0.0.4:1080\";\n",
+                "  if (host == \"lemonde.fr\" || dnsDomainIs(host, \".lemonde.fr\")) return \"DIRECT\";\n",
+                "  return \"SOCKS5 10.0.0.3:1080\";\n",
+                "}\n"
+            )
+        );
+    }
+}
@@ -0,0 +1,568 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use shared::config::load_json;
+use tracing::{debug, trace};
+use url::Url;
+
+use crate::network::{most_specific_domain, Network, DEFAULT_USER_AGENT};
+
+/// how long a robots.txt is trusted before being fetched again
+const ROBOTS_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// how long a host whose robots.txt couldn't be fetched is left alone before trying again
+const ROBOTS_RETRY: Duration = Duration::from_secs(10 * 60);
+
+/// politeness file, limiting the requests the fetcher sends to each host, whatever the source:
+/// ```json
+/// { "requests_per_second": 1, "max_in_flight": 2, "robots_txt": true,
+///   "domains": { "google.com": { "requests_per_second": 0.2, "robots_txt": false } } }
+/// ```
+/// `www.google.com` follows the rules of `google.com` unless it has its own, and falls back on
+/// the top settings for the ones they leave out.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PolitenessFile {
+    #[serde(default = "default_requests_per_second")]
+    pub requests_per_second: f64,
+    /// requests to a host at the same time, across every source
+    #[serde(default = "default_max_in_flight")]
+    pub max_in_flight: usize,
+    #[serde(default = "default_robots_txt")]
+    pub robots_txt: bool,
+    /// the name looked for in the `User-agent` lines of robots.txt
+    #[serde(default = "default_user_agent")]
+    pub user_agent: String,
+    #[serde(default)]
+    pub domains: BTreeMap<String, DomainRules>,
+}
+
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DomainRules {
+    pub requests_per_second: Option<f64>,
+    pub max_in_flight: Option<usize>,
+    pub robots_txt: Option<bool>,
+}
+
+fn default_requests_per_second() -> f64 {
+    1.0
+}
+fn default_max_in_flight() -> usize {
+    2
+}
+fn default_robots_txt() -> bool {
+    true
+}
+fn default_user_agent() -> String {
+    "news-scraper".into()
+}
+
+impl Default for PolitenessFile {
+    fn default() -> PolitenessFile {
+        PolitenessFile {
+            requests_per_second: default_requests_per_second(),
+            max_in_flight: default_max_in_flight(),
+            robots_txt: default_robots_txt(),
+            user_agent: default_user_agent(),
+            // the search results are the `fr::google` source itself
+            domains: BTreeMap::from([(
+                "google.com".into(),
+                DomainRules {
+                    robots_txt: Some(false),
+                    ..Default::default()
+                },
+            )]),
+        }
+    }
+}
+
+/// the rules of a host, with the defaults of the file filled in
+#[derive(Debug, PartialEq)]
+struct Rules {
+    interval: Duration,
+    max_in_flight: usize,
+    robots_txt: bool,
+}
+
+impl PolitenessFile {
+    pub fn load(path: impl AsRef<Path>) -> Result<PolitenessFile> {
+        let path = path.as_ref();
+        let file: PolitenessFile = load_json(path)?;
+        file.validate().with_context(|| format!("in {path:?}"))?;
+        Ok(file)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let domains = self.domains.iter().map(|(d, r)| (d.as_str(), r));
+        for (name, rules) in [("top", &DomainRules::default())]
+            .into_iter()
+            .chain(domains)
+        {
+            let rps = rules
+                .requests_per_second
+                .unwrap_or(self.requests_per_second);
+            if !(rps.is_finite() && rps > 0.0) {
+                bail!("{name}: requests_per_second must be above 0");
+            }
+            if rules.max_in_flight.unwrap_or(self.max_in_flight) == 0 {
+                bail!("{name}: max_in_flight can't be 0");
+            }
+        }
+        Ok(())
+    }
+
+    fn rules_for(&self, host: &str) -> Rules {
+        let domain = most_specific_domain(&self.domains, host)
+            .map(|(_, rules)| rules.clone())
+            .unwrap_or_default();
+        let rps = domain
+            .requests_per_second
+            .unwrap_or(self.requests_per_second);
+        Rules {
+            interval: Duration::from_secs_f64(1.0 / rps),
+            max_in_flight: domain.max_in_flight.unwrap_or(self.max_in_flight),
+            robots_txt: domain.robots_txt.unwrap_or(self.robots_txt),
+        }
+    }
+}
+
+/// the rules of a robots.txt for one user agent
+#[derive(Debug, Default)]
+pub struct Robots {
+    /// `(allow, pattern)`
+    rules: Vec<(bool, String)>,
+    crawl_delay: Option<Duration>,
+}
+
+impl Robots {
+    fn disallow_all() -> Robots {
+        Robots {
+            rules: vec![(false, "/".into())],
+            crawl_delay: None,
+        }
+    }
+
+    /// keep the group of `agent`, or the `*` one when no group names it
+    pub fn parse(content: &str, agent: &str) -> Robots {
+        let agent = agent.to_lowercase();
+        // the length of the matched agent name, 0 for `*`
+        let mut best: Option<usize> = None;
+        let mut robots = Robots::default();
+        let mut group_agents: Vec<String> = Vec::new();
+        let mut in_rules = false;
+        let mut matched: Option<usize> = None;
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let (key, value) = (key.trim().to_lowercase(), value.trim());
+            if key == "user-agent" {
+                // a user-agent line after rules starts another group
+                if in_rules {
+                    group_agents.clear();
+                    in_rules = false;
+                }
+                group_agents.push(value.to_lowercase());
+                matched = group_agents
+                    .iter()
+                    .filter_map(|a| match a.as_str() {
+                        "*" => Some(0),
+                        a if agent.contains(a) => Some(a.len()),
+                        _ => None,
+                    })
+                    .max();
+                continue;
+            }
+            in_rules = true;
+            let Some(score) = matched else {
+                continue;
+            };
+            match best {
+                Some(b) if b > score => continue,
+                // a better group replaces what a worse one set
+                Some(b) if b < score => robots = Robots::default(),
+                _ => (),
+            }
+            best = Some(score);
+            match key.as_str() {
+                "allow" | "disallow" if !value.is_empty() => {
+                    robots.rules.push((key == "allow", value.to_string()));
+                }
+                "crawl-delay" => {
+                    robots.crawl_delay = value.parse::<f64>().ok().and_then(|secs| {
+                        (secs.is_finite() && secs >= 0.0).then(|| Duration::from_secs_f64(secs))
+                    });
+                }
+                _ => (),
+            }
+        }
+        robots
+    }
+
+    /// `path` being the path and the query of the url. the longest matching rule wins, allow on
+    /// a tie
+    pub fn allowed(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|(_, pattern)| matches(pattern, path))
+            .max_by_key(|(allow, pattern)| (pattern.len(), *allow))
+            .is_none_or(|(allow, _)| *allow)
+    }
+}
+
+/// robots.txt patterns: `*` matches anything, a final `$` the end of the path
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = path.strip_prefix(parts.next().unwrap_or_default()) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return !anchored || rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    if anchored {
+        rest.ends_with(last)
+    } else {
+        rest.contains(last)
+    }
+}
+
+#[derive(Default)]
+struct Host {
+    /// when the next request may start
+    next: Option<Instant>,
+    in_flight: usize,
+}
+
+/// paces the requests to each host, shared by the sources threads
+pub struct Politeness {
+    file: PolitenessFile,
+    hosts: Mutex<HashMap<String, Host>>,
+    freed: Condvar,
+    /// by origin, with when they expire
+    robots: Mutex<HashMap<String, (Instant, Arc<Robots>)>>,
+    /// routes the robots.txt requests, directly without it
+    network: Option<&'static Network>,
+}
+
+impl Politeness {
+    pub fn new(file: PolitenessFile) -> Politeness {
+        Politeness {
+            file,
+            hosts: Mutex::default(),
+            freed: Condvar::new(),
+            robots: Mutex::default(),
+            network: None,
+        }
+    }
+
+    pub fn with_network(mut self, network: &'static Network) -> Politeness {
+        self.network = Some(network);
+        self
+    }
+
+    /// wait until `url` can be requested: robots.txt allows it, its host has less than
+    /// `max_in_flight` requests going, and the previous one started long enough ago.
+    /// the request counts as in flight until the permit is dropped
+    pub fn acquire(&self, url: &str) -> Result<Permit<'_>> {
+        let url = Url::parse(url).with_context(|| format!("invalid url `{url}`"))?;
+        let Some(host) = url.host_str() else {
+            bail!("no host in `{url}`");
+        };
+        let rules = self.file.rules_for(host);
+        let mut interval = rules.interval;
+        if rules.robots_txt {
+            let robots = self.robots(&url);
+            let path = match url.query() {
+                Some(query) => format!("{}?{query}", url.path()),
+                None => url.path().to_string(),
+            };
+            if !robots.allowed(&path) {
+                bail!("{url} is disallowed by robots.txt");
+            }
+            interval = interval.max(robots.crawl_delay.unwrap_or_default());
+        }
+
+        let mut hosts = self.hosts.lock().unwrap();
+        while hosts
+            .get(host)
+            .is_some_and(|h| h.in_flight >= rules.max_in_flight)
+        {
+            hosts = self.freed.wait(hosts).unwrap();
+        }
+        let state = hosts.entry(host.to_string()).or_default();
+        state.in_flight += 1;
+        let now = Instant::now();
+        let start = state.next.map_or(now, |next| next.max(now));
+        state.next = Some(start + interval);
+        drop(hosts);
+
+        let wait = start - now;
+        if !wait.is_zero() {
+            trace!(host, ?wait, "waiting before requesting");
+            thread::sleep(wait);
+        }
+        Ok(Permit {
+            politeness: self,
+            host: host.to_string(),
+        })
+    }
+
+    /// the robots.txt of the origin of `url`, fetched once a day through the proxy and with a
+    /// user agent of `NETWORK_FILE`. like RFC 9309 says, a missing one (4xx) allows everything,
+    /// and one that can't be fetched (5xx, network error) disallows everything, until it's tried
+    /// again after [`ROBOTS_RETRY`]
+    fn robots(&self, url: &Url) -> Arc<Robots> {
+        let origin = url.origin().ascii_serialization();
+        if let Some((expires, robots)) = self.robots.lock().unwrap().get(&origin) {
+            if Instant::now() < *expires {
+                return robots.clone();
+            }
+        }
+        let robots_url = format!("{origin}/robots.txt");
+        let response = match self.network {
+            Some(network) => network.agent(&robots_url),
+            None => Ok(ureq::agent()),
+        }
+        .and_then(|agent| {
+            let user_agent = self
+                .network
+                .and_then(Network::user_agent)
+                .unwrap_or(DEFAULT_USER_AGENT);
+            let response = agent
+                .get(&robots_url)
+                .timeout(Duration::from_secs(10))
+                .set("User-Agent", user_agent)
+                .call();
+            match response {
+                Ok(response) => Ok(Ok(response.into_string()?)),
+                Err(ureq::Error::Status(code, _)) if (400..500).contains(&code) => Ok(Err(code)),
+                Err(e) => Err(e.into()),
+            }
+        });
+        let (ttl, robots) = match response {
+            Ok(Ok(content)) => (ROBOTS_TTL, Robots::parse(&content, &self.file.user_agent)),
+            Ok(Err(code)) => {
+                debug!(%robots_url, code, "no robots.txt, allowing everything");
+                (ROBOTS_TTL, Robots::default())
+            }
+            Err(e) => {
+                debug!(%robots_url, error = %format!("{e:#}"), "robots.txt failed, disallowing everything for now");
+                (ROBOTS_RETRY, Robots::disallow_all())
+            }
+        };
+        let robots = Arc::new(robots);
+        self.robots
+            .lock()
+            .unwrap()
+            .insert(origin, (Instant::now() + ttl, robots.clone()));
+        robots
+    }
+}
+
+/// a request in flight to a host
+pub struct Permit<'a> {
+    politeness: &'a Politeness,
+    host: String,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let mut hosts = self.politeness.hosts.lock().unwrap();
+        if let Some(host) = hosts.get_mut(&self.host) {
+            host.in_flight -= 1;
+        }
+        self.politeness.freed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    #[test]
+    fn it_reads_the_group_of_the_agent() {
+        let content = "
+            User-agent: *
+            Disallow: /private
+            Crawl-delay: 5
+
+            # us
+            User-agent: googlebot
+            User-agent: news-scraper
+            Disallow: /*.pdf$
+            Disallow: /search
+            Allow: /search/about
+            Crawl-delay: 0.5
+
+            User-agent: other
+            Disallow: /
+        ";
+        let robots = Robots::parse(content, "news-scraper/1.0");
+        assert_eq!(robots.crawl_delay, Some(Duration::from_millis(500)));
+        assert!(robots.allowed("/private"));
+        assert!(!robots.allowed("/search?q=joie"));
+        assert!(robots.allowed("/search/about"));
+        assert!(!robots.allowed("/files/a.pdf"));
+        assert!(robots.allowed("/files/a.pdf?page=2"));
+
+        let robots = Robots::parse(content, "somebot");
+        assert!(!robots.allowed("/private/a"));
+        assert!(robots.allowed("/search"));
+        assert_eq!(robots.crawl_delay, Some(Duration::from_secs(5)));
+        assert!(Robots::parse("", "somebot").allowed("/"));
+    }
+
+    #[test]
+    fn it_picks_the_most_specific_domain() {
+        let file: PolitenessFile = serde_json::from_str(
+            r#"{ "requests_per_second": 2, "domains": {
+                "lemonde.fr": { "requests_per_second": 0.5 },
+                "www.lemonde.fr": { "max_in_flight": 1 }
+            } }"#,
+        )
+        .unwrap();
+        file.validate().unwrap();
+        assert_eq!(
+            file.rules_for("www.lemonde.fr"),
+            Rules {
+                interval: Duration::from_millis(500),
+                max_in_flight: 1,
+                robots_txt: true
+            }
+        );
+        assert_eq!(
+            file.rules_for("api.lemonde.fr").interval,
+            Duration::from_secs(2)
+        );
+        assert_eq!(
+            file.rules_for("notlemonde.fr").interval,
+            Duration::from_millis(500)
+        );
+        assert!(
+            !PolitenessFile::default()
+                .rules_for("www.google.com")
+                .robots_txt
+        );
+
+        let zero: PolitenessFile =
+            serde_json::from_str(r#"{ "domains": { "a.fr": { "requests_per_second": 0 } } }"#)
+                .unwrap();
+        assert!(zero.validate().is_err());
+    }
+
+    #[test]
+    fn it_paces_and_caps_the_requests_of_a_host() {
+        let politeness = Arc::new(Politeness::new(PolitenessFile {
+            requests_per_second: 20.0,
+            max_in_flight: 1,
+            robots_txt: false,
+            ..Default::default()
+        }));
+        let started = Instant::now();
+        let first = politeness.acquire("https://a.fr/1").unwrap();
+        // another host isn't slowed down
+        drop(politeness.acquire("https://b.fr/1").unwrap());
+        assert!(started.elapsed() < Duration::from_millis(40));
+
+        let other = politeness.clone();
+        let second = thread::spawn(move || {
+            drop(other.acquire("https://a.fr/2").unwrap());
+            Instant::now()
+        });
+        thread::sleep(Duration::from_millis(150));
+        let released = Instant::now();
+        drop(first);
+        // it waited for the first request to finish
+        assert!(second.join().unwrap() >= released);
+        let third = Instant::now();
+        drop(politeness.acquire("https://a.fr/3").unwrap());
+        assert!(third.elapsed() >= Duration::from_millis(40));
+    }
+
+    /// answers one request with `status` and `body`
+    fn serve_once(status: &'static str, body: &'static str) -> (String, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let served = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let read = stream.read(&mut [0; 1024]).unwrap();
+            assert!(read > 0);
+            write!(
+                stream,
+                "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+        });
+        (addr.to_string(), served)
+    }
+
+    #[test]
+    fn it_follows_robots_txt() {
+        let (addr, served) = serve_once("200 OK", "User-agent: *\nDisallow: /admin\n");
+        let politeness = Politeness::new(PolitenessFile {
+            requests_per_second: 1000.0,
+            ..Default::default()
+        });
+        let err = politeness
+            .acquire(&format!("http://{addr}/admin/users"))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("robots.txt"));
+        served.join().unwrap();
+        // the second check uses the cached robots.txt, nothing listens anymore
+        drop(
+            politeness
+                .acquire(&format!("http://{addr}/news/1"))
+                .unwrap(),
+        );
+    }
+
+    #[test]
+    fn it_disallows_everything_when_robots_txt_fails() {
+        let politeness = Politeness::new(PolitenessFile {
+            requests_per_second: 1000.0,
+            ..Default::default()
+        });
+        let (addr, served) = serve_once("503 Service Unavailable", "");
+        let err = politeness
+            .acquire(&format!("http://{addr}/news/1"))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("robots.txt"));
+        served.join().unwrap();
+
+        // a missing one allows everything
+        let (addr, served) = serve_once("404 Not Found", "");
+        drop(
+            politeness
+                .acquire(&format!("http://{addr}/news/1"))
+                .unwrap(),
+        );
+        served.join().unwrap();
+    }
+}
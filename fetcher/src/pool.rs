@@ -0,0 +1,362 @@
+use std::{
+    ffi::OsStr,
+    sync::{Arc, Condvar, Mutex},
+    time::Duration,
+};
+
+use anyhow::{Context as _, Result};
+use headless_chrome::{
+    browser::{context::Context, GetVersionReturnObject},
+    Browser, LaunchOptionsBuilder, Tab,
+};
+use shared::metrics;
+use tracing::{debug, info, warn};
+use tungstenite::Message;
+
+/// `args` are added to the chrome flags, like the proxy of the source
+fn new_browser(headless: bool, args: &[String]) -> Result<Browser> {
+    metrics::BROWSERS_SPAWNED.inc();
+    let mut flags = vec![OsStr::new("--blink-settings=imagesEnabled=false")];
+    flags.extend(args.iter().map(OsStr::new));
+    let options = LaunchOptionsBuilder::default()
+        .window_size(Some((1920, 1080)))
+        .headless(headless)
+        .devtools(false)
+        .args(flags)
+        .idle_browser_timeout(Duration::from_secs(120))
+        .sandbox(false)
+        .build()?;
+    Browser::new(options).context("starting chrome")
+}
+
+struct Entry<B> {
+    id: usize,
+    /// the flags it was started with, a source only gets a browser started with its own
+    args: Vec<String>,
+    browser: B,
+    /// leases given since it started
+    uses: usize,
+    leased: usize,
+}
+
+struct State<B> {
+    entries: Vec<Entry<B>>,
+    /// browsers being started, counted in the size of the pool
+    starting: usize,
+    next_id: usize,
+}
+
+/// at most `size` warm browsers shared by the sources. a browser is recycled after `max_uses`
+/// leases, or when it stopped answering
+pub struct Pool<B> {
+    size: usize,
+    max_uses: usize,
+    state: Mutex<State<B>>,
+    freed: Condvar,
+    start: Box<StartFn<B>>,
+    alive: Box<AliveFn<B>>,
+}
+
+/// starts a browser with these flags
+type StartFn<B> = dyn Fn(&[String]) -> Result<B> + Send + Sync;
+type AliveFn<B> = dyn Fn(&B) -> bool + Send + Sync;
+
+impl<B: Clone> Pool<B> {
+    pub fn new(
+        size: usize,
+        max_uses: usize,
+        start: impl Fn(&[String]) -> Result<B> + Send + Sync + 'static,
+        alive: impl Fn(&B) -> bool + Send + Sync + 'static,
+    ) -> Pool<B> {
+        Pool {
+            size: size.max(1),
+            max_uses: max_uses.max(1),
+            state: Mutex::new(State {
+                entries: Vec::new(),
+                starting: 0,
+                next_id: 0,
+            }),
+            freed: Condvar::new(),
+            start: Box::new(start),
+            alive: Box::new(alive),
+        }
+    }
+
+    /// a browser started with `args`: the least busy warm one, a new one while the pool isn't
+    /// full, or one replacing an idle browser that can't be used. waits for a lease to be
+    /// returned otherwise
+    fn acquire(&self, args: &[String]) -> Result<(usize, B)> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let candidate = state
+                .entries
+                .iter()
+                .enumerate()
+                .filter(|(_, e)| e.args == args && e.uses < self.max_uses)
+                .min_by_key(|(_, e)| e.leased)
+                .map(|(i, _)| i);
+            if let Some(i) = candidate {
+                let entry = &mut state.entries[i];
+                // the browser stops listening after `idle_browser_timeout`, so it may be dead
+                if entry.leased == 0 && !(self.alive)(&entry.browser) {
+                    debug!("dropping dead pooled browser");
+                    metrics::BROWSERS_RECYCLED
+                        .with_label_values(&["dead"])
+                        .inc();
+                    state.entries.remove(i);
+                    continue;
+                }
+                entry.uses += 1;
+                entry.leased += 1;
+                metrics::BROWSERS_REUSED.inc();
+                let leased = (entry.id, entry.browser.clone());
+                self.report(&state);
+                return Ok(leased);
+            }
+            if state.entries.len() + state.starting >= self.size {
+                let idle = state.entries.iter().position(|e| e.leased == 0);
+                match idle {
+                    Some(i) => {
+                        let reason = match state.entries[i].uses >= self.max_uses {
+                            true => "uses",
+                            false => "args",
+                        };
+                        metrics::BROWSERS_RECYCLED
+                            .with_label_values(&[reason])
+                            .inc();
+                        state.entries.remove(i);
+                    }
+                    None => {
+                        state = self.freed.wait(state).unwrap();
+                        continue;
+                    }
+                }
+            }
+            state.starting += 1;
+            drop(state);
+            let started = (self.start)(args);
+            state = self.state.lock().unwrap();
+            state.starting -= 1;
+            let browser = match started {
+                Ok(browser) => browser,
+                Err(e) => {
+                    self.freed.notify_all();
+                    return Err(e);
+                }
+            };
+            let id = state.next_id;
+            state.next_id += 1;
+            state.entries.push(Entry {
+                id,
+                args: args.to_vec(),
+                browser: browser.clone(),
+                uses: 1,
+                leased: 1,
+            });
+            self.report(&state);
+            return Ok((id, browser));
+        }
+    }
+
+    fn release(&self, id: usize) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(i) = state.entries.iter().position(|e| e.id == id) {
+            let entry = &mut state.entries[i];
+            entry.leased -= 1;
+            if entry.leased == 0 {
+                if entry.uses >= self.max_uses {
+                    metrics::BROWSERS_RECYCLED
+                        .with_label_values(&["uses"])
+                        .inc();
+                    state.entries.remove(i);
+                } else if !(self.alive)(&entry.browser) {
+                    warn!("pooled browser crashed");
+                    metrics::BROWSERS_RECYCLED
+                        .with_label_values(&["dead"])
+                        .inc();
+                    state.entries.remove(i);
+                }
+            }
+        }
+        self.report(&state);
+        self.freed.notify_all();
+    }
+
+    fn report(&self, state: &State<B>) {
+        metrics::BROWSER_POOL_SIZE.set(state.entries.len() as i64);
+        metrics::BROWSER_POOL_LEASED.set(state.entries.iter().map(|e| e.leased as i64).sum());
+    }
+
+    /// drop the browsers nobody uses
+    pub fn close_idle(&self) {
+        let mut state = self.state.lock().unwrap();
+        let before = state.entries.len();
+        state.entries.retain(|e| e.leased > 0);
+        info!(
+            closed = before - state.entries.len(),
+            "closing the idle browsers"
+        );
+        self.report(&state);
+    }
+}
+
+impl Pool<Browser> {
+    pub fn browsers(size: usize, max_uses: usize, headless: bool) -> Pool<Browser> {
+        Pool::new(
+            size,
+            max_uses,
+            move |args| new_browser(headless, args),
+            |browser| browser.get_version().is_ok(),
+        )
+    }
+
+    /// a browser context of its own on a pooled browser, as isolated as an incognito window
+    pub fn lease(self: &Arc<Self>, args: &[String]) -> Result<Lease> {
+        let (id, browser) = self.acquire(args)?;
+        let context_id = match browser.new_context() {
+            Ok(context) => context.get_id().to_string(),
+            Err(e) => {
+                self.release(id);
+                return Err(e.context("creating a browser context"));
+            }
+        };
+        Ok(Lease {
+            pool: self.clone(),
+            id,
+            browser,
+            context_id,
+        })
+    }
+}
+
+/// `Target.disposeBrowserContext`, which only the browser's own session may send and
+/// headless_chrome keeps private: sent on a connection of our own to the browser
+fn dispose_context(browser: &Browser, context_id: &str) -> Result<()> {
+    let (mut socket, _) = tungstenite::connect(browser.get_ws_url())?;
+    let call = serde_json::json!({
+        "id": 1,
+        "method": "Target.disposeBrowserContext",
+        "params": { "browserContextId": context_id },
+    });
+    socket.send(Message::Text(call.to_string()))?;
+    loop {
+        let Message::Text(text) = socket.read()? else {
+            continue;
+        };
+        let answer: serde_json::Value = serde_json::from_str(&text)?;
+        if answer["id"] != 1 {
+            continue;
+        }
+        if let Some(error) = answer.get("error") {
+            anyhow::bail!("{error}");
+        }
+        return Ok(());
+    }
+}
+
+/// a source's part of a pooled browser. its tabs and its context are closed and the browser goes
+/// back to the pool when it's dropped
+pub struct Lease {
+    pool: Arc<Pool<Browser>>,
+    id: usize,
+    browser: Browser,
+    context_id: String,
+}
+
+impl Lease {
+    /// a tab in the browser context of the lease, sharing no cookies with the other sources
+    pub fn new_tab(&self) -> Result<Arc<Tab>> {
+        Context::new(&self.browser, self.context_id.clone()).new_tab()
+    }
+
+    pub fn get_version(&self) -> Result<GetVersionReturnObject> {
+        self.browser.get_version()
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        let context = Context::new(&self.browser, self.context_id.clone());
+        for tab in context.get_tabs().unwrap_or_default() {
+            if let Err(e) = tab.close(false) {
+                debug!(error = %e, "closing a tab of a lease");
+            }
+        }
+        // or the contexts pile up in the warm browser until it's recycled
+        if let Err(e) = dispose_context(&self.browser, &self.context_id) {
+            debug!(error = %format!("{e:#}"), "closing the context of a lease");
+        }
+        self.pool.release(self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    };
+
+    use super::*;
+
+    /// browsers are numbers, the even ones are dead
+    fn pool(size: usize, max_uses: usize) -> (Arc<Pool<usize>>, Arc<AtomicUsize>) {
+        let started = Arc::new(AtomicUsize::new(0));
+        let counter = started.clone();
+        let pool = Pool::new(
+            size,
+            max_uses,
+            move |_| Ok(counter.fetch_add(1, Ordering::Relaxed) * 2 + 1),
+            |b| b % 2 == 1,
+        );
+        (Arc::new(pool), started)
+    }
+
+    #[test]
+    fn it_shares_and_recycles_browsers() {
+        let (pool, started) = pool(2, 3);
+        let none: &[String] = &[];
+        let (a, first) = pool.acquire(none).unwrap();
+        // a second source shares the warm browser
+        let (b, second) = pool.acquire(none).unwrap();
+        assert_eq!((a, first), (b, second));
+        pool.release(a);
+        pool.release(b);
+        let (c, _) = pool.acquire(none).unwrap();
+        assert_eq!(c, a);
+        // used 3 times, it's recycled once returned
+        pool.release(c);
+        let (d, browser) = pool.acquire(none).unwrap();
+        assert_ne!(d, a);
+        assert_eq!(browser, 3);
+        assert_eq!(started.load(Ordering::Relaxed), 2);
+
+        // another proxy gets its own browser, then replaces an idle one when the pool is full
+        let proxy = ["--proxy-server=socks5://10.0.0.3:1080".to_string()];
+        let (e, _) = pool.acquire(&proxy).unwrap();
+        assert_ne!(e, d);
+        pool.release(e);
+        pool.release(d);
+        let (f, _) = pool.acquire(&["--other".to_string()]).unwrap();
+        assert!(![d, e].contains(&f));
+        assert_eq!(pool.state.lock().unwrap().entries.len(), 2);
+    }
+
+    #[test]
+    fn it_drops_dead_browsers_and_waits_when_full() {
+        let dead = Pool::new(1, 10, |_| Ok(2), |b| b % 2 == 1);
+        let (a, _) = dead.acquire(&[]).unwrap();
+        dead.release(a);
+        assert!(dead.state.lock().unwrap().entries.is_empty());
+
+        let (pool, _) = pool(1, 10);
+        let (a, _) = pool.acquire(&[]).unwrap();
+        let other = pool.clone();
+        let waiting = thread::spawn(move || other.acquire(&["--other".to_string()]).unwrap());
+        thread::sleep(Duration::from_millis(100));
+        assert!(!waiting.is_finished());
+        pool.release(a);
+        let (b, _) = waiting.join().unwrap();
+        assert_ne!(a, b);
+    }
+}
@@ -0,0 +1,178 @@
+use std::{collections::BTreeMap, path::Path, str::FromStr, time::Duration};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local};
+use serde::Deserialize;
+use shared::config::load_json;
+
+/// what the crontab of the docker image used to do
+const DEFAULT_SCHEDULE: &str = "0 7,9,13,16,18 * * *";
+
+/// schedule file of the daemon mode:
+/// ```json
+/// { "default": "0 7,9,13,16,18 * * *", "sources": { "fr::*": "1h", "lme::beetravel": "1d" } }
+/// ```
+/// values are either a cron expression (5 fields, or 6 with seconds first) or a duration like `1h 30m`.
+/// a source name takes precedence over a `region::*` pattern.
+#[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleFile {
+    pub default: Option<String>,
+    #[serde(default)]
+    pub sources: BTreeMap<String, String>,
+}
+
+impl ScheduleFile {
+    pub fn load(path: impl AsRef<Path>) -> Result<ScheduleFile> {
+        load_json(path)
+    }
+
+    fn every_for(&self, source: &str) -> &str {
+        let region = source.split_once("::").map(|(r, _)| format!("{r}::*"));
+        self.sources
+            .get(source)
+            .or_else(|| region.and_then(|r| self.sources.get(&r)))
+            .or(self.default.as_ref())
+            .map_or(DEFAULT_SCHEDULE, String::as_str)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Every {
+    Cron(Box<cron::Schedule>),
+    Interval(Duration),
+}
+
+impl FromStr for Every {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        if let Ok(interval) = humantime::parse_duration(s) {
+            if interval.is_zero() {
+                bail!("interval can't be zero");
+            }
+            return Ok(Every::Interval(interval));
+        }
+        // the cron crate wants the seconds first
+        let expr = match s.split_whitespace().count() {
+            5 => format!("0 {s}"),
+            _ => s.to_string(),
+        };
+        let schedule = cron::Schedule::from_str(&expr)
+            .with_context(|| format!("`{s}` is neither a duration nor a cron expression"))?;
+        Ok(Every::Cron(Box::new(schedule)))
+    }
+}
+
+impl Every {
+    /// intervals run right away, then every interval. cron runs at the next matching time.
+    fn first(&self, now: DateTime<Local>) -> DateTime<Local> {
+        match self {
+            Every::Interval(_) => now,
+            Every::Cron(_) => self.next(now),
+        }
+    }
+    fn next(&self, after: DateTime<Local>) -> DateTime<Local> {
+        match self {
+            Every::Interval(interval) => after + *interval,
+            Every::Cron(schedule) => schedule
+                .after(&after)
+                .next()
+                .unwrap_or(DateTime::<Local>::MAX_UTC.into()),
+        }
+    }
+}
+
+struct Entry {
+    source: &'static str,
+    every: Every,
+    next: DateTime<Local>,
+}
+
+pub struct Scheduler {
+    entries: Vec<Entry>,
+}
+
+impl Scheduler {
+    pub fn new(
+        file: &ScheduleFile,
+        sources: impl IntoIterator<Item = &'static str>,
+        now: DateTime<Local>,
+    ) -> Result<Scheduler> {
+        let entries = sources
+            .into_iter()
+            .map(|source| {
+                let every: Every = file.every_for(source).parse().context(source)?;
+                Ok(Entry {
+                    source,
+                    next: every.first(now),
+                    every,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Scheduler { entries })
+    }
+
+    /// sources that should run now. they are rescheduled from `now`, so runs missed while the
+    /// previous one was still going are merged into one.
+    pub fn due(&mut self, now: DateTime<Local>) -> Vec<&'static str> {
+        self.entries
+            .iter_mut()
+            .filter(|e| e.next <= now)
+            .map(|e| {
+                e.next = e.every.next(now);
+                e.source
+            })
+            .collect()
+    }
+
+    pub fn next_run(&self) -> Option<DateTime<Local>> {
+        self.entries.iter().map(|e| e.next).min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn it_picks_the_most_specific_schedule() {
+        let file: ScheduleFile = serde_json::from_str(
+            r#"{ "default": "1d", "sources": { "fr::*": "1h", "fr::google": "30m" } }"#,
+        )
+        .unwrap();
+        assert_eq!(file.every_for("fr::google"), "30m");
+        assert_eq!(file.every_for("fr::sudouest"), "1h");
+        assert_eq!(file.every_for("lme::geo"), "1d");
+        assert_eq!(
+            ScheduleFile::default().every_for("lme::geo"),
+            DEFAULT_SCHEDULE
+        );
+    }
+
+    #[test]
+    fn it_runs_due_sources() {
+        let file: ScheduleFile =
+            serde_json::from_str(r#"{ "default": "0 7,18 * * *", "sources": { "fr::*": "1h" } }"#)
+                .unwrap();
+        let start = Local.with_ymd_and_hms(2024, 1, 1, 6, 30, 0).unwrap();
+        let mut scheduler = Scheduler::new(&file, ["fr::google", "lme::geo"], start).unwrap();
+        assert_eq!(scheduler.due(start), vec!["fr::google"]);
+        assert!(scheduler.due(start).is_empty());
+        assert_eq!(
+            scheduler.next_run(),
+            Some(Local.with_ymd_and_hms(2024, 1, 1, 7, 0, 0).unwrap())
+        );
+        let at_seven = Local.with_ymd_and_hms(2024, 1, 1, 7, 0, 0).unwrap();
+        assert_eq!(scheduler.due(at_seven), vec!["lme::geo"]);
+        let later = Local.with_ymd_and_hms(2024, 1, 1, 7, 31, 0).unwrap();
+        assert_eq!(scheduler.due(later), vec!["fr::google"]);
+    }
+
+    #[test]
+    fn it_rejects_invalid_schedules() {
+        assert!("every day".parse::<Every>().is_err());
+        assert!("0s".parse::<Every>().is_err());
+        assert!("*/15 * * * *".parse::<Every>().is_ok());
+    }
+}
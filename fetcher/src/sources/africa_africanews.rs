@@ -1,7 +1,7 @@
 use super::{GetNewsOpts, News};
 use anyhow::{bail, Context, Result};
 use headless_chrome::Tab;
-use log::{error, info};
+use tracing::{error, info};
 use std::sync::Arc;
 
 fn get_articles_links(tab: &Arc<Tab>) -> Result<Vec<String>> {
@@ -20,11 +20,8 @@ fn get_articles_links(tab: &Arc<Tab>) -> Result<Vec<String>> {
 pub fn get_news(opts: GetNewsOpts) -> Result<()> {
     let tab = opts.browser.new_tab()?;
     tab.set_default_timeout(std::time::Duration::from_secs(120));
-    let user_agent = opts.browser.get_version()?.user_agent;
-    let user_agent = user_agent.replace("HeadlessChrome", "Chrome");
-    tab.set_user_agent(&user_agent, None, None)?;
-    tab.navigate_to("https://fr.africanews.com/embed/timeline/")
-        .context("navigate_to")?;
+    opts.set_user_agent(&tab)?;
+    super::navigate(&tab, "https://fr.africanews.com/embed/timeline/").context("navigate_to")?;
     tab.wait_until_navigated().context("wait_until_navigated")?;
 
     for _ in 0..3 {
@@ -34,28 +31,32 @@ pub fn get_news(opts: GetNewsOpts) -> Result<()> {
     }
 
     let links = get_articles_links(&tab)?;
-    info!("found {} articles", links.len());
+    info!(articles = links.len(), "found articles");
     if links.is_empty() {
         bail!("no links found");
     }
 
     for url in links {
+        if opts.is_cancelled() {
+            return Ok(());
+        }
         if opts.is_seen(&url) {
             continue;
         }
-        let res = super::fetch_article(&url);
+        let res = opts.fetch_article(&url);
         let payload = match res {
             Ok(res) => Ok(News {
                 title: res.title,
                 caption: res.description,
                 provider: opts.provider.clone(),
-date: res.published,
+                date: res.published,
                 body: res.content,
+                accessible_for_free: res.accessible_for_free,
                 link: url,
                 ..Default::default()
             }),
             Err(err) => {
-                error!("fetch_article on {url}: {err:?}");
+                error!(link = %url, error = %format!("{err:#}"), "fetch_article failed");
                 continue;
             }
         };
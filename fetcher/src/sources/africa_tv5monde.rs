@@ -1,7 +1,7 @@
 use super::{GetNewsOpts, News};
 use anyhow::{bail, Context, Result};
 use headless_chrome::Tab;
-use log::{error, info};
+use tracing::{error, info};
 use std::{sync::Arc, thread, time::Duration};
 
 fn get_articles_links(tab: &Arc<Tab>) -> Result<Vec<String>> {
@@ -20,18 +20,13 @@ fn get_articles_links(tab: &Arc<Tab>) -> Result<Vec<String>> {
 pub fn get_news(opts: GetNewsOpts) -> Result<()> {
     let tab = opts.browser.new_tab()?;
     tab.set_default_timeout(std::time::Duration::from_secs(120));
-    let user_agent = opts.browser.get_version()?.user_agent;
-    let user_agent = user_agent.replace("HeadlessChrome", "Chrome");
-    tab.set_user_agent(&user_agent, None, None)?;
-    tab.navigate_to("https://information.tv5monde.com/afrique")
+    opts.set_user_agent(&tab)?;
+    super::navigate(&tab, "https://information.tv5monde.com/afrique")
         .context("navigate_to")?
         .wait_until_navigated()
         .context("wait_until_navigated")?;
 
-    if let Ok(cookie) = tab.find_element("#didomi-notice-disagree-button") {
-        cookie.click().context("clicking on cookie")?;
-        thread::sleep(Duration::from_secs(3));
-    }
+    super::dismiss_consent(&tab)?;
 
     for _ in 0..3 {
         tab.wait_for_element(".pager_more")
@@ -41,16 +36,19 @@ pub fn get_news(opts: GetNewsOpts) -> Result<()> {
     }
 
     let links = get_articles_links(&tab)?;
-    info!("found {} articles", links.len());
+    info!(articles = links.len(), "found articles");
     if links.is_empty() {
         bail!("no links found");
     }
 
     for url in links {
+        if opts.is_cancelled() {
+            return Ok(());
+        }
         if opts.is_seen(&url) {
             continue;
         }
-        tab.navigate_to(&url)
+        super::navigate(&tab, &url)
             .context("navigate_to article")?
             .wait_until_navigated()
             .context("wait_until_navigated article")?;
@@ -64,11 +62,12 @@ pub fn get_news(opts: GetNewsOpts) -> Result<()> {
                 provider: opts.provider.clone(),
                 date: res.published,
                 body: res.content,
+                accessible_for_free: res.accessible_for_free,
                 link: url,
                 ..Default::default()
             }),
             Err(err) => {
-                error!("parse_article on {url}: {err:?}");
+                error!(link = %url, error = %format!("{err:#}"), "parse_article failed");
                 continue;
             }
         };
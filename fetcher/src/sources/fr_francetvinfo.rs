@@ -2,7 +2,7 @@ use super::{GetNewsOpts, News};
 use anyhow::bail;
 use anyhow::{Context, Result};
 use headless_chrome::Tab;
-use log::{error, trace};
+use tracing::{error, trace, warn};
 use std::sync::Arc;
 
 const CATEGORIES: &[&str] = &[
@@ -46,30 +46,29 @@ fn get_articles_links(tab: &Arc<Tab>) -> Result<Vec<String>> {
 pub fn get_news(opts: GetNewsOpts) -> Result<()> {
     let tab = opts.browser.new_tab()?;
     tab.set_default_timeout(std::time::Duration::from_secs(120));
-    let user_agent = opts.browser.get_version()?.user_agent;
-    let user_agent = user_agent.replace("HeadlessChrome", "Chrome");
-    tab.set_user_agent(&user_agent, None, None)?;
+    opts.set_user_agent(&tab)?;
     for category in CATEGORIES {
-        trace!("checking out category {category}");
-        tab.navigate_to(&format!("https://www.francetvinfo.fr/{category}/"))
+        trace!(%category, "checking out category");
+        super::navigate(&tab, &format!("https://www.francetvinfo.fr/{category}/"))
             .context("navigate_to")?;
         tab.wait_until_navigated().context("wait_until_navigated")?;
 
-        if let Ok(cookie) = tab.find_element("#didomi-notice-agree-button") {
-            cookie.click().context("clicking on cookie")?;
-        }
+        super::dismiss_consent(&tab)?;
 
         let links = get_articles_links(&tab).context("francetvinfo")?;
-        trace!("found {} links on {category}", links.len());
+        trace!(%category, links = links.len(), "found links");
         if links.is_empty() {
             bail!("no links found");
         }
         for url in links {
+            if opts.is_cancelled() {
+                return Ok(());
+            }
             if opts.is_seen(&url) {
                 continue;
             }
 
-            let res = super::fetch_article(&url);
+            let res = opts.fetch_article(&url);
             let payload = match res {
                 Ok(res) => Ok(News {
                     title: res.title,
@@ -77,16 +76,17 @@ pub fn get_news(opts: GetNewsOpts) -> Result<()> {
                     provider: opts.provider.clone(),
                     date: res.published,
                     body: res.content,
+                    accessible_for_free: res.accessible_for_free,
                     link: url,
                     ..Default::default()
                 }),
                 Err(err) => {
-                    log::warn!("fetch_article on {url}: {err:?}");
+                    warn!(link = %url, error = %format!("{err:#}"), "fetch_article failed");
                     continue;
                 }
             };
             if let Err(e) = opts.tx.blocking_send(payload) {
-                error!("blocking_send: {e}");
+                error!(error = %e, "blocking_send failed");
                 break;
             }
         }
@@ -1,9 +1,8 @@
 use super::GetNewsOpts;
-use crate::sources::fetch_article;
 use anyhow::bail;
 use anyhow::{Context, Result};
 use headless_chrome::Tab;
-use log::info;
+use tracing::{info, warn};
 use shared::News;
 use std::sync::Arc;
 
@@ -29,20 +28,24 @@ fn get_articles_links(tab: &Arc<Tab>) -> Result<Vec<String>> {
 pub fn get_news(opts: GetNewsOpts) -> Result<()> {
     let tab = opts.browser.new_tab()?;
     tab.set_default_timeout(std::time::Duration::from_secs(120));
-    let user_agent = opts.browser.get_version().unwrap().user_agent;
-    let user_agent = user_agent.replace("HeadlessChrome", "Chrome");
-    tab.set_user_agent(&user_agent, None, None)?;
-    tab.navigate_to("https://www.futura-sciences.com/sitemap-html/actualites/")
-        .context("navigate_to")?
-        .wait_until_navigated()
-        .context("wait_until_navigated")?;
+    opts.set_user_agent(&tab)?;
+    super::navigate(
+        &tab,
+        "https://www.futura-sciences.com/sitemap-html/actualites/",
+    )
+    .context("navigate_to")?
+    .wait_until_navigated()
+    .context("wait_until_navigated")?;
 
     let links = get_articles_links(&tab).context("futura-sciences")?;
-    info!("found {} articles", links.len());
+    info!(articles = links.len(), "found articles");
     if links.is_empty() {
         bail!("no links found");
     }
     for url in links {
+        if opts.is_cancelled() {
+            return Ok(());
+        }
         if opts.is_seen(&url) {
             continue;
         }
@@ -55,7 +58,7 @@ pub fn get_news(opts: GetNewsOpts) -> Result<()> {
             .map(str::to_string)
             .collect();
 
-        let res = fetch_article(&url);
+        let res = opts.fetch_article(&url);
         let payload = match res {
             Ok(res) => Ok(News {
                 title: res.title,
@@ -64,10 +67,11 @@ pub fn get_news(opts: GetNewsOpts) -> Result<()> {
                 tags,
                 date: res.published,
                 body: res.content,
+                accessible_for_free: res.accessible_for_free,
                 link: url,
             }),
             Err(err) => {
-                log::warn!("fetch_article on {url}: {err:?}");
+                warn!(link = %url, error = %format!("{err:#}"), "fetch_article failed");
                 continue;
             }
         };
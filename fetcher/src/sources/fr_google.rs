@@ -2,7 +2,7 @@ use super::GetNewsOpts;
 use anyhow::bail;
 use anyhow::{Context, Result};
 use headless_chrome::Tab;
-use log::{error, trace};
+use tracing::{error, trace, warn};
 use shared::News;
 use std::sync::Arc;
 
@@ -29,37 +29,36 @@ fn get_articles_links(tab: &Arc<Tab>) -> Result<Vec<String>> {
 pub fn get_news(opts: GetNewsOpts) -> Result<()> {
     let tab = opts.browser.new_tab()?;
     tab.set_default_timeout(std::time::Duration::from_secs(120));
-    let user_agent = opts.browser.get_version().unwrap().user_agent;
-    let user_agent = user_agent.replace("HeadlessChrome", "Chrome");
-    tab.set_user_agent(&user_agent, None, None)?;
+    opts.set_user_agent(&tab)?;
     for keyword in KEYWORDS {
-        trace!("checking out keyword {keyword}");
-        tab.navigate_to(&format!(
-            "https://www.google.com/search?q={keyword}&tbm=nws&tbs=qdr%3Ad"
-        ))
+        trace!(keyword, "checking out keyword");
+        super::navigate(
+            &tab,
+            &format!("https://www.google.com/search?q={keyword}&tbm=nws&tbs=qdr%3Ad"),
+        )
         .context("navigate_to")?;
         tab.wait_until_navigated().context("wait_until_navigated")?;
-        if let Ok(cookies) = tab.find_element_by_xpath("//span[contains(text(), 'Tout refuser')]") {
-            cookies.click().context("clicking on cookies")?;
-            tab.wait_until_navigated()?;
-        }
+        super::dismiss_consent(&tab)?;
         tab.wait_for_element("#center_col")
             .context("waiting on #center_col")?;
 
         let links = get_articles_links(&tab).context("google")?;
-        trace!("found {} links on {keyword}", links.len());
+        trace!(keyword, links = links.len(), "found links");
         if links.is_empty() {
             bail!("no links found");
         }
         for url in links {
+            if opts.is_cancelled() {
+                return Ok(());
+            }
             if opts.is_seen(&url) {
                 continue;
             }
 
-            let mut res = super::fetch_article(&url);
+            let mut res = opts.fetch_article(&url);
             if let Err(err) = res {
-                log::warn!("fetch_article on {url}: {err:?}");
-                if tab.navigate_to(&url).is_err() {
+                warn!(link = %url, error = %format!("{err:#}"), "fetch_article failed");
+                if super::navigate(&tab, &url).is_err() {
                     continue;
                 };
                 if tab
@@ -78,18 +77,19 @@ pub fn get_news(opts: GetNewsOpts) -> Result<()> {
                     title: res.title,
                     caption: res.description,
                     provider: opts.provider.clone(),
-date: res.published,
+                    date: res.published,
                     body: res.content,
+                    accessible_for_free: res.accessible_for_free,
                     link: url,
                     ..Default::default()
                 }),
                 Err(err) => {
-                    log::warn!("parse_article on {url}: {err:?}");
+                    warn!(link = %url, error = %format!("{err:#}"), "parse_article failed");
                     continue;
                 }
             };
             if let Err(e) = opts.tx.blocking_send(payload) {
-                error!("blocking_send: {e}");
+                error!(error = %e, "blocking_send failed");
                 break;
             }
         }
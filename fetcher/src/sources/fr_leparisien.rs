@@ -2,8 +2,8 @@ use super::{GetNewsOpts, News};
 use anyhow::bail;
 use anyhow::{Context, Result};
 use headless_chrome::Tab;
-use log::{error, trace};
-use std::{sync::Arc, thread};
+use tracing::{error, trace, warn};
+use std::sync::Arc;
 
 const CATEGORIES: [&str; 7] = [
     "faits-divers",
@@ -37,51 +37,48 @@ fn get_articles_links(tab: &Arc<Tab>) -> Result<Vec<String>> {
 pub fn get_news(opts: GetNewsOpts) -> Result<()> {
     let tab = opts.browser.new_tab()?;
     tab.set_default_timeout(std::time::Duration::from_secs(120));
-    let user_agent = opts.browser.get_version().unwrap().user_agent;
-    let user_agent = user_agent.replace("HeadlessChrome", "Chrome");
-    tab.set_user_agent(&user_agent, None, None)?;
+    opts.set_user_agent(&tab)?;
     for category in CATEGORIES {
-        trace!("checking out category {category}");
-        tab.navigate_to(&format!("https://www.leparisien.fr/{category}"))
+        trace!(%category, "checking out category");
+        super::navigate(&tab, &format!("https://www.leparisien.fr/{category}"))
             .context("navigate_to")?;
         tab.wait_until_navigated()
             .context("category wait_until_navigated")?;
 
-        if let Ok(cookies) = tab.find_element_by_xpath("//button[contains(text(), 'Accepter')]") {
-            cookies.click().context("clicking on cookies")?;
-            tab.wait_until_navigated()
-                .context("cookies wait_until_navigated")?;
-            thread::sleep(std::time::Duration::from_secs(1));
-        };
+        super::dismiss_consent(&tab)?;
 
         let links = get_articles_links(&tab).context("leparisien")?;
-        trace!("found {} links on {category}", links.len());
+        trace!(%category, links = links.len(), "found links");
         if links.is_empty() {
             bail!("no links found");
         }
         for url in links {
+            if opts.is_cancelled() {
+                return Ok(());
+            }
             if opts.is_seen(&url) {
                 continue;
             }
 
-            let res = super::fetch_article(&url);
+            let res = opts.fetch_article(&url);
             let payload = match res {
                 Ok(res) => Ok(News {
                     tags: vec![category.to_string()],
                     title: res.title,
                     caption: res.description,
                     provider: opts.provider.clone(),
-date: res.published,
+                    date: res.published,
                     body: res.content,
+                    accessible_for_free: res.accessible_for_free,
                     link: url,
                 }),
                 Err(err) => {
-                    log::warn!("fetch_article on {url}: {err:?}");
+                    warn!(link = %url, error = %format!("{err:#}"), "fetch_article failed");
                     continue;
                 }
             };
             if let Err(e) = opts.tx.blocking_send(payload) {
-                error!("blocking_send: {e}");
+                error!(error = %e, "blocking_send failed");
                 break;
             }
         }
@@ -2,7 +2,7 @@ use super::{GetNewsOpts, News};
 use anyhow::bail;
 use anyhow::{Context, Result};
 use headless_chrome::{Element, Tab};
-use log::error;
+use tracing::{error, warn};
 use std::{sync::Arc, thread, time::Duration};
 
 fn _isvalidpost(el: &Element) -> bool {
@@ -39,11 +39,8 @@ fn get_articles_links(tab: &Arc<Tab>) -> Result<Vec<String>> {
 pub fn get_news(opts: GetNewsOpts) -> Result<()> {
     let tab = opts.browser.new_tab()?;
     tab.set_default_timeout(std::time::Duration::from_secs(120));
-    let user_agent = opts.browser.get_version().unwrap().user_agent;
-    let user_agent = user_agent.replace("HeadlessChrome", "Chrome");
-    tab.set_user_agent(&user_agent, None, None)?;
-    tab.navigate_to("https://www.reddit.com/r/UpliftingNews/new/")
-        .context("navigate_to")?;
+    opts.set_user_agent(&tab)?;
+    super::navigate(&tab, "https://www.reddit.com/r/UpliftingNews/new/").context("navigate_to")?;
     tab.wait_until_navigated().context("wait_until_navigated")?;
     thread::sleep(Duration::from_secs(2));
 
@@ -52,19 +49,22 @@ pub fn get_news(opts: GetNewsOpts) -> Result<()> {
         bail!("no links found");
     }
     for url in links {
+        if opts.is_cancelled() {
+            return Ok(());
+        }
         if opts.is_seen(&url) {
             continue;
         }
 
-        let mut res = super::fetch_article(&url);
+        let mut res = opts.fetch_article(&url);
         if let Err(err) = res {
-            log::warn!("fetch_article on {url}: {err:?}");
-            if let Err(e) = tab.navigate_to(&url) {
-                log::warn!("could not navigate to {url}: {e}");
+            warn!(link = %url, error = %format!("{err:#}"), "fetch_article failed");
+            if let Err(e) = super::navigate(&tab, &url) {
+                warn!(link = %url, error = %format!("{e:#}"), "could not navigate");
                 continue;
             };
             if let Err(e) = tab.wait_until_navigated().context("wait_until_navigated") {
-                log::warn!("could not load {url}: {e}");
+                warn!(link = %url, error = %format!("{e:#}"), "could not load");
                 continue;
             }
             std::thread::sleep(std::time::Duration::from_secs(1));
@@ -80,16 +80,17 @@ pub fn get_news(opts: GetNewsOpts) -> Result<()> {
                 provider: opts.provider.clone(),
                 date: res.published,
                 body: res.content,
+                accessible_for_free: res.accessible_for_free,
                 link: url,
                 ..Default::default()
             }),
             Err(err) => {
-                log::warn!("parse_article on {url}: {err:?}");
+                warn!(link = %url, error = %format!("{err:#}"), "parse_article failed");
                 continue;
             }
         };
         if let Err(e) = opts.tx.blocking_send(payload) {
-            error!("blocking_send: {e}");
+            error!(error = %e, "blocking_send failed");
             break;
         }
     }
@@ -2,7 +2,7 @@ use super::{GetNewsOpts, News};
 use anyhow::bail;
 use anyhow::{Context, Result};
 use headless_chrome::Tab;
-use log::trace;
+use tracing::{trace, warn};
 use std::sync::Arc;
 
 const CATEGORIES: [&str; 8] = [
@@ -36,38 +36,40 @@ fn get_articles_links(tab: &Arc<Tab>) -> Result<Vec<String>> {
 pub fn get_news(opts: GetNewsOpts) -> Result<()> {
     let tab = opts.browser.new_tab()?;
     tab.set_default_timeout(std::time::Duration::from_secs(120));
-    let user_agent = opts.browser.get_version().unwrap().user_agent;
-    let user_agent = user_agent.replace("HeadlessChrome", "Chrome");
-    tab.set_user_agent(&user_agent, None, None)?;
+    opts.set_user_agent(&tab)?;
     for category in CATEGORIES {
-        trace!("checking out category {category}");
-        tab.navigate_to(&format!("https://reporterre.net/{category}"))
+        trace!(%category, "checking out category");
+        super::navigate(&tab, &format!("https://reporterre.net/{category}"))
             .context("navigate_to")?;
         tab.wait_until_navigated()
             .context("category wait_until_navigated")?;
 
         let links = get_articles_links(&tab).context("reporterre")?;
-        trace!("found {} links on {category}", links.len());
+        trace!(%category, links = links.len(), "found links");
         if links.is_empty() {
             bail!("no links found");
         }
         for url in links {
+            if opts.is_cancelled() {
+                return Ok(());
+            }
             if opts.is_seen(&url) {
                 continue;
             }
 
-            let payload = match super::fetch_article(&url) {
+            let payload = match opts.fetch_article(&url) {
                 Ok(res) => Ok(News {
                     title: res.title,
                     caption: res.description,
                     provider: opts.provider.clone(),
                     tags: vec![category.to_string()],
-date: res.published,
+                    date: res.published,
                     body: res.content,
+                    accessible_for_free: res.accessible_for_free,
                     link: url,
                 }),
                 Err(err) => {
-                    log::warn!("fetch_article on {url}: {err:?}");
+                    warn!(link = %url, error = %format!("{err:#}"), "fetch_article failed");
                     continue;
                 }
             };
@@ -1,9 +1,8 @@
 use super::GetNewsOpts;
-use crate::sources::fetch_article;
 use anyhow::bail;
 use anyhow::{Context, Result};
 use headless_chrome::Tab;
-use log::{debug, info};
+use tracing::{debug, info, warn};
 use shared::News;
 use std::sync::Arc;
 
@@ -31,36 +30,41 @@ fn get_articles_links(tab: &Arc<Tab>) -> Result<Vec<String>> {
 pub fn get_news(opts: GetNewsOpts) -> Result<()> {
     let tab = opts.browser.new_tab()?;
     tab.set_default_timeout(std::time::Duration::from_secs(120));
-    let user_agent = opts.browser.get_version().unwrap().user_agent;
-    let user_agent = user_agent.replace("HeadlessChrome", "Chrome");
-    tab.set_user_agent(&user_agent, None, None)?;
+    opts.set_user_agent(&tab)?;
     for category in CATEGORIES {
-        debug!("checking out category {category}");
-        tab.navigate_to(&format!("https://www.futura-sciences.com/{category}/"))
-            .context("navigate_to")?
-            .wait_until_navigated()
-            .context("wait_until_navigated")?;
+        debug!(%category, "checking out category");
+        super::navigate(
+            &tab,
+            &format!("https://www.futura-sciences.com/{category}/"),
+        )
+        .context("navigate_to")?
+        .wait_until_navigated()
+        .context("wait_until_navigated")?;
         let links = get_articles_links(&tab).context(opts.provider.clone())?;
-        info!("found {} articles in category {category}", links.len());
+        info!(%category, articles = links.len(), "found articles");
         if links.is_empty() {
             bail!("no links found");
         }
         for url in links {
+            if opts.is_cancelled() {
+                return Ok(());
+            }
             if opts.is_seen(&url) {
                 continue;
             }
-            let payload = match fetch_article(&url) {
+            let payload = match opts.fetch_article(&url) {
                 Ok(res) => Ok(News {
                     title: res.title,
                     caption: res.description,
                     provider: opts.provider.clone(),
                     date: res.published,
                     body: res.content,
+                    accessible_for_free: res.accessible_for_free,
                     link: url,
                     ..Default::default()
                 }),
                 Err(err) => {
-                    log::warn!("fetch_article on {url}: {err:?}");
+                    warn!(link = %url, error = %format!("{err:#}"), "fetch_article failed");
                     continue;
                 }
             };
@@ -3,7 +3,7 @@ use crate::sources::parse_article;
 use anyhow::bail;
 use anyhow::{Context, Result};
 use headless_chrome::Tab;
-use log::info;
+use tracing::{info, warn};
 use shared::News;
 use std::sync::Arc;
 
@@ -25,24 +25,25 @@ fn get_articles_links(tab: &Arc<Tab>) -> Result<Vec<String>> {
 pub fn get_news(opts: GetNewsOpts) -> Result<()> {
     let tab = opts.browser.new_tab()?;
     tab.set_default_timeout(std::time::Duration::from_secs(120));
-    let user_agent = opts.browser.get_version().unwrap().user_agent;
-    let user_agent = user_agent.replace("HeadlessChrome", "Chrome");
-    tab.set_user_agent(&user_agent, None, None)?;
+    opts.set_user_agent(&tab)?;
     for category in ["evenement/vacances-en-france", "voyage", "aventure"] {
-        tab.navigate_to(&format!("https://www.geo.fr/{category}"))
+        super::navigate(&tab, &format!("https://www.geo.fr/{category}"))
             .context("navigate_to")?
             .wait_until_navigated()
             .context("wait_until_navigated")?;
         let links = get_articles_links(&tab).context("get_articles_links")?;
-        info!("found {} articles", links.len());
+        info!(articles = links.len(), "found articles");
         if links.is_empty() {
             bail!("no links found");
         }
         for url in links {
+            if opts.is_cancelled() {
+                return Ok(());
+            }
             if opts.is_seen(&url) {
                 continue;
             }
-            tab.navigate_to(&url)
+            super::navigate(&tab, &url)
                 .context(format!("navigate_to {url}"))?
                 .wait_until_navigated()
                 .context("wait_until_navigated url")?;
@@ -54,11 +55,12 @@ pub fn get_news(opts: GetNewsOpts) -> Result<()> {
                     provider: opts.provider.clone(),
                     date: res.published,
                     body: res.content,
+                    accessible_for_free: res.accessible_for_free,
                     link: url,
                     ..Default::default()
                 }),
                 Err(err) => {
-                    log::warn!("parse_article on {url}: {err:?}");
+                    warn!(link = %url, error = %format!("{err:#}"), "parse_article failed");
                     continue;
                 }
             };
@@ -1,7 +1,7 @@
 use super::{GetNewsOpts, News};
 use anyhow::{bail, Context, Result};
 use headless_chrome::Tab;
-use log::{error, info};
+use tracing::{error, info};
 use std::{collections::HashSet, sync::Arc};
 
 fn get_articles_links(tab: &Arc<Tab>) -> Result<HashSet<String>> {
@@ -20,25 +20,26 @@ fn get_articles_links(tab: &Arc<Tab>) -> Result<HashSet<String>> {
 pub fn get_news(opts: GetNewsOpts) -> Result<()> {
     let tab = opts.browser.new_tab()?;
     tab.set_default_timeout(std::time::Duration::from_secs(120));
-    let user_agent = opts.browser.get_version()?.user_agent;
-    let user_agent = user_agent.replace("HeadlessChrome", "Chrome");
-    tab.set_user_agent(&user_agent, None, None)?;
+    opts.set_user_agent(&tab)?;
     for category in ["societe/actu", "societe/sportives", "loisirs/voyages"] {
-        tab.navigate_to(&format!("https://www.journaldesfemmes.fr/{category}"))
+        super::navigate(&tab, &format!("https://www.journaldesfemmes.fr/{category}"))
             .context("navigate_to")?;
         tab.wait_until_navigated().context("wait_until_navigated")?;
 
         let links = get_articles_links(&tab)?;
-        info!("found {} articles on {category}", links.len());
+        info!(%category, articles = links.len(), "found articles");
         if links.is_empty() {
             bail!("no links found");
         }
 
         for url in links {
+            if opts.is_cancelled() {
+                return Ok(());
+            }
             if opts.is_seen(&url) {
                 continue;
             }
-            let res = super::fetch_article(&url);
+            let res = opts.fetch_article(&url);
             let payload = match res {
                 Ok(res) => Ok(News {
                     title: res.title,
@@ -46,11 +47,12 @@ pub fn get_news(opts: GetNewsOpts) -> Result<()> {
                     provider: opts.provider.clone(),
                     date: res.published,
                     body: res.content,
+                    accessible_for_free: res.accessible_for_free,
                     link: url,
                     tags: vec!["fr".to_string()],
                 }),
                 Err(err) => {
-                    error!("fetch_article on {url}: {err:?}");
+                    error!(link = %url, error = %format!("{err:#}"), "fetch_article failed");
                     continue;
                 }
             };
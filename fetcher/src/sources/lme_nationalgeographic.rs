@@ -1,9 +1,8 @@
 use super::GetNewsOpts;
-use crate::sources::fetch_article;
 use anyhow::bail;
 use anyhow::{Context, Result};
 use headless_chrome::Tab;
-use log::{info, trace};
+use tracing::{info, trace, warn};
 use shared::News;
 use std::collections::HashSet;
 use std::sync::Arc;
@@ -29,36 +28,41 @@ fn get_articles_links(tab: &Arc<Tab>) -> Result<Vec<String>> {
 pub fn get_news(opts: GetNewsOpts) -> Result<()> {
     let tab = opts.browser.new_tab()?;
     tab.set_default_timeout(std::time::Duration::from_secs(120));
-    let user_agent = opts.browser.get_version().unwrap().user_agent;
-    let user_agent = user_agent.replace("HeadlessChrome", "Chrome");
-    tab.set_user_agent(&user_agent, None, None)?;
+    opts.set_user_agent(&tab)?;
     for category in ["voyage", "environnement", "histoire"] {
-        trace!("checking out category {}", category);
-        tab.navigate_to(&format!("https://www.nationalgeographic.fr/{category}"))
-            .context("navigate_to")?
-            .wait_until_navigated()
-            .context("wait_until_navigated")?;
+        trace!(%category, "checking out category");
+        super::navigate(
+            &tab,
+            &format!("https://www.nationalgeographic.fr/{category}"),
+        )
+        .context("navigate_to")?
+        .wait_until_navigated()
+        .context("wait_until_navigated")?;
         let links = get_articles_links(&tab).context("get_articles_links")?;
-        info!("found {} articles", links.len());
+        info!(articles = links.len(), "found articles");
         if links.is_empty() {
             bail!("no links found");
         }
         for url in links {
+            if opts.is_cancelled() {
+                return Ok(());
+            }
             if opts.is_seen(&url) {
                 continue;
             }
-            let payload = match fetch_article(&url) {
+            let payload = match opts.fetch_article(&url) {
                 Ok(res) => Ok(News {
                     title: res.title,
                     caption: res.description,
                     provider: opts.provider.clone(),
                     date: res.published,
                     body: res.content,
+                    accessible_for_free: res.accessible_for_free,
                     link: url,
                     ..Default::default()
                 }),
                 Err(err) => {
-                    log::warn!("fetch_article on {url}: {err:?}");
+                    warn!(link = %url, error = %format!("{err:#}"), "fetch_article failed");
                     continue;
                 }
             };
@@ -1,34 +1,121 @@
 automod::dir!("src/sources");
 
+use crate::{
+    consent::Consent,
+    network::{Network, DEFAULT_USER_AGENT},
+    politeness::Politeness,
+    pool::Lease,
+};
 use anyhow::{anyhow, Context};
 use chrono::{DateTime, Local};
-use headless_chrome::Browser;
-use log::{debug, trace};
+use headless_chrome::Tab;
 use serde::{Deserialize, Deserializer, Serialize};
-use shared::News;
-use std::sync::{Arc, RwLock};
-use std::time::Duration;
+use shared::{ingest::canonical_link, metrics, News};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, OnceLock, RwLock,
+};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc::Sender;
+use tracing::{debug, trace, warn};
 
 pub struct GetNewsOpts {
-    pub browser: Browser,
+    /// tabs of its own on a browser of the pool
+    pub browser: Lease,
     pub tx: Sender<anyhow::Result<News>>,
     pub seen_links: Arc<RwLock<Vec<String>>>,
     pub provider: String,
+    pub cancel: Arc<AtomicBool>,
 }
 impl GetNewsOpts {
+    /// set on ctrl-c or SIGTERM. sources should stop before starting another article
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+    /// a user agent of the pool, or the browser's one without `Headless`
+    pub fn set_user_agent(&self, tab: &Tab) -> anyhow::Result<()> {
+        let user_agent = match network().user_agent() {
+            Some(user_agent) => user_agent.to_string(),
+            None => self
+                .browser
+                .get_version()?
+                .user_agent
+                .replace("HeadlessChrome", "Chrome"),
+        };
+        tab.set_user_agent(&user_agent, None, None)?;
+        Ok(())
+    }
+    /// [`fetch_article`] with the proxy of this source for `url`, and a user agent of the pool
+    pub fn fetch_article(&self, url: &str) -> anyhow::Result<ApiResponse> {
+        let network = network();
+        fetch_article(
+            url,
+            network.proxy_for(&self.provider, url),
+            network.user_agent(),
+        )
+    }
     // is the link seen with the current provider?
     pub fn is_seen(&self, link: &str) -> bool {
-        if self.seen_links.read().unwrap().contains(&link.to_string()) {
-            trace!("already seen {} with provider {}", link, self.provider);
+        metrics::NEWS_FOUND
+            .with_label_values(&[&self.provider])
+            .inc();
+        if self
+            .seen_links
+            .read()
+            .unwrap()
+            .contains(&canonical_link(link))
+        {
+            trace!(link, provider = %self.provider, "already seen");
             return true;
         }
         false
     }
 }
 
-pub fn extract_prefix_from_provider(module: &str) -> String {
-    module.split_once("::").unwrap().0.to_string()
+/// set from the config when the fetcher starts
+pub static ARTICLE_PARSER_URL: OnceLock<String> = OnceLock::new();
+
+fn article_parser_url() -> &'static str {
+    ARTICLE_PARSER_URL
+        .get()
+        .expect("ARTICLE_PARSER_URL not set")
+}
+
+/// set from `POLITENESS_FILE` when the fetcher starts
+pub static POLITENESS: OnceLock<Politeness> = OnceLock::new();
+
+fn politeness() -> &'static Politeness {
+    POLITENESS.get().expect("POLITENESS not set")
+}
+
+/// set from `NETWORK_FILE` when the fetcher starts
+pub static NETWORK: OnceLock<Network> = OnceLock::new();
+
+pub fn network() -> &'static Network {
+    NETWORK.get().expect("NETWORK not set")
+}
+
+/// set from `CONSENT_FILE` when the fetcher starts
+pub static CONSENT: OnceLock<Consent> = OnceLock::new();
+
+fn consent() -> &'static Consent {
+    CONSENT.get().expect("CONSENT not set")
+}
+
+/// `tab.navigate_to` then `wait_until_navigated`, once the politeness rules of the domain of
+/// `url` allow it, with the consent cookies of the domain set. the page counts as in flight
+/// until it's loaded
+pub fn navigate<'a>(tab: &'a Tab, url: &str) -> anyhow::Result<&'a Tab> {
+    consent().seed(tab, url)?;
+    let _permit = politeness().acquire(url)?;
+    tab.navigate_to(url)?.wait_until_navigated()
+}
+
+/// answers the cookie banner of the page like `CONSENT_FILE` says, whatever its CMP, and removes
+/// the overlays covering it. call it once the page is loaded
+pub fn dismiss_consent(tab: &Tab) -> anyhow::Result<()> {
+    consent().dismiss(tab)?;
+    Ok(())
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -46,6 +133,9 @@ pub struct ApiResponse {
     links: Vec<String>,
     ttr: f64,
     r#type: String,
+    /// from the JSON-LD of the page, `None` when it doesn't say
+    #[serde(default)]
+    accessible_for_free: Option<bool>,
 }
 
 fn deserialize_null_default<'de, D>(deserializer: D) -> Result<DateTime<Local>, D::Error>
@@ -57,19 +147,48 @@ where
     Ok(parsed.map(Into::into).unwrap_or_else(|_| Local::now()))
 }
 
-pub fn fetch_article(url: impl AsRef<str>) -> Result<ApiResponse, anyhow::Error> {
-    let url = url.as_ref();
-    debug!("fetching {}", url);
-    let endpoint = format!(
-        "{}/fetch?url={}",
-        std::env::var("ARTICLE_PARSER_URL").expect("ARTICLE_PARSER_URL not set"),
-        url
-    );
-    let response = ureq::get(&endpoint).timeout(Duration::from_secs(6)).call();
+/// the article at `url`, requested by the article parser, or downloaded here through `proxy` and
+/// sent to the article parser, which can't use a proxy
+pub fn fetch_article(
+    url: &str,
+    proxy: Option<&str>,
+    user_agent: Option<&str>,
+) -> Result<ApiResponse, anyhow::Error> {
+    debug!(link = url, "fetching");
+    // the article parser requests the page itself
+    let _permit = politeness().acquire(url)?;
+    if let Some(proxy) = proxy {
+        let agent = ureq::AgentBuilder::new()
+            .proxy(ureq::Proxy::new(proxy)?)
+            .build();
+        // not ureq's own user agent, which stands out
+        let html = agent
+            .get(url)
+            .timeout(Duration::from_secs(10))
+            .set("User-Agent", user_agent.unwrap_or(DEFAULT_USER_AGENT))
+            .call()
+            .with_context(|| format!("{url} through {proxy}"))?
+            .into_string()?;
+        return parse(&html, Some(url));
+    }
+    let endpoint = format!("{}/fetch?url={}", article_parser_url(), url);
+    let started = Instant::now();
+    let mut request = ureq::get(&endpoint).timeout(Duration::from_secs(6));
+    if let Some(user_agent) = user_agent {
+        request = request.query("user_agent", user_agent);
+    }
+    let response = request.call();
+    observe_parser("fetch", started, &response);
     let response = match response {
         Ok(response) => response.into_string()?,
         Err(ureq::Error::Status(code, res)) => {
-            return Err(anyhow!("{url} returned: {code} {res:#?}"));
+            warn!(
+                url,
+                code,
+                status = res.status_text(),
+                "article parser /fetch failed"
+            );
+            return Err(anyhow!("{url} returned {code}"));
         }
         Err(e) => {
             return Err(anyhow!("{url}: {e}"));
@@ -77,32 +196,72 @@ pub fn fetch_article(url: impl AsRef<str>) -> Result<ApiResponse, anyhow::Error>
     };
     let json_result = serde_json::from_str(&response)
         .or_else(|_| serde_json::from_str(&response.replace('"', "'")))
-        .context("deserialize json response to ApiResponse struct")?;
-    Ok(json_result)
+        .context("deserialize json response to ApiResponse struct");
+    if json_result.is_err() {
+        metrics::ARTICLE_PARSER_ERRORS
+            .with_label_values(&["fetch", "invalid_response"])
+            .inc();
+    }
+    json_result
 }
 
 pub fn parse_article(str: impl AsRef<str>) -> Result<ApiResponse, anyhow::Error> {
-    let str = str.as_ref();
-    let endpoint = format!(
-        "{}/parse",
-        std::env::var("ARTICLE_PARSER_URL").expect("ARTICLE_PARSER_URL not set")
-    );
-    let response = ureq::post(&endpoint)
-        .timeout(Duration::from_secs(5))
-        .send_string(str);
+    parse(str.as_ref(), None)
+}
+
+/// `url` resolves the relative links of the page
+fn parse(str: &str, url: Option<&str>) -> Result<ApiResponse, anyhow::Error> {
+    let endpoint = format!("{}/parse", article_parser_url());
+    let started = Instant::now();
+    let mut request = ureq::post(&endpoint).timeout(Duration::from_secs(5));
+    if let Some(url) = url {
+        request = request.query("url", url);
+    }
+    let response = request.send_string(str);
+    observe_parser("parse", started, &response);
     let response = match response {
         Ok(response) => response,
         Err(ureq::Error::Status(code, res)) => {
-            return Err(anyhow!("{}: {:#?}", code, res));
+            warn!(
+                url,
+                code,
+                status = res.status_text(),
+                "article parser /parse failed"
+            );
+            return Err(anyhow!("article parser returned {code}"));
         }
         Err(e) => {
             return Err(anyhow!("{}", e));
         }
     };
-    let json_result: ApiResponse = response
+    let json_result = response
         .into_json()
-        .context("deserialize json response to ApiResponse struct")?;
-    Ok(json_result)
+        .context("deserialize json response to ApiResponse struct");
+    if json_result.is_err() {
+        metrics::ARTICLE_PARSER_ERRORS
+            .with_label_values(&["parse", "invalid_response"])
+            .inc();
+    }
+    json_result
+}
+
+/// the latency of an article parser request, and its error if any
+fn observe_parser(
+    endpoint: &str,
+    started: Instant,
+    response: &Result<ureq::Response, ureq::Error>,
+) {
+    metrics::ARTICLE_PARSER_DURATION
+        .with_label_values(&[endpoint])
+        .observe(started.elapsed().as_secs_f64());
+    let class = match response {
+        Ok(_) => return,
+        Err(ureq::Error::Status(..)) => "status",
+        Err(ureq::Error::Transport(_)) => "transport",
+    };
+    metrics::ARTICLE_PARSER_ERRORS
+        .with_label_values(&[endpoint, class])
+        .inc();
 }
 
 pub type SourceFn = fn(GetNewsOpts) -> anyhow::Result<()>;
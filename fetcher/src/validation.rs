@@ -0,0 +1,254 @@
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+use shared::{config::load_json, lang, DbNews};
+
+/// clocks and time zones of the sites are not always right
+const FUTURE_TOLERANCE: Duration = Duration::hours(1);
+
+/// below it, the language detected for a title is a guess
+const MIN_LANG_CONFIDENCE: f64 = 0.5;
+
+/// the errors, bot checks and banners returned instead of the article, in lowercase. they are
+/// looked for in the title and the first words of the body
+const ERROR_PAGES: &[&str] = &[
+    "access denied",
+    "accès refusé",
+    "403 forbidden",
+    "404 not found",
+    "page not found",
+    "page introuvable",
+    "erreur 404",
+    "just a moment...",
+    "attention required",
+    "are you a robot",
+    "êtes-vous un robot",
+    "enable javascript",
+    "activez javascript",
+    "nous utilisons des cookies",
+    "we use cookies",
+    "en acceptant les cookies",
+];
+
+/// how many words of the body can be an error page
+const ERROR_PAGE_WORDS: usize = 60;
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    /// the body has at least `min_words` words
+    MinWords,
+    Title,
+    ErrorPage,
+    FutureDate,
+    /// the title and the body are in the same language
+    LanguageMatch,
+}
+
+impl Rule {
+    /// its name in the file, the metrics and the flags of the news
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::MinWords => "min_words",
+            Rule::Title => "title",
+            Rule::ErrorPage => "error_page",
+            Rule::FutureDate => "future_date",
+            Rule::LanguageMatch => "language_match",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Off,
+    /// the news is saved, with the rule in its `quality_flags`
+    Flag,
+    Reject,
+}
+
+/// validation file, what a news must pass to be saved:
+/// ```json
+/// { "min_words": 50, "rules": { "language_match": "reject", "min_words": "flag" } }
+/// ```
+/// every rule is `off`, `flag` or `reject`. the ones left out only flag the news, so nothing is
+/// rejected without a file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ValidationFile {
+    #[serde(default = "default_min_words")]
+    pub min_words: usize,
+    #[serde(default)]
+    pub rules: BTreeMap<Rule, Action>,
+}
+
+fn default_min_words() -> usize {
+    50
+}
+
+impl Default for ValidationFile {
+    fn default() -> ValidationFile {
+        ValidationFile {
+            min_words: default_min_words(),
+            rules: BTreeMap::new(),
+        }
+    }
+}
+
+impl ValidationFile {
+    pub fn load(path: impl AsRef<Path>) -> Result<ValidationFile> {
+        load_json(path)
+    }
+}
+
+/// the rules a news broke
+#[derive(Debug, Default, PartialEq)]
+pub struct Verdict {
+    pub rejected: Vec<Rule>,
+    pub flagged: Vec<Rule>,
+}
+
+/// checks the news between the sources and the store
+pub struct Validator {
+    file: ValidationFile,
+}
+
+impl Validator {
+    pub fn new(file: ValidationFile) -> Validator {
+        Validator { file }
+    }
+
+    fn action(&self, rule: Rule) -> Action {
+        self.file.rules.get(&rule).copied().unwrap_or(Action::Flag)
+    }
+
+    /// `news` as it's about to be inserted, its body cleaned and its language detected
+    pub fn validate(&self, news: &DbNews) -> Verdict {
+        let text = news.text_body.as_ref();
+        let words = text.split_whitespace().count();
+        let mut verdict = Verdict::default();
+        for rule in [
+            Rule::MinWords,
+            Rule::Title,
+            Rule::ErrorPage,
+            Rule::FutureDate,
+            Rule::LanguageMatch,
+        ] {
+            let action = self.action(rule);
+            if action == Action::Off {
+                continue;
+            }
+            let broken = match rule {
+                Rule::MinWords => words < self.file.min_words,
+                Rule::Title => news.title.trim().is_empty(),
+                Rule::ErrorPage => is_error_page(&news.title, text),
+                Rule::FutureDate => *news.date > Utc::now() + FUTURE_TOLERANCE,
+                Rule::LanguageMatch => !same_language(&news.title, news),
+            };
+            match (broken, action) {
+                (true, Action::Reject) => verdict.rejected.push(rule),
+                (true, Action::Flag) => verdict.flagged.push(rule),
+                _ => (),
+            }
+        }
+        verdict
+    }
+}
+
+fn is_error_page(title: &str, text: &str) -> bool {
+    let start = text
+        .split_whitespace()
+        .take(ERROR_PAGE_WORDS)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let (title, start) = (title.to_lowercase(), start.to_lowercase());
+    ERROR_PAGES
+        .iter()
+        .any(|p| title.contains(p) || start.contains(p))
+}
+
+/// false only when the languages of the title and the body are detected with confidence, and
+/// differ
+fn same_language(title: &str, news: &DbNews) -> bool {
+    let title = lang::detect(title).filter(|d| d.confidence >= MIN_LANG_CONFIDENCE);
+    let confident = news
+        .lang_confidence
+        .is_some_and(|c| c >= MIN_LANG_CONFIDENCE);
+    match (title, &news.lang) {
+        (Some(title), Some(body)) if confident => title.lang == *body,
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn news(title: &str, body: &str) -> DbNews {
+        let detected = lang::detect(body);
+        DbNews {
+            provider: "fr::test".into(),
+            date: Utc::now().into(),
+            title: title.to_string().into(),
+            html_body: format!("<p>{body}</p>").into(),
+            text_body: body.to_string().into(),
+            link: "https://a.fr/1".into(),
+            lang_confidence: detected.as_ref().map(|d| d.confidence),
+            lang: detected.map(|d| d.lang),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn it_rejects_or_flags_the_news() {
+        let validator = Validator::new(
+            serde_json::from_str(include_str!("../../validation.example.json")).unwrap(),
+        );
+        let body = "Les bénévoles du refuge ont soigné plus de cent hérissons blessés cette année, et ils cherchent maintenant de nouvelles familles pour les accueillir pendant l'hiver. ".repeat(3);
+        let good = news("Un refuge pour les hérissons ouvre ses portes", &body);
+        assert_eq!(validator.validate(&good), Verdict::default());
+
+        let verdict = validator.validate(&news(" ", "Access denied"));
+        assert_eq!(
+            verdict.rejected,
+            [Rule::MinWords, Rule::Title, Rule::ErrorPage]
+        );
+
+        let mut future = news("Un refuge pour les hérissons ouvre ses portes", &body);
+        future.date = (Utc::now() + Duration::days(2)).into();
+        assert_eq!(validator.validate(&future).rejected, [Rule::FutureDate]);
+
+        let english = news("Hedgehogs get a new shelter in the north of France", &body);
+        assert_eq!(
+            validator.validate(&english),
+            Verdict {
+                rejected: vec![],
+                flagged: vec![Rule::LanguageMatch]
+            }
+        );
+
+        let file: ValidationFile = serde_json::from_str(
+            r#"{ "min_words": 5, "rules": { "error_page": "flag", "title": "off" } }"#,
+        )
+        .unwrap();
+        let verdict = Validator::new(file).validate(&news("", "Access denied, are you a robot?"));
+        assert_eq!(verdict.rejected, []);
+        assert_eq!(verdict.flagged, [Rule::ErrorPage]);
+        assert!(
+            serde_json::from_str::<ValidationFile>(r#"{ "rules": { "length": "flag" } }"#).is_err()
+        );
+    }
+
+    #[test]
+    fn it_only_flags_without_a_file() {
+        let verdict =
+            Validator::new(ValidationFile::default()).validate(&news(" ", "Access denied"));
+        assert_eq!(verdict.rejected, []);
+        assert_eq!(
+            verdict.flagged,
+            [Rule::MinWords, Rule::Title, Rule::ErrorPage]
+        );
+    }
+}
@@ -0,0 +1,205 @@
+mod record;
+
+use std::{path::PathBuf, process::exit, sync::RwLock};
+
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use record::Record;
+use shared::{
+    config::{ConfigArgs, ConfigCommand},
+    ingest::{ingest_with, Ingested},
+    logging,
+    store::{self, NewsStore},
+};
+use tracing::{debug, error, info, warn};
+
+#[derive(Parser, Debug)]
+#[command(subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(flatten)]
+    config: ConfigArgs,
+    #[arg(
+        required = true,
+        help = "JSON lines (like `export` writes) or a JSON array of news, optionally .gz or .zst. `-` reads stdin"
+    )]
+    inputs: Vec<PathBuf>,
+    #[arg(
+        long,
+        help = "Provider of the news that have none, like archive::lemonde"
+    )]
+    provider: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Stats {
+    inserted: usize,
+    merged: usize,
+    /// invalid records, and those the store refused
+    rejected: usize,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let config = cli.config.loader("import")?.load();
+    let _logging = logging::init("import", "import=info", config.as_ref().ok())?;
+    let config = config.unwrap_or_else(|e| {
+        error!("config: {e:?}");
+        exit(1);
+    });
+    if let Some(Command::Config {
+        command: ConfigCommand::Check,
+    }) = cli.command
+    {
+        print!("{}", config.redacted_toml()?);
+        return Ok(());
+    }
+
+    let store = store::connect(&config).await?;
+    store::ensure_schema(store.as_ref(), &config).await?;
+    let seen_links = RwLock::new(store.seen_links().await?);
+
+    let mut stats = Stats::default();
+    for input in &cli.inputs {
+        let records = record::records(record::open(input)?)?;
+        let name = input.display().to_string();
+        import(
+            store.as_ref(),
+            &seen_links,
+            &name,
+            records,
+            cli.provider.as_deref(),
+            &mut stats,
+        )
+        .await;
+    }
+    info!(
+        inserted = stats.inserted,
+        merged = stats.merged,
+        rejected = stats.rejected,
+        "import done"
+    );
+    Ok(())
+}
+
+/// ingest the records like the fetcher does with the news of a source, keeping their ratings
+async fn import(
+    store: &dyn NewsStore,
+    seen_links: &RwLock<Vec<String>>,
+    name: &str,
+    records: impl Iterator<Item = (usize, Result<Record>)>,
+    provider: Option<&str>,
+    stats: &mut Stats,
+) {
+    for (line, record) in records {
+        let ingested = match record.and_then(|r| Ok((r.news(provider)?, r))) {
+            Ok((news, record)) => {
+                let link = news.link.clone();
+                ingest_with(store, seen_links, news, |db| record.restore(db))
+                    .await
+                    .map(|ingested| (ingested, link))
+            }
+            Err(e) => Err(e),
+        };
+        match ingested {
+            Ok((ingested, link)) => {
+                debug!(?ingested, link, "ingested");
+                match ingested {
+                    Ingested::Inserted => stats.inserted += 1,
+                    Ingested::Merged => stats.merged += 1,
+                    Ingested::Rejected => stats.rejected += 1,
+                }
+            }
+            Err(e) => {
+                warn!(file = name, line, error = %format!("{e:#}"), "rejected");
+                stats.rejected += 1;
+            }
+        }
+        let done = stats.inserted + stats.merged + stats.rejected;
+        if done.is_multiple_of(1000) {
+            info!(done, "news imported");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use shared::{
+        store::{NewsFilter, SqliteStore},
+        DbNews,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn it_imports_merges_and_rejects() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        store
+            .insert(&DbNews {
+                title: "known".into(),
+                link: "https://a.fr/known".into(),
+                provider: "fr::google".into(),
+                tags: vec!["fr".into()],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let seen_links = RwLock::new(store.seen_links().await.unwrap());
+        let input = concat!(
+            r#"{"date":"2024-05-01T08:00:00Z","title":"a","link":"https://a.fr/1","html_body":"<p>hello <script>x</script></p>","rating":80,"used":true,"tags":["fr"]}"#,
+            "\n",
+            r#"{"date":"2024-05-01T08:00:00Z","title":"b","link":"https://a.fr/known?utm_source=rss#top","tags":["cats"]}"#,
+            "\n",
+            r#"{"date":"2024-05-01T08:00:00Z","title":"a again","link":"https://a.fr/1?fbclid=42"}"#,
+            "\n",
+            r#"{"date":"2024-05-01T08:00:00Z","link":"https://a.fr/3"}"#,
+            "\n{\n",
+        );
+        let records = record::records(Box::new(Cursor::new(input))).unwrap();
+        let mut stats = Stats::default();
+        import(
+            &store,
+            &seen_links,
+            "test",
+            records,
+            Some("archive"),
+            &mut stats,
+        )
+        .await;
+        assert_eq!(
+            stats,
+            Stats {
+                inserted: 1,
+                merged: 2,
+                rejected: 2
+            }
+        );
+
+        let news = store.news(&NewsFilter::default()).await.unwrap();
+        let imported = news.iter().find(|n| n.link == "https://a.fr/1").unwrap();
+        assert_eq!(imported.provider, "archive");
+        // a provider without a region is its own region, like in the digest
+        assert_eq!(imported.tags, ["fr", "archive"]);
+        assert_eq!(imported.html_body, "<p>hello </p>");
+        assert_eq!(imported.rating, Some(80));
+        assert!(imported.used);
+        let known = news
+            .iter()
+            .find(|n| n.link == "https://a.fr/known")
+            .unwrap();
+        assert_eq!(known.tags, ["fr", "cats", "archive"]);
+    }
+}
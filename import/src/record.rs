@@ -0,0 +1,231 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, FixedOffset, Local};
+use flate2::read::MultiGzDecoder;
+use serde::Deserialize;
+use shared::{translate::Translation, DbNews, News};
+use url::Url;
+
+/// a news to import: a line of `export --format jsonl`, or the fields of `shared::News`
+#[derive(Debug, Deserialize)]
+pub struct Record {
+    pub provider: Option<String>,
+    pub date: Option<DateTime<FixedOffset>>,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub caption: String,
+    /// `body` of a `shared::News`, `html_body` of an export
+    #[serde(alias = "html_body")]
+    pub body: Option<String>,
+    /// only used without an html body, when it was left out of the export
+    pub text_body: Option<String>,
+    #[serde(default)]
+    pub link: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub rating: Option<u8>,
+    pub rating_travel: Option<u8>,
+    #[serde(default)]
+    pub used: bool,
+    #[serde(default)]
+    pub note: String,
+    #[serde(default)]
+    pub translations: BTreeMap<String, Translation>,
+    /// what the export found, the body is assessed again without it
+    #[serde(default)]
+    pub paywalled: Option<bool>,
+    #[serde(default)]
+    pub quality_flags: Option<Vec<String>>,
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+impl Record {
+    /// the news for [`shared::ingest`], or why it can't be imported. `provider` is used when
+    /// the record has none
+    pub fn news(&self, provider: Option<&str>) -> Result<News> {
+        let link = self.link.trim();
+        match Url::parse(link) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => (),
+            _ => bail!("invalid link `{link}`"),
+        }
+        if self.title.trim().is_empty() {
+            bail!("no title");
+        }
+        let Some(date) = self.date else {
+            bail!("no date");
+        };
+        let Some(provider) = self.provider.as_deref().or(provider) else {
+            bail!("no provider, pass --provider");
+        };
+        let body = match (&self.body, &self.text_body) {
+            (Some(html), _) => html.clone(),
+            (None, Some(text)) => format!("<p>{}</p>", escape(text)),
+            (None, None) => String::new(),
+        };
+        Ok(News {
+            provider: provider.to_string(),
+            date: date.with_timezone(&Local),
+            title: self.title.clone(),
+            caption: self.caption.clone(),
+            body,
+            link: link.to_string(),
+            tags: self.tags.clone(),
+            accessible_for_free: None,
+        })
+    }
+
+    /// keep what was known about the news, so it isn't rated again
+    pub fn restore(self, news: &mut DbNews) {
+        news.rating = self.rating;
+        news.rating_travel = self.rating_travel;
+        news.used = self.used;
+        news.note = self.note.into();
+        news.translations = self.translations;
+        if let Some(paywalled) = self.paywalled {
+            news.paywalled = paywalled;
+        }
+        if let Some(flags) = self.quality_flags {
+            news.quality_flags = flags;
+        }
+    }
+}
+
+/// the file, or stdin for `-`, decompressed when it ends with `.gz` or `.zst`
+pub fn open(path: &Path) -> Result<Box<dyn BufRead>> {
+    if path == Path::new("-") {
+        return Ok(Box::new(BufReader::new(io::stdin())));
+    }
+    let file = File::open(path).with_context(|| format!("opening {path:?}"))?;
+    let reader: Box<dyn Read> = match path.extension().and_then(|e| e.to_str()) {
+        Some("gz") => Box::new(MultiGzDecoder::new(file)),
+        Some("zst") => Box::new(zstd::Decoder::new(file)?),
+        _ => Box::new(file),
+    };
+    Ok(Box::new(BufReader::new(reader)))
+}
+
+/// the records of a JSON array, or of JSON lines, with their line or index to report errors.
+/// a JSON array is read at once, JSON lines one by one
+pub fn records(
+    mut reader: Box<dyn BufRead>,
+) -> Result<Box<dyn Iterator<Item = (usize, Result<Record>)>>> {
+    let array = loop {
+        let buf = reader.fill_buf()?;
+        match buf.iter().position(|b| !b.is_ascii_whitespace()) {
+            Some(i) => {
+                let array = buf[i] == b'[';
+                reader.consume(i);
+                break array;
+            }
+            None if buf.is_empty() => return Ok(Box::new(std::iter::empty())),
+            None => {
+                let len = buf.len();
+                reader.consume(len);
+            }
+        }
+    };
+    if array {
+        let values: Vec<serde_json::Value> =
+            serde_json::from_reader(reader).context("reading the JSON array")?;
+        return Ok(Box::new(values.into_iter().enumerate().map(
+            |(i, value)| (i + 1, serde_json::from_value(value).map_err(Into::into)),
+        )));
+    }
+    Ok(Box::new(
+        reader
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+            .map(|(i, line)| {
+                let record = line
+                    .map_err(anyhow::Error::from)
+                    .and_then(|line| Ok(serde_json::from_str(&line)?));
+                (i + 1, record)
+            }),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn read(input: &'static str) -> Vec<(usize, Result<Record>)> {
+        records(Box::new(Cursor::new(input))).unwrap().collect()
+    }
+
+    #[test]
+    fn it_reads_exported_lines() {
+        let records = read(concat!(
+            r#"{"id":"news:1","date":"2024-05-01T08:00:00+02:00","title":"a","link":"https://a.fr/1","provider":"fr::google","html_body":"<p>x</p>","rating":80,"tags":["fr"],"paywalled":true,"quality_flags":["short_body"]}"#,
+            "\n\n",
+            "not json\n",
+            r#"{"date":"2024-05-01T08:00:00Z","title":"b","link":"https://a.fr/2","text_body":"1 < 2"}"#,
+            "\n",
+        ));
+        assert_eq!(records.len(), 3);
+        let (line, first) = &records[0];
+        assert_eq!(*line, 1);
+        let first = first.as_ref().unwrap();
+        assert_eq!(first.rating, Some(80));
+        assert_eq!(first.news(None).unwrap().body, "<p>x</p>");
+        assert_eq!(first.paywalled, Some(true));
+        assert_eq!(
+            first.quality_flags.as_deref(),
+            Some(&["short_body".to_string()][..])
+        );
+        assert_eq!(records[1].0, 3);
+        assert!(records[1].1.is_err());
+
+        let (line, last) = &records[2];
+        assert_eq!(*line, 4);
+        let last = last.as_ref().unwrap();
+        assert!(last.news(None).is_err());
+        assert_eq!(last.paywalled, None);
+        let news = last.news(Some("archive")).unwrap();
+        assert_eq!(news.provider, "archive");
+        assert_eq!(news.body, "<p>1 &lt; 2</p>");
+    }
+
+    #[test]
+    fn it_reads_an_array_of_news() {
+        let records = read(
+            r#"  [
+            {"provider":"be::rtbf","date":"2024-05-01T08:00:00Z","title":"a","caption":"c","body":"<p>b</p>","link":"https://a.be/1","tags":["be"]},
+            {"provider":"be::rtbf","date":"2024-05-01T08:00:00Z","title":"","link":"https://a.be/2"},
+            {"provider":"be::rtbf","date":"2024-05-01T08:00:00Z","title":"c","link":"ftp://a.be/3"},
+            {"provider":"be::rtbf","title":"d","link":"https://a.be/4"}
+        ]"#,
+        );
+        let errors: Vec<String> = records
+            .into_iter()
+            .map(|(i, r)| match r.unwrap().news(None) {
+                Ok(_) => format!("{i}: ok"),
+                Err(e) => format!("{i}: {e}"),
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                "1: ok",
+                "2: no title",
+                "3: invalid link `ftp://a.be/3`",
+                "4: no date"
+            ]
+        );
+        assert!(read(" \n").is_empty());
+    }
+}
@@ -1,32 +1,38 @@
-use anyhow::{Context, Result};
+use anyhow::Result;
 use async_openai::{config::OpenAIConfig, Client as ChatClient};
-use env_logger::Builder;
+use clap::{Parser, Subcommand};
 use futures::future::select_all;
-use log::{error, info, trace, warn};
-use shared::Telegram;
-use shared::{config::Config, db_news::DbNews};
-use std::env;
+use shared::{
+    config::{ConfigArgs, ConfigCommand},
+    db_news::DbNews,
+    logging,
+    metrics::{self, Exporter},
+    quality::Paywalled,
+    store, Notify,
+};
 use std::process::exit;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
-use surrealdb::engine::remote::http::Http;
-use surrealdb::{opt::auth::Root, Surreal};
 use tokio::sync::Semaphore;
 use tokio::task::JoinHandle;
+use tracing::{error, info, info_span, trace, warn, Instrument};
 
-async fn retrieve_db_news<T: surrealdb::Connection>(db: &Surreal<T>) -> Result<Vec<DbNews>> {
-    let db_news: Vec<DbNews> = db
-        .query(
-            "return select * from news
-where rating == none
-AND date >= time::now() - 1w
-AND !string::contains(note, 'error rating')
-ORDER BY date DESC limit 500",
-        )
-        .await?
-        .take(0)?;
-    Ok(db_news)
+#[derive(Parser, Debug)]
+struct Cli {
+    #[command(flatten)]
+    config: ConfigArgs,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
 }
 
 fn sleep_check(running: &AtomicBool, duration: Duration) {
@@ -42,9 +48,20 @@ fn sleep_check(running: &AtomicBool, duration: Duration) {
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    Builder::new()
-        .parse_filters(&env::var("RUST_LOG").unwrap_or("rater=trace".into()))
-        .init();
+    let cli = Cli::parse();
+    let config = cli.config.loader("rater")?.load();
+    let _logging = logging::init("rater", "rater=trace", config.as_ref().ok())?;
+    let config = config.unwrap_or_else(|e| {
+        error!("config: {e:?}");
+        exit(1);
+    });
+    if let Some(Command::Config {
+        command: ConfigCommand::Check,
+    }) = cli.command
+    {
+        print!("{}", config.redacted_toml()?);
+        return Ok(());
+    }
 
     let running = Arc::new(AtomicBool::new(true));
     let r = running.clone();
@@ -60,37 +77,40 @@ async fn main() -> Result<()> {
     })
     .expect("Error setting Ctrl-C handler");
 
-    let config = Config::load(".env").unwrap_or_else(|e| {
-        error!(".env: {:?}", e);
-        exit(1);
-    });
-
-    let db = Surreal::new::<Http>(&config.surrealdb_host).await?;
-    db.signin(Root {
-        username: &config.db_user,
-        password: &config.db_password,
-    })
-    .await
-    .context("connecting to surrealdb")?;
-    db.use_ns("news").use_db("news").await?;
-    let db = Arc::new(db);
+    let store = store::connect(&config).await?;
+    let applied = store::ensure_schema(store.as_ref(), &config).await?;
+    if !applied.is_empty() {
+        info!("applied migrations {applied:?}");
+    }
 
-    let openai =
-        ChatClient::with_config(OpenAIConfig::default().with_api_key(&config.openai_api_key));
+    // the key is checked by `Config::validate`
+    let openai_api_key = config.openai_api_key.clone().unwrap_or_default();
+    let openai = ChatClient::with_config(OpenAIConfig::default().with_api_key(openai_api_key));
     let openai = Arc::new(openai);
 
-    let telegram = Telegram::new(config.telegram_token.clone(), config.telegram_id);
-    let telegram = Arc::new(telegram);
+    let notify = Notify::from_config(&config, "rater")?;
+    let exporter = Exporter::start(&config, "rater")?;
     let sem = Arc::new(Semaphore::new(config.parallel_rating));
     let prompt = include_str!("../../rating-prompt.md");
+    let translate_languages = Arc::new(config.translate_languages.clone().unwrap_or_default());
+    let translate_body = config.translate_body.unwrap_or(false);
+    // checked by `Config::validate`
+    let paywalled = Paywalled::parse(config.rater_paywalled.as_deref())?;
 
     loop {
         if !running.load(Ordering::Relaxed) {
+            notify.flush().await;
+            exporter.flush();
             return Ok(());
         }
         let total_news;
         let mut news_done = 0;
-        let db_news = retrieve_db_news(&db).await;
+        let db_news = store.unrated(config.rater_languages.as_deref()).await;
+        // the whole backlog, `unrated` only returns the first batch of it
+        match store.backlog(config.rater_languages.as_deref()).await {
+            Ok(backlog) => metrics::RATER_BACKLOG.set(backlog as i64),
+            Err(e) => warn!(error = %format!("{e:#}"), "counting the backlog"),
+        }
         let db_news = match db_news {
             Ok(news) if news.is_empty() => {
                 info!("no news to process");
@@ -102,7 +122,11 @@ async fn main() -> Result<()> {
                 info!("got {} news to process", total_news);
                 news
             }
-            Err(e) => return Err(e.context("failed to get news from db")),
+            Err(e) => {
+                notify.error(format!("failed to get news from db: {e:#}"));
+                notify.flush().await;
+                return Err(e.context("failed to get news from db"));
+            }
         };
 
         let mut handles = Vec::with_capacity(db_news.len());
@@ -111,59 +135,102 @@ async fn main() -> Result<()> {
             let id = news.id.clone().expect("no id wtf");
             let sem = sem.clone();
             let openai = openai.clone();
-            let db = db.clone();
+            let store = store.clone();
             let running = running.clone();
-            let telegram = telegram.clone();
-            let handle: JoinHandle<Result<Option<DbNews>>> = tokio::spawn(async move {
+            let notify = notify.clone();
+            let translate_languages = translate_languages.clone();
+            let span =
+                info_span!("article", id = %id, provider = %news.provider, link = %news.link);
+            let task = async move {
                 let _permit = sem.acquire().await;
                 if !running.load(Ordering::Relaxed) {
                     return Ok(None);
                 }
-                trace!("processing {}, {}", id.id, news.link);
-                let rating = match news.rate(&openai, prompt).await {
-                    Ok(rating) => Some(rating),
-                    Err(e) if e.to_string().to_lowercase().contains("bad gateway") => {
-                        error!("bad gateway: {:?}", e.to_string());
-                        dbg!(&e);
-                        news.rating = None;
-                        None
-                    }
-                    Err(e) if e.to_string().to_lowercase().contains("service unavailable") => {
-                        error!("service unavailable: {:?}", e.to_string());
-                        dbg!(&e);
-                        news.rating = None;
-                        None
+                trace!("processing");
+                let rating = if news.paywalled && paywalled == Paywalled::Skip {
+                    info!("paywalled, not rating it");
+                    metrics::RATINGS.with_label_values(&["skipped"]).inc();
+                    news.rating = Some(0);
+                    let newline = if news.note.is_empty() { "" } else { "\n" };
+                    news.note = format!("{}{newline}skipped: paywalled", news.note).into();
+                    None
+                } else {
+                    let rating = news.rate(&openai, prompt).await;
+                    if let Err(e) = &rating {
+                        metrics::LLM_ERRORS
+                            .with_label_values(&["rate", metrics::llm_error_class(e)])
+                            .inc();
                     }
-                    Err(e) => {
-                        error!("rating {id}: {e:?}");
-                        news.rating = Some(0);
-                        let newline = if news.note.is_empty() { "" } else { "\n" };
-                        news.note = format!("{}{newline}rating failed: '{e}'", news.note).into();
-                        // only send telegram notif if it's not a "sorry" message
-                        if !e.to_string().to_lowercase().contains("i'm sorry") {
-                            telegram
-                                .send(format!("rater: {id} {} rating failed: {e:?}", news.link))?;
+                    match rating {
+                        Ok(rating) => {
+                            metrics::RATINGS.with_label_values(&["rated"]).inc();
+                            Some(rating)
+                        }
+                        Err(e) if e.to_string().to_lowercase().contains("bad gateway") => {
+                            error!(error = %format!("{e:#}"), "bad gateway, retrying next batch");
+                            metrics::RATINGS.with_label_values(&["retry"]).inc();
+                            news.rating = None;
+                            None
+                        }
+                        Err(e) if e.to_string().to_lowercase().contains("service unavailable") => {
+                            error!(error = %format!("{e:#}"), "service unavailable, retrying next batch");
+                            metrics::RATINGS.with_label_values(&["retry"]).inc();
+                            news.rating = None;
+                            None
+                        }
+                        Err(e) => {
+                            error!(error = %format!("{e:#}"), "rating failed");
+                            metrics::RATINGS.with_label_values(&["failed"]).inc();
+                            news.rating = Some(0);
+                            let newline = if news.note.is_empty() { "" } else { "\n" };
+                            news.note =
+                                format!("{}{newline}rating failed: '{e}'", news.note).into();
+                            // only notify if it's not a "sorry" message
+                            if !e.to_string().to_lowercase().contains("i'm sorry") {
+                                notify.warning(format!("{id} {} rating failed: {e:#}", news.link));
+                            }
+                            None
                         }
-                        None
                     }
                 };
-                info!("{id} rating: {rating:?}");
-                match news.save(&db).await {
+                if rating.is_some() && news.paywalled && paywalled == Paywalled::Downweight {
+                    let weight = |r: u8| (f64::from(r) * Paywalled::WEIGHT).round() as u8;
+                    news.rating = news.rating.map(weight);
+                    news.rating_travel = news.rating_travel.map(weight);
+                    info!("paywalled, ratings down-weighted");
+                }
+                info!(?rating, "rated");
+                if rating.is_some() && !translate_languages.is_empty() {
+                    match news
+                        .translate(&openai, &translate_languages, translate_body)
+                        .await
+                    {
+                        Ok(langs) if !langs.is_empty() => info!(?langs, "translated"),
+                        Ok(_) => (),
+                        Err(e) => {
+                            metrics::LLM_ERRORS
+                                .with_label_values(&["translate", metrics::llm_error_class(&e)])
+                                .inc();
+                            warn!(error = %format!("{e:#}"), "translating");
+                        }
+                    }
+                }
+                match store.save_rating(&news).await {
                     Ok(_) => Ok(Some(news)),
                     Err(e) => {
-                        warn!("saving {id} failed one time: {e:#?}");
-                        // telegram.send(format!("rater: saving {id} failed one time: {e:#?}"))?;
+                        warn!(error = %format!("{e:#}"), "saving failed one time");
                         tokio::time::sleep(Duration::from_secs(5)).await;
-                        let e = match news.save(&db).await {
+                        let e = match store.save_rating(&news).await {
                             Ok(_) => return Ok(Some(news)),
                             Err(e) => e,
                         };
-                        error!("saving {id} with {rating:?} second time: {e:#?}");
-                        telegram.send(format!("rater: re-saving {id} failed: {e:#?}"))?;
+                        error!(?rating, error = %format!("{e:#}"), "saving failed a second time");
+                        notify.error(format!("re-saving {id} failed: {e:#}"));
                         Err(e)
                     }
                 }
-            });
+            };
+            let handle: JoinHandle<Result<Option<DbNews>>> = tokio::spawn(task.instrument(span));
             handles.push(handle);
         }
         while !handles.is_empty() {
@@ -172,9 +239,8 @@ async fn main() -> Result<()> {
             match handle? {
                 Err(e) => {
                     running.store(false, Ordering::Relaxed);
-                    if let Err(e) = telegram.send(format!("rater: thread error: {e}")) {
-                        error!("TelegramError: {}", e);
-                    }
+                    notify.error(format!("thread error: {e}"));
+                    notify.flush().await;
                     return Err(e);
                 }
                 Ok(Some(news)) => {
@@ -1,43 +1,434 @@
 use core::fmt;
-use std::path::PathBuf;
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
 
-use anyhow::{Context, Result};
-use serde::Deserialize;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, Default)]
 #[serde(rename_all = "lowercase")]
 pub struct Config {
-    pub db_user: String,
-    pub db_password: String,
-    pub openai_api_key: String,
+    pub db_user: Option<String>,
+    pub db_password: Option<String>,
+    pub openai_api_key: Option<String>,
     pub article_parser_url: String,
     pub surrealdb_host: String,
     pub parallel_rating: usize,
-    pub telegram_token: String,
-    pub telegram_id: i64,
+    pub telegram_token: Option<String>,
+    pub telegram_id: Option<i64>,
+    pub telegram_api: Option<String>,
     pub chrome_concurrent: Option<usize>,
     pub chrome_data_dir: Option<PathBuf>,
     pub chrome_headless: Option<bool>,
+    pub chrome_pool_size: Option<usize>,
+    pub chrome_max_uses: Option<usize>,
+    pub translate_languages: Option<Vec<String>>,
+    pub translate_body: Option<bool>,
+    pub rater_languages: Option<Vec<String>>,
+    pub rater_paywalled: Option<String>,
+    pub retention_file: Option<PathBuf>,
+    pub politeness_file: Option<PathBuf>,
+    pub network_file: Option<PathBuf>,
+    pub consent_file: Option<PathBuf>,
+    pub validation_file: Option<PathBuf>,
+    pub store: Option<String>,
+    pub sqlite_path: Option<PathBuf>,
+    pub auto_migrate: Option<bool>,
+    pub notify_file: Option<PathBuf>,
+    pub notify_window: Option<u64>,
+    pub digest_top: Option<usize>,
+    pub digest_templates: Option<PathBuf>,
+    pub feeds_listen: Option<String>,
+    pub feeds_base_url: Option<String>,
+    pub api_listen: Option<String>,
+    pub metrics_listen: Option<String>,
+    pub metrics_push_url: Option<String>,
+    pub log_format: Option<String>,
+    pub otlp_endpoint: Option<String>,
 }
+
+/// used when no layer sets them
+const DEFAULTS: &[(&str, &str)] = &[
+    ("article_parser_url", "http://127.0.0.1:8080"),
+    ("surrealdb_host", "127.0.0.1:8000"),
+    ("parallel_rating", "10"),
+];
+
+/// never printed
+const SECRETS: &[&str] = &["db_user", "db_password", "openai_api_key", "telegram_token"];
+
+/// sections of the config file, one per binary
+pub const SECTIONS: &[&str] = &[
+    "fetcher", "rater", "digest", "feeds", "api", "export", "import",
+];
+
+/// the setting names, which are the fields of [`Config`]
+fn keys() -> Vec<String> {
+    match serde_json::to_value(Config::default()) {
+        Ok(serde_json::Value::Object(map)) => map.into_iter().map(|(k, _)| k).collect(),
+        _ => unreachable!("Config serializes to a map"),
+    }
+}
+
+type Layer = BTreeMap<String, String>;
+
+/// builds a [`Config`] from layers, each one overriding the previous:
+/// defaults, the top of the TOML file, its `[<binary>]` section, the environment
+/// (including the env file), then the command line.
+/// any setting `NAME` can instead be read from the file at `NAME_FILE`, for docker secrets.
+pub struct Loader {
+    section: Option<&'static str>,
+    config_file: Option<PathBuf>,
+    env_file: Option<PathBuf>,
+    env: Option<Vec<(String, String)>>,
+    overrides: Vec<(String, String)>,
+}
+
+impl Loader {
+    /// `section` is the binary, see [`SECTIONS`]
+    pub fn new(section: Option<&'static str>) -> Loader {
+        Loader {
+            section,
+            config_file: None,
+            env_file: None,
+            env: None,
+            overrides: Vec::new(),
+        }
+    }
+
+    /// without it, `CONFIG_FILE` or `config.toml` if it exists
+    pub fn config_file(mut self, path: impl Into<PathBuf>) -> Loader {
+        self.config_file = Some(path.into());
+        self
+    }
+
+    /// without it, `.env` if it exists
+    pub fn env_file(mut self, path: impl Into<PathBuf>) -> Loader {
+        self.env_file = Some(path.into());
+        self
+    }
+
+    /// use these variables instead of the process environment, and no env file
+    pub fn env_vars(mut self, vars: impl IntoIterator<Item = (String, String)>) -> Loader {
+        self.env = Some(vars.into_iter().collect());
+        self
+    }
+
+    pub fn set(mut self, key: &str, value: impl ToString) -> Loader {
+        self.overrides.push((key.to_lowercase(), value.to_string()));
+        self
+    }
+
+    pub fn load(self) -> Result<Config> {
+        let keys = keys();
+        let env = match self.env {
+            Some(env) => env,
+            None => {
+                match &self.env_file {
+                    Some(path) => {
+                        dotenvy::from_path(path).with_context(|| format!("loading {path:?}"))?;
+                    }
+                    None if Path::new(".env").exists() => {
+                        dotenvy::from_path(".env").context("loading .env")?;
+                    }
+                    None => (),
+                }
+                env::vars().collect()
+            }
+        };
+
+        let mut merged: Layer = DEFAULTS
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+
+        let config_file = self.config_file.or_else(|| {
+            env.iter()
+                .find(|(k, _)| k == "CONFIG_FILE")
+                .map(|(_, v)| PathBuf::from(v))
+                .or_else(|| Some("config.toml".into()).filter(|p: &PathBuf| p.exists()))
+        });
+        if let Some(path) = config_file {
+            let (top, section) = toml_layers(&path, self.section)
+                .with_context(|| format!("config file {path:?}"))?;
+            merged.extend(resolve(top, &keys).with_context(|| format!("{path:?}"))?);
+            merged.extend(resolve(section, &keys).with_context(|| format!("{path:?}"))?);
+        }
+
+        let env: Layer = env
+            .into_iter()
+            .map(|(k, v)| (k.to_lowercase(), v))
+            .filter(|(k, _)| is_key(k, &keys))
+            .collect();
+        merged.extend(resolve(env, &keys).context("environment")?);
+        merged
+            .extend(resolve(self.overrides.into_iter().collect(), &keys).context("command line")?);
+
+        let config: Config = envy::from_iter(merged).context("invalid setting")?;
+        config.validate(self.section)?;
+        Ok(config)
+    }
+}
+
+/// a setting, or the `_FILE` variant of one
+fn is_key(key: &str, keys: &[String]) -> bool {
+    keys.iter().any(|k| k == key)
+        || key
+            .strip_suffix("_file")
+            .is_some_and(|k| keys.iter().any(|known| known == k))
+}
+
+/// check the names of a layer and replace the `NAME_FILE` entries by the content of the file
+fn resolve(layer: Layer, keys: &[String]) -> Result<Layer> {
+    let mut resolved = Layer::new();
+    for (key, value) in &layer {
+        if keys.contains(key) {
+            resolved.insert(key.clone(), value.clone());
+            continue;
+        }
+        let Some(name) = key
+            .strip_suffix("_file")
+            .filter(|k| keys.iter().any(|known| known == k))
+        else {
+            bail!("unknown setting `{key}`");
+        };
+        if layer.contains_key(name) {
+            bail!("both `{name}` and `{key}` are set");
+        }
+        let content =
+            fs::read_to_string(value).with_context(|| format!("{key}: reading {value}"))?;
+        resolved.insert(
+            name.to_string(),
+            content.trim_end_matches(['\n', '\r']).to_string(),
+        );
+    }
+    Ok(resolved)
+}
+
+/// the top level of the file, and the section of this binary
+fn toml_layers(path: &Path, section: Option<&str>) -> Result<(Layer, Layer)> {
+    let content = fs::read_to_string(path)?;
+    let table: toml::Table = toml::from_str(&content)?;
+    let mut top = Layer::new();
+    let mut own = Layer::new();
+    for (key, value) in table {
+        match value {
+            toml::Value::Table(table) => {
+                if !SECTIONS.contains(&key.as_str()) {
+                    bail!("unknown section `[{key}]`, expected one of {SECTIONS:?}");
+                }
+                if Some(key.as_str()) != section {
+                    continue;
+                }
+                for (key, value) in table {
+                    let value = toml_to_env(&value).with_context(|| key.clone())?;
+                    own.insert(key, value);
+                }
+            }
+            value => {
+                top.insert(key.clone(), toml_to_env(&value).context(key)?);
+            }
+        }
+    }
+    Ok((top, own))
+}
+
+/// the same format as an environment variable, lists are comma separated
+fn toml_to_env(value: &toml::Value) -> Result<String> {
+    Ok(match value {
+        toml::Value::String(s) => s.clone(),
+        toml::Value::Integer(i) => i.to_string(),
+        toml::Value::Float(f) => f.to_string(),
+        toml::Value::Boolean(b) => b.to_string(),
+        toml::Value::Datetime(d) => d.to_string(),
+        toml::Value::Array(values) => values
+            .iter()
+            .map(toml_to_env)
+            .collect::<Result<Vec<_>>>()?
+            .join(","),
+        toml::Value::Table(_) => bail!("nested tables are not supported"),
+    })
+}
+
+/// one of the `*_FILE` settings, a JSON file read as a whole
+pub fn load_json<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<T> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path).with_context(|| format!("reading {path:?}"))?;
+    serde_json::from_str(&content).with_context(|| format!("parsing {path:?}"))
+}
+
 impl Config {
+    /// only reads an env file, see [`Loader`] for the other layers
     pub fn load(path: &str) -> Result<Config> {
-        dotenvy::from_filename(path).context("dotenvy")?;
-        let config: Config = envy::from_env().context("envy")?;
-        Ok(config)
+        Loader::new(None).env_file(path).load()
+    }
+
+    /// every problem at once, instead of failing on the first one when it's used
+    pub fn validate(&self, section: Option<&str>) -> Result<()> {
+        let mut errors = Vec::new();
+        match self.store.as_deref().unwrap_or("surrealdb") {
+            "surrealdb" => {
+                for (name, value) in [
+                    ("DB_USER", &self.db_user),
+                    ("DB_PASSWORD", &self.db_password),
+                ] {
+                    if value.as_deref().unwrap_or_default().is_empty() {
+                        errors.push(format!(
+                            "{name} (or {name}_FILE) is required with the surrealdb store"
+                        ));
+                    }
+                }
+            }
+            "sqlite" => (),
+            other => errors.push(format!(
+                "STORE is `{other}`, expected `surrealdb` or `sqlite`"
+            )),
+        }
+        if self.telegram_token.is_some() != self.telegram_id.is_some() {
+            errors.push("TELEGRAM_TOKEN and TELEGRAM_ID go together".to_string());
+        }
+        for (name, value) in [
+            ("CHROME_CONCURRENT", self.chrome_concurrent),
+            ("CHROME_POOL_SIZE", self.chrome_pool_size),
+            ("CHROME_MAX_USES", self.chrome_max_uses),
+        ] {
+            if value == Some(0) {
+                errors.push(format!("{name} can't be 0"));
+            }
+        }
+        if let Err(e) = crate::logging::Format::parse(self.log_format.as_deref()) {
+            errors.push(e.to_string());
+        }
+        if let Err(e) = crate::quality::Paywalled::parse(self.rater_paywalled.as_deref()) {
+            errors.push(e.to_string());
+        }
+        if self.notify_window == Some(0) {
+            errors.push("NOTIFY_WINDOW can't be 0".to_string());
+        }
+        if self.digest_top == Some(0) {
+            errors.push("DIGEST_TOP can't be 0".to_string());
+        }
+        if let Some(dir) = self.digest_templates.as_ref().filter(|p| !p.is_dir()) {
+            errors.push(format!("DIGEST_TEMPLATES: {dir:?} isn't a directory"));
+        }
+        for (name, languages) in [
+            ("TRANSLATE_LANGUAGES", &self.translate_languages),
+            ("RATER_LANGUAGES", &self.rater_languages),
+        ] {
+            for lang in languages.iter().flatten() {
+                if lang.len() != 2 || !lang.chars().all(|c| c.is_ascii_lowercase()) {
+                    errors.push(format!(
+                        "{name}: `{lang}` is not a 2 letters ISO 639-1 code"
+                    ));
+                }
+            }
+        }
+        for (name, path) in [
+            ("RETENTION_FILE", &self.retention_file),
+            ("NOTIFY_FILE", &self.notify_file),
+            ("POLITENESS_FILE", &self.politeness_file),
+            ("NETWORK_FILE", &self.network_file),
+            ("CONSENT_FILE", &self.consent_file),
+            ("VALIDATION_FILE", &self.validation_file),
+        ] {
+            if let Some(path) = path.as_ref().filter(|p| !p.is_file()) {
+                errors.push(format!("{name}: {path:?} doesn't exist"));
+            }
+        }
+        if section == Some("rater") {
+            if self
+                .openai_api_key
+                .as_deref()
+                .unwrap_or_default()
+                .is_empty()
+            {
+                errors.push(
+                    "OPENAI_API_KEY (or OPENAI_API_KEY_FILE) is required by the rater".to_string(),
+                );
+            }
+            if self.parallel_rating == 0 {
+                errors.push("PARALLEL_RATING can't be 0".to_string());
+            }
+        }
+        if errors.is_empty() {
+            return Ok(());
+        }
+        Err(anyhow!("invalid config:\n  {}", errors.join("\n  ")))
+    }
+
+    /// the effective settings with the secrets hidden, unset ones are left out
+    pub fn redacted(&self) -> BTreeMap<String, serde_json::Value> {
+        let serde_json::Value::Object(map) = serde_json::to_value(self).unwrap() else {
+            unreachable!("Config serializes to a map")
+        };
+        map.into_iter()
+            .filter(|(_, v)| !v.is_null())
+            .map(|(k, v)| match (SECRETS.contains(&k.as_str()), v) {
+                (true, serde_json::Value::String(s)) => (k, "*".repeat(s.len()).into()),
+                (_, v) => (k, v),
+            })
+            .collect()
+    }
+
+    /// for `config check`
+    pub fn redacted_toml(&self) -> Result<String> {
+        Ok(toml::to_string(&self.redacted())?)
     }
 }
+
 impl fmt::Debug for Config {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "Config {{\n    db_user: {}\n    db_password: {}\n    openai_api_key: {}\n    article_parser_url: {}\n    surrealdb_url: {}\n    chrome_concurrent: {:?}\n    chrome_data_dir: {:?}\n}}",
-            "*".repeat(self.db_user.len()),
-            "*".repeat(self.db_password.len()),
-            "*".repeat(self.openai_api_key.len()),
-            self.article_parser_url,
-            self.surrealdb_host,
-            self.chrome_concurrent,
-            self.chrome_data_dir,
-        )
+        let mut s = f.debug_struct("Config");
+        for (key, value) in &self.redacted() {
+            s.field(key, value);
+        }
+        s.finish()
     }
 }
+
+/// the config flags shared by every binary
+#[derive(clap::Args, Debug)]
+pub struct ConfigArgs {
+    #[arg(
+        long,
+        short,
+        help = "TOML config file, by default CONFIG_FILE or config.toml if it exists"
+    )]
+    pub config: Option<PathBuf>,
+    #[arg(long, help = "Env file to load, by default .env if it exists")]
+    pub env_file: Option<PathBuf>,
+    #[arg(
+        long = "set",
+        value_name = "KEY=VALUE",
+        help = "Override a setting, like --set chrome_concurrent=2"
+    )]
+    pub set: Vec<String>,
+}
+
+impl ConfigArgs {
+    pub fn loader(&self, section: &'static str) -> Result<Loader> {
+        let mut loader = Loader::new(Some(section));
+        if let Some(path) = &self.config {
+            loader = loader.config_file(path);
+        }
+        if let Some(path) = &self.env_file {
+            loader = loader.env_file(path);
+        }
+        for set in &self.set {
+            let (key, value) = set
+                .split_once('=')
+                .with_context(|| format!("--set {set}: expected KEY=VALUE"))?;
+            loader = loader.set(key.trim(), value.trim());
+        }
+        Ok(loader)
+    }
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Validate the configuration and print the effective settings, secrets hidden
+    Check,
+}
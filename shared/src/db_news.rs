@@ -8,8 +8,10 @@ use async_openai::{
     Client as ChatClient,
 };
 use serde::{Deserialize, Serialize};
-use std::borrow::Cow;
-use surrealdb::Surreal;
+use std::{borrow::Cow, collections::BTreeMap, time::Instant};
+use tracing::warn;
+
+use crate::{metrics, translate::Translation};
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct DbNews {
@@ -26,19 +28,21 @@ pub struct DbNews {
     pub tags: Vec<String>,
     pub title: Cow<'static, str>,
     pub used: bool,
+    /// detected language of `text_body`, see [`crate::lang::detect`]
+    pub lang: Option<String>,
+    pub lang_confidence: Option<f64>,
+    /// keyed by target language
+    #[serde(default)]
+    pub translations: BTreeMap<String, Translation>,
+    /// only the teaser of the article is there, see [`crate::quality`]
+    #[serde(default)]
+    pub paywalled: bool,
+    /// the heuristics of [`crate::quality`] that matched
+    #[serde(default)]
+    pub quality_flags: Vec<String>,
 }
 
 impl DbNews {
-    pub async fn save<T: surrealdb::Connection>(&self, db: &Surreal<T>) -> Result<DbNews> {
-        let id = self.id.clone().unwrap();
-        let news = db
-            .update::<Option<DbNews>>(("news", id))
-            .content(self)
-            .await
-            .context("surrealdb error")?
-            .ok_or(anyhow!("no news found"))?;
-        Ok(news)
-    }
     pub async fn rate(
         &mut self,
         client: &ChatClient<OpenAIConfig>,
@@ -80,11 +84,13 @@ impl DbNews {
             .build()
             .unwrap();
 
+        let started = Instant::now();
         let response = client
             .chat() // Get the API "group" (completions, images, etc.) from the client
             .create(request) // Make the API call in that "group"
             .await
             .context("created openai request")?;
+        metrics::observe_llm("rate", started, response.usage.as_ref());
         let choice = response
             .choices
             .first()
@@ -122,12 +128,17 @@ impl DbNews {
             .collect();
         // if response was truncated, remove the last unfinished tag
         if let Some(FinishReason::Length) = &choice.finish_reason {
-            println!("db_news.rate(): response was truncated");
+            warn!(link = %self.link, "rating response was truncated");
             _ = tags.pop();
         };
         self.rating = Some(ratings.0);
         self.rating_travel = Some(ratings.1);
-        self.tags.extend(tags.clone());
+        // a news rated again already has the tags of its last rating
+        for tag in &tags {
+            if !self.tags.contains(tag) {
+                self.tags.push(tag.clone());
+            }
+        }
         Ok((ratings.0, ratings.1, tags))
     }
 }
@@ -0,0 +1,149 @@
+use std::{borrow::Cow, sync::RwLock};
+
+use anyhow::Result;
+use chrono::DateTime;
+use url::Url;
+
+use crate::{extract_clean_text, lang, quality, sanitize_html, store::NewsStore, DbNews, News};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ingested {
+    Inserted,
+    /// the link was already known, only the tags were added to it
+    Merged,
+    /// a new news turned down by the check of [`ingest_checked`], neither saved nor marked as seen
+    Rejected,
+}
+
+/// query parameters only there to track where the reader came from
+const TRACKING_PARAMS: &[&str] = &[
+    "fbclid",
+    "gclid",
+    "dclid",
+    "msclkid",
+    "mc_cid",
+    "mc_eid",
+    "igshid",
+    "xtor",
+    "at_medium",
+    "at_campaign",
+    "ref_src",
+];
+
+fn is_tracking(param: &str) -> bool {
+    param.starts_with("utm_") || TRACKING_PARAMS.contains(&param)
+}
+
+/// the link news are deduplicated on: without its fragment and tracking parameters. a link
+/// without either is kept as is, so it still matches the links saved before
+#[must_use]
+pub fn canonical_link(link: &str) -> String {
+    let link = link.trim();
+    let Ok(mut url) = Url::parse(link) else {
+        return link.to_string();
+    };
+    let tracked = url.query_pairs().any(|(k, _)| is_tracking(&k));
+    if url.fragment().is_none() && !tracked {
+        return link.to_string();
+    }
+    url.set_fragment(None);
+    if tracked {
+        let query: Vec<(String, String)> = url
+            .query_pairs()
+            .filter(|(k, _)| !is_tracking(k))
+            .map(|(k, v)| (k.into_owned(), v.into_owned()))
+            .collect();
+        if query.is_empty() {
+            url.set_query(None);
+        } else {
+            url.query_pairs_mut().clear().extend_pairs(query);
+        }
+    }
+    url.into()
+}
+
+/// the region of a provider like `de::spiegel`, the part before `::`
+#[must_use]
+pub fn region_of(provider: &str) -> &str {
+    provider
+        .split_once("::")
+        .map_or(provider, |(region, _)| region)
+}
+
+/// save a news found by a source, tagged with the region of its provider. a link already in
+/// `seen_links` only gets its tags merged, otherwise the body is cleaned, the language detected,
+/// the paywalls looked for and the news inserted.
+/// the link is marked as seen even if saving fails, so the other sources don't retry it.
+pub async fn ingest(
+    store: &dyn NewsStore,
+    seen_links: &RwLock<Vec<String>>,
+    news: News,
+) -> Result<Ingested> {
+    ingest_with(store, seen_links, news, |_| ()).await
+}
+
+/// like [`ingest`], with `edit` setting what a source can't know before the news is inserted,
+/// like the rating of an imported news
+pub async fn ingest_with(
+    store: &dyn NewsStore,
+    seen_links: &RwLock<Vec<String>>,
+    news: News,
+    edit: impl FnOnce(&mut DbNews),
+) -> Result<Ingested> {
+    ingest_checked(store, seen_links, news, |db_news| {
+        edit(db_news);
+        true
+    })
+    .await
+}
+
+/// like [`ingest_with`], with `check` also telling whether a new news is inserted, once its body
+/// is cleaned. a link already seen only gets its tags merged without being checked, and a news
+/// turned down isn't marked as seen, another source can still find it whole
+pub async fn ingest_checked(
+    store: &dyn NewsStore,
+    seen_links: &RwLock<Vec<String>>,
+    mut news: News,
+    check: impl FnOnce(&mut DbNews) -> bool,
+) -> Result<Ingested> {
+    news.link = canonical_link(&news.link);
+    let region = region_of(&news.provider).to_string();
+    if !news.tags.contains(&region) {
+        news.tags.push(region);
+    }
+    let merging = seen_links.read().unwrap().contains(&news.link);
+    let result = if merging {
+        store.merge_tags(&news.link, &news.tags).await
+    } else {
+        let html_body = sanitize_html(&news.body);
+        let text_body = extract_clean_text(&html_body);
+        let detected = lang::detect(&text_body).or_else(|| lang::detect(&news.title));
+        let quality = quality::assess(&news.body, &text_body, news.accessible_for_free);
+        let mut db_news = DbNews {
+            title: news.title.into(),
+            link: Cow::Owned(news.link.clone()),
+            tags: news.tags,
+            html_body: html_body.into(),
+            text_body: text_body.into(),
+            provider: news.provider.into(),
+            date: DateTime::from(news.date).into(),
+            caption: news.caption.into(),
+            lang_confidence: detected.as_ref().map(|d| d.confidence),
+            lang: detected.map(|d| d.lang),
+            paywalled: quality.paywalled,
+            quality_flags: quality.flags,
+            ..Default::default()
+        };
+        if !check(&mut db_news) {
+            return Ok(Ingested::Rejected);
+        }
+        store.insert(&db_news).await
+    };
+    seen_links.write().unwrap().push(news.link);
+    result?;
+    Ok(if merging {
+        Ingested::Merged
+    } else {
+        Ingested::Inserted
+    })
+}
@@ -0,0 +1,33 @@
+use whatlang::Lang;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Detected {
+    /// ISO 639-1 code when we know it ("fr", "en"), ISO 639-3 otherwise
+    pub lang: String,
+    pub confidence: f64,
+}
+
+/// detect the language of a text. returns None if the text is too short or ambiguous to say
+#[must_use]
+pub fn detect(text: &str) -> Option<Detected> {
+    let info = whatlang::detect(text)?;
+    Some(Detected {
+        lang: code(info.lang()).to_string(),
+        confidence: info.confidence(),
+    })
+}
+
+/// whatlang only knows ISO 639-3, but everything else (config, web ui) uses the short codes
+fn code(lang: Lang) -> &'static str {
+    match lang {
+        Lang::Fra => "fr",
+        Lang::Eng => "en",
+        Lang::Spa => "es",
+        Lang::Deu => "de",
+        Lang::Ita => "it",
+        Lang::Por => "pt",
+        Lang::Nld => "nl",
+        Lang::Ara => "ar",
+        lang => lang.code(),
+    }
+}
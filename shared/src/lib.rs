@@ -1,10 +1,19 @@
 pub mod config;
 pub mod db_news;
-pub mod telegram;
+pub mod ingest;
+pub mod lang;
+pub mod logging;
+pub mod metrics;
+pub mod migrate;
+pub mod notify;
+pub mod quality;
+pub mod retention;
+pub mod store;
+pub mod translate;
 use chrono::{DateTime, Local};
 pub use config::Config;
 pub use db_news::DbNews;
-pub use telegram::Telegram;
+pub use notify::{telegram::Telegram, Notify};
 
 #[derive(Debug, Clone, Default)]
 pub struct News {
@@ -15,6 +24,8 @@ pub struct News {
     pub body: String,
     pub link: String,
     pub tags: Vec<String>,
+    /// the `isAccessibleForFree` of the page's JSON-LD, when it has one
+    pub accessible_for_free: Option<bool>,
 }
 
 #[must_use]
@@ -0,0 +1,122 @@
+//! logs of the binaries: `tracing` events and spans, printed as text or JSON lines, and
+//! optionally exported to an OpenTelemetry collector. `log` records of the dependencies are
+//! turned into events, so they get the fields of the current span too
+use std::{env, io};
+
+use anyhow::{bail, Context, Result};
+use opentelemetry::{trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+use tracing::Subscriber;
+use tracing_subscriber::{
+    fmt::MakeWriter, layer::SubscriberExt, registry::LookupSpan, util::SubscriberInitExt,
+    EnvFilter, Layer,
+};
+
+use crate::Config;
+
+/// the path the collector receives spans on, appended to `OTLP_ENDPOINT` when it's missing
+const TRACES_PATH: &str = "/v1/traces";
+
+/// how the events are printed, from `LOG_FORMAT`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    /// one JSON object per line, with the fields of the spans, for log shipping
+    Json,
+}
+
+impl Format {
+    pub fn parse(format: Option<&str>) -> Result<Format> {
+        match format.unwrap_or("text") {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            other => bail!("LOG_FORMAT is `{other}`, expected `text` or `json`"),
+        }
+    }
+}
+
+/// the layer printing the events to `writer`
+pub fn fmt_layer<S, W>(format: Format, writer: W) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer().with_writer(writer);
+    match format {
+        Format::Text => layer.boxed(),
+        Format::Json => layer
+            .json()
+            .flatten_event(true)
+            .with_current_span(false)
+            .with_span_list(true)
+            .boxed(),
+    }
+}
+
+/// the `/v1/traces` url of a collector, `endpoint` being its base url or already the full one
+pub fn traces_url(endpoint: &str) -> String {
+    let endpoint = endpoint.trim_end_matches('/');
+    if endpoint.ends_with(TRACES_PATH) {
+        endpoint.to_string()
+    } else {
+        format!("{endpoint}{TRACES_PATH}")
+    }
+}
+
+/// flushes the spans not exported yet when dropped, keep it until the end of `main`
+pub struct Guard(Option<SdkTracerProvider>);
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.0.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("exporting the last spans: {e}");
+            }
+        }
+    }
+}
+
+/// install the logger of `service`. `RUST_LOG` filters the events, `default_filter` without it.
+/// `config` is `None` when it failed to load, to still log why
+pub fn init(service: &'static str, default_filter: &str, config: Option<&Config>) -> Result<Guard> {
+    let filter = EnvFilter::try_new(env::var("RUST_LOG").unwrap_or(default_filter.into()))
+        .context("RUST_LOG")?;
+    let format = Format::parse(config.and_then(|c| c.log_format.as_deref()))?;
+
+    let provider = match config.and_then(|c| c.otlp_endpoint.as_deref()) {
+        Some(endpoint) => {
+            let exporter = SpanExporter::builder()
+                .with_http()
+                .with_endpoint(traces_url(endpoint))
+                .build()
+                .with_context(|| format!("OTLP exporter to {endpoint}"))?;
+            Some(
+                SdkTracerProvider::builder()
+                    .with_batch_exporter(exporter)
+                    .with_resource(
+                        Resource::builder()
+                            .with_service_name(service)
+                            .with_attribute(KeyValue::new(
+                                "service.version",
+                                env!("CARGO_PKG_VERSION"),
+                            ))
+                            .build(),
+                    )
+                    .build(),
+            )
+        }
+        None => None,
+    };
+    let otel = provider
+        .as_ref()
+        .map(|p| tracing_opentelemetry::layer().with_tracer(p.tracer(service)));
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt_layer(format, io::stderr))
+        .with(otel)
+        .try_init()
+        .context("installing the logger")?;
+    Ok(Guard(provider))
+}
@@ -0,0 +1,303 @@
+//! prometheus metrics of the fetcher and the rater. a metric is registered the first time it's
+//! used, so each binary only exposes its own
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener},
+    sync::LazyLock,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use async_openai::types::CompletionUsage;
+use prometheus::{
+    exponential_buckets, register_histogram_vec, register_int_counter, register_int_counter_vec,
+    register_int_gauge, Encoder, HistogramVec, IntCounter, IntCounterVec, IntGauge, TextEncoder,
+};
+use tracing::{debug, info, warn};
+
+use crate::Config;
+
+/// links the sources looked at, already seen or not
+pub static NEWS_FOUND: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "fetcher_news_found_total",
+        "Links found by the sources",
+        &["provider"]
+    )
+    .unwrap()
+});
+pub static NEWS_FETCHED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "fetcher_news_fetched_total",
+        "News fetched by the sources, before being saved",
+        &["provider"]
+    )
+    .unwrap()
+});
+/// `result` is inserted, merged or failed
+pub static NEWS_SAVED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "fetcher_news_saved_total",
+        "News saved by the fetcher, by result",
+        &["provider", "result"]
+    )
+    .unwrap()
+});
+/// news not saved because they broke a `rule` of the validation
+pub static NEWS_REJECTED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "fetcher_news_rejected_total",
+        "News rejected before being saved, by rule",
+        &["provider", "rule"]
+    )
+    .unwrap()
+});
+/// inserted news with only the teaser of a paywall, see [`crate::quality`]
+pub static NEWS_PAYWALLED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "fetcher_news_paywalled_total",
+        "News inserted with only the teaser of a paywall",
+        &["provider"]
+    )
+    .unwrap()
+});
+pub static SOURCE_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "fetcher_source_duration_seconds",
+        "Duration of a source run",
+        &["source"],
+        exponential_buckets(1.0, 2.0, 13).unwrap()
+    )
+    .unwrap()
+});
+pub static SOURCE_ERRORS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "fetcher_source_errors_total",
+        "Source runs that failed or panicked",
+        &["source"]
+    )
+    .unwrap()
+});
+/// `endpoint` is fetch or parse
+pub static ARTICLE_PARSER_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "fetcher_article_parser_duration_seconds",
+        "Duration of the article parser requests",
+        &["endpoint"]
+    )
+    .unwrap()
+});
+/// `class` is status, transport or invalid_response
+pub static ARTICLE_PARSER_ERRORS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "fetcher_article_parser_errors_total",
+        "Failed article parser requests",
+        &["endpoint", "class"]
+    )
+    .unwrap()
+});
+pub static BROWSERS_SPAWNED: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!("fetcher_browsers_spawned_total", "Chrome processes started").unwrap()
+});
+pub static BROWSERS_REUSED: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!(
+        "fetcher_browsers_reused_total",
+        "Sources that got a warm browser of the pool instead of a new one"
+    )
+    .unwrap()
+});
+/// `reason` is uses, dead, or args when it made room for a browser with other flags
+pub static BROWSERS_RECYCLED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "fetcher_browsers_recycled_total",
+        "Chrome processes closed by the pool, by reason",
+        &["reason"]
+    )
+    .unwrap()
+});
+pub static BROWSER_POOL_SIZE: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!("fetcher_browser_pool_size", "Chrome processes in the pool").unwrap()
+});
+/// divided by the pool size, the utilization of the pool
+pub static BROWSER_POOL_LEASED: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!(
+        "fetcher_browser_pool_leased",
+        "Sources using a browser of the pool"
+    )
+    .unwrap()
+});
+
+pub static RATER_BACKLOG: LazyLock<IntGauge> =
+    LazyLock::new(|| register_int_gauge!("rater_backlog", "News waiting for a rating").unwrap());
+/// `result` is rated, failed, skipped for a paywalled news, or retry when the news is left for
+/// the next batch
+pub static RATINGS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!("rater_ratings_total", "Rated news, by result", &["result"]).unwrap()
+});
+/// `task` is rate or translate
+pub static LLM_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "llm_request_duration_seconds",
+        "Duration of the LLM requests",
+        &["task"],
+        exponential_buckets(0.25, 2.0, 10).unwrap()
+    )
+    .unwrap()
+});
+/// `kind` is prompt or completion
+pub static LLM_TOKENS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "llm_tokens_total",
+        "Tokens used by the LLM requests",
+        &["task", "kind"]
+    )
+    .unwrap()
+});
+/// `class` is from [`llm_error_class`]
+pub static LLM_ERRORS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "llm_errors_total",
+        "Failed LLM tasks, by error class",
+        &["task", "class"]
+    )
+    .unwrap()
+});
+
+/// record the duration and the tokens of an LLM request that started at `started`
+pub fn observe_llm(task: &str, started: Instant, usage: Option<&CompletionUsage>) {
+    LLM_DURATION
+        .with_label_values(&[task])
+        .observe(started.elapsed().as_secs_f64());
+    if let Some(usage) = usage {
+        LLM_TOKENS
+            .with_label_values(&[task, "prompt"])
+            .inc_by(usage.prompt_tokens.into());
+        LLM_TOKENS
+            .with_label_values(&[task, "completion"])
+            .inc_by(usage.completion_tokens.into());
+    }
+}
+
+/// a short label for an LLM error, to count them without one series per message
+pub fn llm_error_class(error: &anyhow::Error) -> &'static str {
+    let error = format!("{error:#}").to_lowercase();
+    let has = |words: &[&str]| words.iter().any(|w| error.contains(w));
+    if has(&["bad gateway", "service unavailable", "502", "503"]) {
+        "unavailable"
+    } else if has(&["rate limit", "429", "quota"]) {
+        "rate_limited"
+    } else if has(&["timed out", "timeout"]) {
+        "timeout"
+    } else if has(&["i'm sorry"]) {
+        "refused"
+    } else if has(&[
+        "invalid response",
+        "no content",
+        "no response",
+        "invalid digit",
+    ]) {
+        "invalid_response"
+    } else if has(&["created openai request"]) {
+        "request"
+    } else {
+        "other"
+    }
+}
+
+/// the registered metrics, in the prometheus text format
+pub fn render() -> String {
+    let mut buffer = Vec::new();
+    // only fails on invalid metric names, which are all static
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .unwrap();
+    String::from_utf8(buffer).unwrap()
+}
+
+/// serve `GET /metrics` on `listen`, in a thread. returns the address it listens on
+pub fn serve(listen: &str) -> Result<SocketAddr> {
+    let listener = TcpListener::bind(listen).with_context(|| format!("binding {listen}"))?;
+    let addr = listener.local_addr()?;
+    info!(%addr, "serving metrics on /metrics");
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    debug!(error = %e, "metrics connection failed");
+                    continue;
+                }
+            };
+            let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+            // the request line is all we need
+            let mut request = [0; 1024];
+            let read = stream.read(&mut request).unwrap_or(0);
+            let request = String::from_utf8_lossy(&request[..read]);
+            let response = if request.starts_with("GET /metrics ") {
+                let body = render();
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+            } else {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".into()
+            };
+            if let Err(e) = stream.write_all(response.as_bytes()) {
+                debug!(error = %e, "metrics response failed");
+            }
+        }
+    });
+    Ok(addr)
+}
+
+/// replace the metrics of `job` on a pushgateway
+pub fn push(url: &str, job: &str) -> Result<()> {
+    let url = format!("{}/metrics/job/{job}", url.trim_end_matches('/'));
+    ureq::put(&url)
+        .timeout(Duration::from_secs(10))
+        .set("Content-Type", "text/plain; version=0.0.4")
+        .send_string(&render())
+        .with_context(|| format!("pushing metrics to {url}"))?;
+    Ok(())
+}
+
+/// how often metrics are pushed
+const PUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// exposes or pushes the metrics of a binary, as configured by `METRICS_LISTEN` and
+/// `METRICS_PUSH_URL`
+pub struct Exporter {
+    push_url: Option<String>,
+    job: String,
+}
+
+impl Exporter {
+    pub fn start(config: &Config, job: &str) -> Result<Exporter> {
+        if let Some(listen) = &config.metrics_listen {
+            serve(listen)?;
+        }
+        if let Some(url) = config.metrics_push_url.clone() {
+            let job = job.to_string();
+            thread::spawn(move || loop {
+                thread::sleep(PUSH_INTERVAL);
+                if let Err(e) = push(&url, &job) {
+                    warn!(error = %format!("{e:#}"), "pushing the metrics failed");
+                }
+            });
+        }
+        Ok(Exporter {
+            push_url: config.metrics_push_url.clone(),
+            job: job.to_string(),
+        })
+    }
+
+    /// push once more before exiting, so the last run isn't lost
+    pub fn flush(&self) {
+        if let Some(url) = &self.push_url {
+            if let Err(e) = push(url, &self.job) {
+                warn!(error = %format!("{e:#}"), "pushing the metrics failed");
+            }
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use surrealdb::Surreal;
+
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+/// every file of `migrations/`, in order. they are embedded so a binary knows which schema it was
+/// built for. never edit a migration that was released, add a new one.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial",
+        sql: include_str!("../../migrations/0001_initial.surql"),
+    },
+    Migration {
+        version: 2,
+        name: "date_index",
+        sql: include_str!("../../migrations/0002_date_index.surql"),
+    },
+    Migration {
+        version: 3,
+        name: "sources",
+        sql: include_str!("../../migrations/0003_sources.surql"),
+    },
+    Migration {
+        version: 4,
+        name: "api_tokens",
+        sql: include_str!("../../migrations/0004_api_tokens.surql"),
+    },
+    Migration {
+        version: 5,
+        name: "quality",
+        sql: include_str!("../../migrations/0005_quality.surql"),
+    },
+];
+
+const MIGRATIONS_TABLE: &str = "
+DEFINE TABLE migrations SCHEMAFULL;
+DEFINE FIELD version ON migrations TYPE int;
+DEFINE FIELD name ON migrations TYPE string;
+DEFINE FIELD applied_at ON migrations TYPE datetime DEFAULT time::now();
+";
+
+#[derive(Debug, Deserialize)]
+pub struct Applied {
+    pub version: u32,
+    pub name: String,
+}
+
+#[derive(Debug)]
+pub struct Status {
+    pub applied: Vec<Applied>,
+    /// versions of `MIGRATIONS` not applied yet
+    pub pending: Vec<u32>,
+}
+
+impl Status {
+    /// the database was migrated by a newer binary, or by migrations this one doesn't know
+    pub fn check_not_ahead(&self) -> Result<()> {
+        for applied in &self.applied {
+            match MIGRATIONS.iter().find(|m| m.version == applied.version) {
+                Some(m) if m.name == applied.name => (),
+                Some(m) => bail!(
+                    "migration {} is `{}` in the database but `{}` in this binary",
+                    m.version,
+                    applied.name,
+                    m.name
+                ),
+                None => bail!(
+                    "the database schema is ahead of this binary (migration {} `{}`), update it",
+                    applied.version,
+                    applied.name
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    /// the database has exactly the schema this binary expects
+    pub fn check(&self) -> Result<()> {
+        self.check_not_ahead()?;
+        if !self.pending.is_empty() {
+            bail!(
+                "{} pending migrations ({:?}), run `fetcher migrate` or drop AUTO_MIGRATE=false",
+                self.pending.len(),
+                self.pending
+            );
+        }
+        Ok(())
+    }
+}
+
+pub async fn status<C: surrealdb::Connection>(db: &Surreal<C>) -> Result<Status> {
+    db.query(MIGRATIONS_TABLE).await?.check()?;
+    let applied: Vec<Applied> = db
+        .query("SELECT version, name FROM migrations ORDER BY version")
+        .await?
+        .take(0)?;
+    let pending = MIGRATIONS
+        .iter()
+        .filter(|m| !applied.iter().any(|a| a.version == m.version))
+        .map(|m| m.version)
+        .collect();
+    Ok(Status { applied, pending })
+}
+
+/// apply the pending migrations, each one in its own transaction. returns their versions
+pub async fn migrate<C: surrealdb::Connection>(db: &Surreal<C>) -> Result<Vec<u32>> {
+    let status = status(db).await?;
+    status.check_not_ahead()?;
+    for migration in MIGRATIONS
+        .iter()
+        .filter(|m| status.pending.contains(&m.version))
+    {
+        db.query(format!(
+            "BEGIN TRANSACTION;\n{}\n;\nCREATE type::thing('migrations', $version) CONTENT {{ version: $version, name: $name }};\nCOMMIT TRANSACTION;",
+            migration.sql
+        ))
+        .bind(("version", migration.version))
+        .bind(("name", migration.name))
+        .await?
+        .check()
+        .with_context(|| format!("migration {} `{}`", migration.version, migration.name))?;
+    }
+    Ok(status.pending)
+}
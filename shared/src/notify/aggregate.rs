@@ -0,0 +1,96 @@
+use std::{cmp::Reverse, collections::BTreeMap, sync::OnceLock};
+
+use regex::{Captures, Regex};
+
+use super::{Message, Severity};
+
+/// lines of a digest, the rest is summed up
+const MAX_LINES: usize = 20;
+
+/// the same error about different articles gives the same fingerprint: only the first line is
+/// kept, links become `<url>` and the words with a digit (ids, durations, counts) become `#`,
+/// except in the names of the providers (`quebec::24heures`)
+pub fn fingerprint(text: &str) -> String {
+    static URL: OnceLock<Regex> = OnceLock::new();
+    static NUMBERED: OnceLock<Regex> = OnceLock::new();
+    let url = URL.get_or_init(|| Regex::new(r"https?://[^\s'`]+").unwrap());
+    let numbered = NUMBERED.get_or_init(|| Regex::new(r"(::)?[^\s:'`]*\d[^\s:'`]*").unwrap());
+    let line = text.lines().next().unwrap_or_default();
+    let line = url.replace_all(line, "<url>");
+    let line = numbered.replace_all(&line, |c: &Captures| match c.get(1) {
+        Some(_) => c[0].to_string(),
+        None => "#".to_string(),
+    });
+    line.chars().take(200).collect()
+}
+
+struct Group {
+    /// the first message, shown in the digest
+    text: String,
+    html: Option<String>,
+    count: usize,
+}
+
+/// collects the messages of a window, then turns them into one digest per binary and severity
+#[derive(Default)]
+pub struct Aggregator {
+    pending: BTreeMap<(String, Severity), BTreeMap<String, Group>>,
+}
+
+impl Aggregator {
+    pub fn push(&mut self, message: Message) {
+        let groups = self
+            .pending
+            .entry((message.binary, message.severity))
+            .or_default();
+        groups
+            .entry(fingerprint(&message.text))
+            .and_modify(|g| g.count += 1)
+            .or_insert(Group {
+                text: message.text,
+                html: message.html,
+                count: 1,
+            });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// a message seen once is sent as is, otherwise a digest like `23× be::rtbf: timeout`
+    pub fn drain(&mut self) -> Vec<Message> {
+        std::mem::take(&mut self.pending)
+            .into_iter()
+            .map(|((binary, severity), groups)| {
+                let mut groups: Vec<Group> = groups.into_values().collect();
+                if let [group] = groups.as_slice() {
+                    if group.count == 1 {
+                        let group = groups.remove(0);
+                        return Message {
+                            severity,
+                            binary,
+                            text: group.text,
+                            html: group.html,
+                        };
+                    }
+                }
+                groups.sort_by_key(|g| Reverse(g.count));
+                let total: usize = groups.iter().map(|g| g.count).sum();
+                let mut lines = vec![format!("{total} {severity}s:")];
+                lines.extend(groups.iter().take(MAX_LINES).map(|g| {
+                    let first_line = g.text.lines().next().unwrap_or_default();
+                    format!("{}× {first_line}", g.count)
+                }));
+                if groups.len() > MAX_LINES {
+                    lines.push(format!("and {} other kinds", groups.len() - MAX_LINES));
+                }
+                Message {
+                    severity,
+                    binary,
+                    text: lines.join("\n"),
+                    html: None,
+                }
+            })
+            .collect()
+    }
+}
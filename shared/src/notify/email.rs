@@ -0,0 +1,91 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use lettre::{
+    message::{Mailbox, MultiPart},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Tokio1Executor,
+};
+use serde::Deserialize;
+
+use super::{Message, Notifier};
+
+#[derive(Debug, Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Security {
+    /// port 587 by default
+    #[default]
+    Starttls,
+    /// port 465 by default
+    Tls,
+    /// for a local relay, port 25 by default
+    None,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmailConfig {
+    pub smtp_host: String,
+    pub smtp_port: Option<u16>,
+    #[serde(default)]
+    pub security: Security,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+pub struct Email {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl Email {
+    pub fn new(config: EmailConfig) -> Result<Self> {
+        let mut transport = match config.security {
+            Security::Starttls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.smtp_host)?
+            }
+            Security::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.smtp_host)?,
+            Security::None => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.smtp_host)
+            }
+        };
+        if let Some(port) = config.smtp_port {
+            transport = transport.port(port);
+        }
+        if let (Some(username), Some(password)) = (config.username, config.password) {
+            transport = transport.credentials(Credentials::new(username, password));
+        }
+        Ok(Email {
+            transport: transport.build(),
+            from: config.from.parse().context("email `from`")?,
+            to: config
+                .to
+                .iter()
+                .map(|to| to.parse().with_context(|| format!("email `to` {to}")))
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for Email {
+    async fn send(&self, message: &Message) -> Result<()> {
+        let mut email = lettre::Message::builder()
+            .from(self.from.clone())
+            .subject(message.title());
+        for to in &self.to {
+            email = email.to(to.clone());
+        }
+        let email = match &message.html {
+            Some(html) => email.multipart(MultiPart::alternative_plain_html(
+                message.text.clone(),
+                html.clone(),
+            ))?,
+            None => email.body(message.text.clone())?,
+        };
+        self.transport.send(email).await?;
+        Ok(())
+    }
+}
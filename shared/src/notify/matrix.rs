@@ -0,0 +1,58 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::{blocking, Message, Notifier};
+
+/// posts in a room with the client-server api, the access token's user must have joined it
+pub struct Matrix {
+    homeserver: String,
+    room_id: String,
+    access_token: String,
+    sent: AtomicU64,
+}
+
+impl Matrix {
+    pub fn new(
+        homeserver: impl Into<String>,
+        room_id: impl Into<String>,
+        access_token: impl Into<String>,
+    ) -> Self {
+        Matrix {
+            homeserver: homeserver.into(),
+            room_id: room_id.into(),
+            access_token: access_token.into(),
+            sent: AtomicU64::new(0),
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for Matrix {
+    async fn send(&self, message: &Message) -> Result<()> {
+        // the transaction id makes retries idempotent, it has to be unique per access token
+        let started = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        let txn_id = format!("{started}-{}", self.sent.fetch_add(1, Ordering::Relaxed));
+        let url = format!(
+            "{}/_matrix/client/v3/rooms/{}/send/m.room.message/{txn_id}",
+            self.homeserver.trim_end_matches('/'),
+            self.room_id.replace('#', "%23"),
+        );
+        let authorization = format!("Bearer {}", self.access_token);
+        let body = serde_json::json!({
+            "msgtype": "m.text",
+            "body": message.to_string(),
+        });
+        blocking(move || {
+            ureq::put(&url)
+                .set("Authorization", &authorization)
+                .send_json(body)?;
+            Ok(())
+        })
+        .await
+    }
+}
@@ -0,0 +1,384 @@
+mod aggregate;
+mod email;
+mod matrix;
+mod ntfy;
+mod recorder;
+pub mod telegram;
+mod webhook;
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::MissedTickBehavior,
+};
+use tracing::{error, warn};
+
+pub use aggregate::{fingerprint, Aggregator};
+pub use email::{Email, EmailConfig, Security};
+pub use matrix::Matrix;
+pub use ntfy::Ntfy;
+pub use recorder::Recorder;
+pub use telegram::Telegram;
+pub use webhook::Webhook;
+
+use crate::{config::load_json, Config};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    #[default]
+    Info,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Message {
+    pub severity: Severity,
+    /// the binary that sent it, like `fetcher`
+    pub binary: String,
+    pub text: String,
+    /// richer version of `text`, for the backends that can show it (email)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub html: Option<String>,
+}
+
+impl Message {
+    /// for the backends that have a subject
+    pub fn title(&self) -> String {
+        format!("{} {}", self.binary, self.severity)
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.binary, self.text)
+    }
+}
+
+/// somewhere to send alerts to
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn send(&self, message: &Message) -> Result<()>;
+}
+
+/// run a blocking `ureq` call without blocking the runtime
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> Result<T> + Send + 'static) -> Result<T> {
+    tokio::task::spawn_blocking(f).await?
+}
+
+/// how many messages the telegram channel sends at most, when there is no `NOTIFY_FILE`
+const DEFAULT_MAX_PER_HOUR: usize = 30;
+
+pub struct Route {
+    notifier: Arc<dyn Notifier>,
+    min_severity: Severity,
+    /// only messages from these binaries, all of them if `None`
+    binaries: Option<Vec<String>>,
+    limit: Option<Mutex<RateLimit>>,
+}
+
+/// at most `max` messages in the last hour, the others are dropped and counted
+struct RateLimit {
+    max: usize,
+    sent: VecDeque<Instant>,
+    dropped: usize,
+}
+
+impl RateLimit {
+    /// `None` if the message must be dropped, or how many were dropped before it
+    fn allow(&mut self, now: Instant) -> Option<usize> {
+        while let Some(first) = self.sent.front() {
+            if now.duration_since(*first) < Duration::from_secs(60 * 60) {
+                break;
+            }
+            self.sent.pop_front();
+        }
+        if self.sent.len() >= self.max {
+            self.dropped += 1;
+            return None;
+        }
+        self.sent.push_back(now);
+        Some(std::mem::take(&mut self.dropped))
+    }
+}
+
+impl Route {
+    pub fn new(
+        notifier: Arc<dyn Notifier>,
+        min_severity: Severity,
+        binaries: Option<Vec<String>>,
+    ) -> Route {
+        Route {
+            notifier,
+            min_severity,
+            binaries,
+            limit: None,
+        }
+    }
+
+    pub fn max_per_hour(mut self, max: usize) -> Route {
+        self.limit = Some(Mutex::new(RateLimit {
+            max,
+            sent: VecDeque::new(),
+            dropped: 0,
+        }));
+        self
+    }
+
+    fn wants(&self, message: &Message) -> bool {
+        message.severity >= self.min_severity
+            && self
+                .binaries
+                .as_ref()
+                .is_none_or(|b| b.contains(&message.binary))
+    }
+}
+
+/// sends every message to the routes that want it
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+impl Router {
+    pub fn new(routes: Vec<Route>) -> Router {
+        Router { routes }
+    }
+
+    /// the channels of `NOTIFY_FILE`, or only telegram (if configured) for every message
+    pub fn from_config(config: &Config) -> Result<Router> {
+        if let Some(path) = &config.notify_file {
+            return NotifyFile::load(path)?.router();
+        }
+        let telegram = Telegram::from_config(config);
+        Ok(Router::new(vec![Route::new(
+            Arc::new(telegram),
+            Severity::Info,
+            None,
+        )
+        .max_per_hour(DEFAULT_MAX_PER_HOUR)]))
+    }
+
+    /// a failing channel doesn't prevent the others from getting the message
+    pub async fn dispatch(&self, message: &Message) {
+        for route in self.routes.iter().filter(|r| r.wants(message)) {
+            let dropped = match &route.limit {
+                Some(limit) => match limit.lock().unwrap().allow(Instant::now()) {
+                    Some(dropped) => dropped,
+                    None => {
+                        warn!(%message, "rate limited, dropping the notification");
+                        continue;
+                    }
+                },
+                None => 0,
+            };
+            let result = if dropped > 0 {
+                let mut message = message.clone();
+                message.text += &format!("\n({dropped} notifications dropped by the rate limit)");
+                route.notifier.send(&message).await
+            } else {
+                route.notifier.send(message).await
+            };
+            if let Err(e) = result {
+                error!(%message, error = %format!("{e:#}"), "sending the notification failed");
+            }
+        }
+    }
+}
+
+/// `NOTIFY_FILE`, see `notify.example.json`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NotifyFile {
+    pub channels: Vec<Channel>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Channel {
+    #[serde(flatten)]
+    pub backend: Backend,
+    #[serde(default)]
+    pub min_severity: Severity,
+    pub binaries: Option<Vec<String>>,
+    pub max_per_hour: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Backend {
+    Telegram {
+        token: String,
+        chat_id: i64,
+    },
+    /// the message is POSTed as json
+    Webhook {
+        url: String,
+    },
+    Email(EmailConfig),
+    Ntfy {
+        /// `https://ntfy.sh` or a self hosted server
+        url: String,
+        topic: String,
+        token: Option<String>,
+    },
+    Matrix {
+        homeserver: String,
+        room_id: String,
+        access_token: String,
+    },
+}
+
+impl NotifyFile {
+    pub fn load(path: impl AsRef<Path>) -> Result<NotifyFile> {
+        load_json(path)
+    }
+
+    pub fn router(self) -> Result<Router> {
+        let routes = self
+            .channels
+            .into_iter()
+            .map(|channel| {
+                let notifier: Arc<dyn Notifier> = match channel.backend {
+                    Backend::Telegram { token, chat_id } => Arc::new(Telegram::new(token, chat_id)),
+                    Backend::Webhook { url } => Arc::new(Webhook::new(url)),
+                    Backend::Email(config) => Arc::new(Email::new(config)?),
+                    Backend::Ntfy { url, topic, token } => Arc::new(Ntfy::new(url, topic, token)),
+                    Backend::Matrix {
+                        homeserver,
+                        room_id,
+                        access_token,
+                    } => Arc::new(Matrix::new(homeserver, room_id, access_token)),
+                };
+                let route = Route::new(notifier, channel.min_severity, channel.binaries);
+                Ok(match channel.max_per_hour {
+                    Some(max) => route.max_per_hour(max),
+                    None => route,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Router::new(routes))
+    }
+}
+
+enum Command {
+    Send(Message),
+    Flush(oneshot::Sender<()>),
+}
+
+/// handle to send notifications from anywhere, sync or async code. they are delivered by a
+/// background task, so a slow channel never blocks the caller. the messages are aggregated over
+/// a window, so a failing source sends one digest instead of a message per article.
+#[derive(Clone)]
+pub struct Notify {
+    binary: &'static str,
+    tx: mpsc::UnboundedSender<Command>,
+}
+
+impl Notify {
+    /// with the routes of [`Router::from_config`], aggregated over `NOTIFY_WINDOW` seconds (60 by default)
+    pub fn from_config(config: &Config, binary: &'static str) -> Result<Notify> {
+        // an interval of 0 panics, `validate` rejects it but not every caller validates
+        let window = Duration::from_secs(config.notify_window.unwrap_or(60).max(1));
+        Ok(Notify::spawn(Router::from_config(config)?, binary, window))
+    }
+
+    /// must be called inside a tokio runtime
+    pub fn spawn(router: Router, binary: &'static str, window: Duration) -> Notify {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut aggregator = Aggregator::default();
+            let mut tick = tokio::time::interval(window);
+            tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                let command = tokio::select! {
+                    command = rx.recv() => command,
+                    _ = tick.tick() => {
+                        for message in aggregator.drain() {
+                            router.dispatch(&message).await;
+                        }
+                        continue;
+                    }
+                };
+                match command {
+                    Some(Command::Send(message)) => aggregator.push(message),
+                    Some(Command::Flush(done)) => {
+                        for message in aggregator.drain() {
+                            router.dispatch(&message).await;
+                        }
+                        _ = done.send(());
+                    }
+                    None => {
+                        for message in aggregator.drain() {
+                            router.dispatch(&message).await;
+                        }
+                        break;
+                    }
+                }
+            }
+        });
+        Notify { binary, tx }
+    }
+
+    pub fn send(&self, severity: Severity, text: impl Into<String>) {
+        let message = Message {
+            severity,
+            binary: self.binary.to_string(),
+            text: text.into(),
+            html: None,
+        };
+        if let Err(e) = self.tx.send(Command::Send(message)) {
+            warn!(text = e.0.text(), "notifications are stopped, dropping");
+        }
+    }
+
+    pub fn info(&self, text: impl Into<String>) {
+        self.send(Severity::Info, text)
+    }
+
+    pub fn warning(&self, text: impl Into<String>) {
+        self.send(Severity::Warning, text)
+    }
+
+    pub fn error(&self, text: impl Into<String>) {
+        self.send(Severity::Error, text)
+    }
+
+    /// send what is waiting for the end of the window, and wait until it's delivered.
+    /// call it before exiting
+    pub async fn flush(&self) {
+        let (done, wait) = oneshot::channel();
+        if self.tx.send(Command::Flush(done)).is_ok() {
+            _ = wait.await;
+        }
+    }
+}
+
+impl Command {
+    fn text(&self) -> &str {
+        match self {
+            Command::Send(message) => &message.text,
+            Command::Flush(_) => "flush",
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::{blocking, Message, Notifier, Severity};
+
+/// https://docs.ntfy.sh/publish/
+pub struct Ntfy {
+    url: String,
+    topic: String,
+    token: Option<String>,
+}
+
+impl Ntfy {
+    pub fn new(url: impl Into<String>, topic: impl Into<String>, token: Option<String>) -> Self {
+        Ntfy {
+            url: url.into(),
+            topic: topic.into(),
+            token,
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for Ntfy {
+    async fn send(&self, message: &Message) -> Result<()> {
+        let url = format!("{}/{}", self.url.trim_end_matches('/'), self.topic);
+        let token = self.token.clone();
+        let title = message.title();
+        let text = message.text.clone();
+        let priority = match message.severity {
+            Severity::Info => "low",
+            Severity::Warning => "default",
+            Severity::Error => "high",
+        };
+        blocking(move || {
+            let mut request = ureq::post(&url)
+                .set("Title", &title)
+                .set("Priority", priority);
+            if let Some(token) = token {
+                request = request.set("Authorization", &format!("Bearer {token}"));
+            }
+            request.send_string(&text)?;
+            Ok(())
+        })
+        .await
+    }
+}
@@ -0,0 +1,24 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::{Message, Notifier};
+
+/// keeps the messages instead of sending them, to test the error paths
+#[derive(Clone, Default)]
+pub struct Recorder(Arc<Mutex<Vec<Message>>>);
+
+impl Recorder {
+    pub fn messages(&self) -> Vec<Message> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Notifier for Recorder {
+    async fn send(&self, message: &Message) -> Result<()> {
+        self.0.lock().unwrap().push(message.clone());
+        Ok(())
+    }
+}
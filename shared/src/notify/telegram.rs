@@ -0,0 +1,185 @@
+use std::time::Duration;
+
+use anyhow::bail;
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use super::{blocking, Message, Notifier};
+use crate::Config;
+
+pub const DEFAULT_API: &str = "https://api.telegram.org";
+
+/// longest text of a telegram message, in characters
+const MAX_LENGTH: usize = 4096;
+
+#[derive(Clone)]
+pub struct Telegram {
+    /// token and chat id, `None` when telegram isn't configured
+    bot: Option<(String, i64)>,
+    /// base url of the Bot API, a local server in tests
+    api: String,
+}
+
+/// what the bot receives from `getUpdates`, only the fields we use
+#[derive(Debug, Deserialize)]
+pub struct Update {
+    pub update_id: i64,
+    pub message: Option<IncomingMessage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IncomingMessage {
+    pub chat: Chat,
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Chat {
+    pub id: i64,
+}
+
+#[derive(Deserialize)]
+struct ApiResponse<T> {
+    ok: bool,
+    result: Option<T>,
+    description: Option<String>,
+}
+
+impl Telegram {
+    pub fn new(token: impl Into<String>, chat_id: i64) -> Self {
+        Telegram {
+            bot: Some((token.into(), chat_id)),
+            api: DEFAULT_API.to_string(),
+        }
+    }
+    /// without `TELEGRAM_TOKEN` and `TELEGRAM_ID`, messages are dropped
+    pub fn from_config(config: &Config) -> Self {
+        let telegram = match (&config.telegram_token, config.telegram_id) {
+            (Some(token), Some(chat_id)) => Telegram::new(token, chat_id),
+            _ => Telegram {
+                bot: None,
+                api: DEFAULT_API.to_string(),
+            },
+        };
+        match &config.telegram_api {
+            Some(api) => telegram.api(api),
+            None => telegram,
+        }
+    }
+    pub fn api(mut self, api: impl Into<String>) -> Self {
+        self.api = api.into().trim_end_matches('/').to_string();
+        self
+    }
+    /// the only chat the bot talks to
+    pub fn chat_id(&self) -> Option<i64> {
+        self.bot.as_ref().map(|(_, id)| *id)
+    }
+    /// blocking long poll for the messages sent to the bot, waiting up to `timeout` for one.
+    /// pass the last `update_id` + 1 as `offset` to acknowledge the previous ones
+    pub fn get_updates(&self, offset: i64, timeout: Duration) -> anyhow::Result<Vec<Update>> {
+        let Some((token, _)) = &self.bot else {
+            bail!("telegram isn't configured");
+        };
+        let url = format!("{}/bot{token}/getUpdates", self.api);
+        let response: ApiResponse<Vec<Update>> = ureq::get(&url)
+            .query("offset", &offset.to_string())
+            .query("timeout", &timeout.as_secs().to_string())
+            .query("allowed_updates", r#"["message"]"#)
+            .timeout(timeout + Duration::from_secs(10))
+            .call()?
+            .into_json()?;
+        if !response.ok {
+            bail!("getUpdates: {}", response.description.unwrap_or_default());
+        }
+        Ok(response.result.unwrap_or_default())
+    }
+    /// blocking, prefer going through [`super::Notify`]
+    pub fn send(&self, msg: impl Into<String>) -> anyhow::Result<()> {
+        if std::env::var("NO_TELEGRAM").is_ok() {
+            bail!("telegram disabled by NO_TELEGRAM env");
+        }
+        let Some((token, chat_id)) = &self.bot else {
+            return Ok(());
+        };
+        let msg = msg.into();
+        let url = format!("{}/bot{token}/sendMessage", self.api);
+        for text in split(&msg) {
+            let data = serde_json::json!({
+                "chat_id": chat_id,
+                "text": text,
+            });
+            ureq::post(&url).send_json(data)?;
+        }
+        Ok(())
+    }
+}
+
+/// cut a long text into messages telegram accepts, between lines when possible
+fn split(text: &str) -> Vec<String> {
+    let mut messages = vec![String::new()];
+    for line in text.split_inclusive('\n') {
+        let mut line = line;
+        loop {
+            let current = messages.last_mut().unwrap();
+            let room = MAX_LENGTH - current.chars().count();
+            if line.chars().count() <= room {
+                current.push_str(line);
+                break;
+            }
+            if current.is_empty() {
+                // a single line longer than a message
+                let cut = line.char_indices().nth(room).map_or(line.len(), |(i, _)| i);
+                current.push_str(&line[..cut]);
+                line = &line[cut..];
+            }
+            messages.push(String::new());
+        }
+    }
+    messages
+}
+
+#[async_trait]
+impl Notifier for Telegram {
+    async fn send(&self, message: &Message) -> anyhow::Result<()> {
+        let telegram = self.clone();
+        let text = message.to_string();
+        blocking(move || telegram.send(text)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    #[ignore]
+    fn it_creates_from_env() {
+        if let Err(e) = dotenvy::dotenv() {
+            eprintln!("Error loading .env file: {e}");
+        }
+
+        let token = env::var("TELEGRAM_TOKEN").expect("TELEGRAM_TOKEN not set in env");
+        let id = env::var("TELEGRAM_ID")
+            .expect("TELEGRAM_ID not set in env")
+            .parse::<i64>()
+            .unwrap();
+
+        let tg = Telegram::new(token, id);
+        tg.send("you can ignore this. this is a test for news-scraper::shared::telegram module")
+            .unwrap();
+    }
+
+    #[test]
+    fn it_splits_long_messages() {
+        assert_eq!(split("short\ntext"), vec!["short\ntext"]);
+        let line = format!("{}\n", "a".repeat(3000));
+        let messages = split(&line.repeat(3));
+        assert_eq!(messages.len(), 3);
+        assert!(messages.iter().all(|m| m == &line));
+        let messages = split(&"é".repeat(5000));
+        assert_eq!(messages[0].chars().count(), MAX_LENGTH);
+        assert_eq!(messages[1].chars().count(), 5000 - MAX_LENGTH);
+    }
+}
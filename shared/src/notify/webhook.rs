@@ -0,0 +1,28 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::{blocking, Message, Notifier};
+
+/// POSTs `{"severity": "error", "binary": "fetcher", "text": "..."}`
+pub struct Webhook {
+    url: String,
+}
+
+impl Webhook {
+    pub fn new(url: impl Into<String>) -> Self {
+        Webhook { url: url.into() }
+    }
+}
+
+#[async_trait]
+impl Notifier for Webhook {
+    async fn send(&self, message: &Message) -> Result<()> {
+        let url = self.url.clone();
+        let body = serde_json::to_value(message)?;
+        blocking(move || {
+            ureq::post(&url).send_json(body)?;
+            Ok(())
+        })
+        .await
+    }
+}
@@ -0,0 +1,119 @@
+//! heuristics telling a complete article from the teaser of a paywall or a truncated body, run
+//! on every news before it's inserted
+
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+/// the page's JSON-LD says `"isAccessibleForFree": false`
+pub const NOT_FREE: &str = "not_accessible_for_free";
+/// the body is short and has the markup of a paywall
+pub const PAYWALL_MARKER: &str = "paywall_marker";
+/// the body is short and ends by telling the reader to subscribe to read the rest
+pub const SUBSCRIBER_PHRASE: &str = "subscriber_phrase";
+/// the body has less than [`SHORT_BODY_WORDS`] words, likely truncated
+pub const SHORT_BODY: &str = "short_body";
+
+/// below it, a body is likely a teaser or a truncated article
+pub const SHORT_BODY_WORDS: usize = 120;
+
+/// the end of the text where a teaser tells to subscribe, an article can quote the phrases before
+const TAIL_CHARS: usize = 300;
+
+/// the values of the `class` and `id` attributes
+static ATTRIBUTES: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)\b(?:class|id)\s*=\s*["']([^"']*)["']"#).unwrap());
+
+/// in the classes and ids left in the body by the paywalls
+const PAYWALL_MARKERS: &[&str] = &[
+    "paywall",
+    "premium-content",
+    "article-premium",
+    "subscriber-only",
+    "tp-container-inner",
+    "poool-widget",
+];
+
+/// what the teasers end with, in lowercase, looked for in the last [`TAIL_CHARS`] of the text
+const SUBSCRIBER_PHRASES: &[&str] = &[
+    "réservé aux abonnés",
+    "réservé à nos abonnés",
+    "réservée aux abonnés",
+    "la suite est réservée",
+    "pour lire la suite",
+    "il vous reste",
+    "contenu exclusif abonnés",
+    "subscribers only",
+    "subscribe to continue reading",
+    "subscribe to read",
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Quality {
+    /// only the beginning of the article is there
+    pub paywalled: bool,
+    /// the constants of this module that matched
+    pub flags: Vec<String>,
+}
+
+/// `html_body` as the article parser returned it, `text_body` its text, and
+/// `accessible_for_free` from the JSON-LD of the page if it has one
+#[must_use]
+pub fn assess(html_body: &str, text_body: &str, accessible_for_free: Option<bool>) -> Quality {
+    let mut flags = Vec::new();
+    if accessible_for_free == Some(false) {
+        flags.push(NOT_FREE);
+    }
+    // a complete article can have a paywall class around it or quote a phrase, it takes a short
+    // body for them to mean the rest is missing
+    let short = text_body.split_whitespace().count() < SHORT_BODY_WORDS;
+    if short {
+        let marked = ATTRIBUTES.captures_iter(html_body).any(|c| {
+            let value = c[1].to_lowercase();
+            PAYWALL_MARKERS.iter().any(|m| value.contains(m))
+        });
+        if marked {
+            flags.push(PAYWALL_MARKER);
+        }
+        let text = text_body.to_lowercase();
+        let skip = text.chars().count().saturating_sub(TAIL_CHARS);
+        let tail: String = text.chars().skip(skip).collect();
+        if SUBSCRIBER_PHRASES.iter().any(|p| tail.contains(p)) {
+            flags.push(SUBSCRIBER_PHRASE);
+        }
+    }
+    let paywalled = !flags.is_empty();
+    if short {
+        flags.push(SHORT_BODY);
+    }
+    Quality {
+        paywalled,
+        flags: flags.into_iter().map(String::from).collect(),
+    }
+}
+
+/// what the rater does with a paywalled news, from `RATER_PAYWALLED`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Paywalled {
+    /// like any other news
+    Rate,
+    /// not rated, left with a rating of 0 and a note
+    Skip,
+    /// its ratings are multiplied by [`Paywalled::WEIGHT`]
+    Downweight,
+}
+
+impl Paywalled {
+    pub const WEIGHT: f64 = 0.5;
+
+    pub fn parse(mode: Option<&str>) -> anyhow::Result<Paywalled> {
+        match mode.unwrap_or("rate") {
+            "rate" => Ok(Paywalled::Rate),
+            "skip" => Ok(Paywalled::Skip),
+            "downweight" => Ok(Paywalled::Downweight),
+            other => anyhow::bail!(
+                "RATER_PAYWALLED is `{other}`, expected `rate`, `skip` or `downweight`"
+            ),
+        }
+    }
+}
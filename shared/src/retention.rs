@@ -0,0 +1,292 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use surrealdb::{
+    sql::{Datetime, Thing},
+    Surreal,
+};
+
+use crate::{config::load_json, DbNews};
+
+pub(crate) const ARCHIVE_BATCH: usize = 500;
+
+/// what to do with the bodies of old news. rules are checked in order and the first one matching a
+/// news decides for it, so put the `keep forever` rules first.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    /// bodies are written to `<archive_dir>/<date>-<rule>.jsonl.zst` before being wiped, with
+    /// anything but `[A-Za-z0-9_-]` in the name of the rule replaced by `_`
+    pub archive_dir: Option<PathBuf>,
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub name: String,
+    #[serde(default)]
+    pub providers: Vec<String>,
+    /// matches if the news has any of theses tags, usually the region (`fr`, `lme`...)
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub used: Option<bool>,
+    pub min_rating: Option<u8>,
+    pub min_rating_travel: Option<u8>,
+    /// bodies older than this are wiped. None keeps them forever
+    pub max_age_days: Option<u32>,
+}
+
+impl Default for Policy {
+    /// what the fetcher always did: wipe everything older than 60 days
+    fn default() -> Self {
+        Policy {
+            archive_dir: None,
+            rules: vec![Rule {
+                name: "default".into(),
+                max_age_days: Some(60),
+                ..Default::default()
+            }],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RuleReport {
+    pub rule: String,
+    /// news with a body that this rule is responsible for
+    pub matched: usize,
+    /// news whose body was (or would be, in dry run) wiped
+    pub wiped: usize,
+    pub archived: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ArchivedBody {
+    pub id: Thing,
+    pub link: String,
+    pub provider: String,
+    pub date: Datetime,
+    pub html_body: String,
+    pub text_body: String,
+}
+
+impl Rule {
+    fn condition(&self, index: usize, vars: &mut BTreeMap<String, Value>) -> String {
+        let mut conds = Vec::new();
+        let mut var = |name: &str, value: Value| {
+            let name = format!("r{index}_{name}");
+            vars.insert(name.clone(), value);
+            format!("${name}")
+        };
+        if !self.providers.is_empty() {
+            conds.push(format!(
+                "provider INSIDE {}",
+                var("providers", self.providers.clone().into())
+            ));
+        }
+        if !self.tags.is_empty() {
+            conds.push(format!(
+                "tags CONTAINSANY {}",
+                var("tags", self.tags.clone().into())
+            ));
+        }
+        if let Some(used) = self.used {
+            conds.push(format!("used = {}", var("used", used.into())));
+        }
+        if let Some(rating) = self.min_rating {
+            conds.push(format!("rating >= {}", var("min_rating", rating.into())));
+        }
+        if let Some(rating) = self.min_rating_travel {
+            conds.push(format!(
+                "rating_travel >= {}",
+                var("min_rating_travel", rating.into())
+            ));
+        }
+        if conds.is_empty() {
+            "true".to_string()
+        } else {
+            conds.join(" AND ")
+        }
+    }
+
+    /// same as [`Rule::condition`], for stores that can't run SurrealQL
+    #[must_use]
+    pub fn matches(&self, news: &DbNews) -> bool {
+        let at_least = |rating: Option<u8>, min: Option<u8>| match min {
+            Some(min) => rating.is_some_and(|r| r >= min),
+            None => true,
+        };
+        (self.providers.is_empty() || self.providers.iter().any(|p| *p == news.provider))
+            && (self.tags.is_empty() || self.tags.iter().any(|t| news.tags.contains(t)))
+            && self.used.is_none_or(|used| used == news.used)
+            && at_least(news.rating, self.min_rating)
+            && at_least(news.rating_travel, self.min_rating_travel)
+    }
+}
+
+impl Policy {
+    pub fn load(path: impl AsRef<Path>) -> Result<Policy> {
+        load_json(path)
+    }
+
+    /// the WHERE clause selecting the news each rule is responsible for, excluding the ones an
+    /// earlier rule already took, and the variables to bind with it.
+    #[must_use]
+    pub fn where_clauses(&self) -> (Vec<String>, BTreeMap<String, Value>) {
+        let mut vars = BTreeMap::new();
+        let conds: Vec<String> = self
+            .rules
+            .iter()
+            .enumerate()
+            .map(|(i, rule)| rule.condition(i, &mut vars))
+            .collect();
+        let clauses = conds
+            .iter()
+            .enumerate()
+            .map(|(i, cond)| {
+                let mut clause = format!("({cond})");
+                for previous in &conds[..i] {
+                    clause.push_str(&format!(" AND !({previous})"));
+                }
+                clause.push_str(" AND string::len(html_body) > 0");
+                clause
+            })
+            .collect();
+        (clauses, vars)
+    }
+
+    /// the first rule matching this news, which decides what happens to its body
+    #[must_use]
+    pub fn rule_for(&self, news: &DbNews) -> Option<&Rule> {
+        self.rules.iter().find(|rule| rule.matches(news))
+    }
+
+    /// wipe (and archive) the bodies of old news according to the rules.
+    /// with `dry_run`, only count what each rule would touch.
+    pub async fn apply<C: surrealdb::Connection>(
+        &self,
+        db: &Surreal<C>,
+        dry_run: bool,
+    ) -> Result<Vec<RuleReport>> {
+        let (clauses, vars) = self.where_clauses();
+        let mut reports = Vec::with_capacity(self.rules.len());
+        for (rule, clause) in self.rules.iter().zip(clauses) {
+            let mut report = RuleReport {
+                rule: rule.name.clone(),
+                matched: count(db, &clause, &vars).await?,
+                ..Default::default()
+            };
+            let Some(days) = rule.max_age_days else {
+                reports.push(report);
+                continue;
+            };
+            let clause = format!("{clause} AND date < time::now() - {days}d");
+            if dry_run {
+                report.wiped = count(db, &clause, &vars).await?;
+            } else if let Some(dir) = &self.archive_dir {
+                (report.archived, report.wiped) = archive_and_wipe(db, dir, rule, &clause, &vars)
+                    .await
+                    .with_context(|| format!("rule {}", rule.name))?;
+            } else {
+                report.wiped = db
+                    .query(format!(
+                        "UPDATE news SET html_body = '', text_body = '' WHERE {clause} RETURN id"
+                    ))
+                    .bind(vars.clone())
+                    .await?
+                    .take::<Vec<Thing>>((0, "id"))?
+                    .len();
+            }
+            reports.push(report);
+        }
+        Ok(reports)
+    }
+}
+
+async fn count<C: surrealdb::Connection>(
+    db: &Surreal<C>,
+    clause: &str,
+    vars: &BTreeMap<String, Value>,
+) -> Result<usize> {
+    let count: Option<usize> = db
+        .query(format!("SELECT count() FROM news WHERE {clause} GROUP ALL"))
+        .bind(vars.clone())
+        .await?
+        .take((0, "count"))?;
+    Ok(count.unwrap_or_default())
+}
+
+/// wiped rows stop matching the clause, so we can keep taking the first batch until there is none
+async fn archive_and_wipe<C: surrealdb::Connection>(
+    db: &Surreal<C>,
+    dir: &Path,
+    rule: &Rule,
+    clause: &str,
+    vars: &BTreeMap<String, Value>,
+) -> Result<(usize, usize)> {
+    let path = archive_path(dir, rule)?;
+    let (mut archived, mut wiped) = (0, 0);
+    loop {
+        let batch: Vec<ArchivedBody> = db
+            .query(format!(
+                "SELECT id, link, provider, date, html_body, text_body FROM news WHERE {clause} LIMIT {ARCHIVE_BATCH}"
+            ))
+            .bind(vars.clone())
+            .await?
+            .take(0)?;
+        if batch.is_empty() {
+            return Ok((archived, wiped));
+        }
+        write_archive(&path, &batch)?;
+        archived += batch.len();
+
+        let ids: Vec<Thing> = batch.into_iter().map(|b| b.id).collect();
+        wiped += db
+            .query("UPDATE $ids SET html_body = '', text_body = '' RETURN id")
+            .bind(("ids", ids))
+            .await?
+            .take::<Vec<Thing>>((0, "id"))?
+            .len();
+    }
+}
+
+pub(crate) fn archive_path(dir: &Path, rule: &Rule) -> Result<PathBuf> {
+    fs::create_dir_all(dir).with_context(|| format!("creating {dir:?}"))?;
+    // the name comes from the file, a `/` or `..` in it mustn't leave the directory
+    let name: String = rule
+        .name
+        .chars()
+        .map(|c| match c {
+            'A'..='Z' | 'a'..='z' | '0'..='9' | '_' | '-' => c,
+            _ => '_',
+        })
+        .collect();
+    Ok(dir.join(format!(
+        "{}-{name}.jsonl.zst",
+        chrono::Local::now().format("%Y-%m-%d"),
+    )))
+}
+
+pub(crate) fn write_archive(path: &Path, bodies: &[ArchivedBody]) -> Result<()> {
+    // a zstd file can be made of several frames, so appending a new one each batch is fine
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("opening {path:?}"))?;
+    let mut encoder = zstd::Encoder::new(file, 3)?;
+    for body in bodies {
+        serde_json::to_writer(&mut encoder, body)?;
+        encoder.write_all(b"\n")?;
+    }
+    encoder.finish()?.sync_all()?;
+    Ok(())
+}
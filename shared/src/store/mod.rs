@@ -0,0 +1,230 @@
+mod sqlite;
+mod surreal;
+
+use std::{fmt, path::Path, str::FromStr, sync::Arc};
+
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use surrealdb::sql::Datetime;
+
+pub use sqlite::SqliteStore;
+pub use surreal::SurrealStore;
+
+use crate::{
+    retention::{Policy, RuleReport},
+    Config, DbNews,
+};
+
+/// the last run of a fetcher source
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceRun {
+    pub source: String,
+    pub started: Datetime,
+    pub finished: Datetime,
+    pub inserted: usize,
+    pub merged: usize,
+    pub failed: usize,
+    /// why the source stopped, if it failed
+    pub error: Option<String>,
+}
+
+/// what [`NewsStore::news`] returns when the filter has no `limit`
+pub const DEFAULT_LIMIT: usize = 50;
+
+/// the note of a news whose rating was reset, the rater goes through it even if it's older than
+/// a week
+pub const RERATE_NOTE: &str = "rerate";
+
+/// which news to list, every field narrows it down
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NewsFilter {
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub provider: Option<String>,
+    /// a region tag like `fr` or `lme`, every source tags its news with its region
+    pub region: Option<String>,
+    /// news having all of these tags
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub min_rating: Option<u8>,
+    pub max_rating: Option<u8>,
+    pub min_rating_travel: Option<u8>,
+    pub max_rating_travel: Option<u8>,
+    pub used: Option<bool>,
+    pub lang: Option<String>,
+    /// case insensitive search in the title, caption and text
+    pub text: Option<String>,
+    /// only the news after this one, to get the next page
+    pub after: Option<Cursor>,
+    pub limit: Option<usize>,
+}
+
+/// a position in the listing of [`NewsStore::news`], which is sorted by date then id. unlike an
+/// offset, it stays right when news are inserted between two pages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    pub date: DateTime<Utc>,
+    /// the key of the news id, `abc` for `news:abc`
+    pub id: String,
+}
+
+impl Cursor {
+    /// the cursor to continue after this news
+    pub fn after(news: &DbNews) -> Option<Cursor> {
+        Some(Cursor {
+            date: news.date.0,
+            id: news.id.as_ref()?.id.to_raw(),
+        })
+    }
+}
+
+/// `<unix micros>.<id>`, opaque for the clients
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.date.timestamp_micros(), self.id)
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let (micros, id) = s
+            .split_once('.')
+            .ok_or_else(|| anyhow!("invalid cursor `{s}`"))?;
+        let date = micros
+            .parse()
+            .ok()
+            .and_then(DateTime::from_timestamp_micros)
+            .ok_or_else(|| anyhow!("invalid cursor `{s}`"))?;
+        Ok(Cursor {
+            date,
+            id: id.to_string(),
+        })
+    }
+}
+
+impl Serialize for Cursor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Cursor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// the fields of a news that can be edited from the api, `None` keeps the current value
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NewsUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub used: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+}
+
+/// the owner of an api token
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiUser {
+    pub name: String,
+    /// only activated users can use the api, like the `news` table permissions
+    pub activated: bool,
+}
+
+/// `news:abc` or `abc`, as shown to the operators
+fn news_key(id: &str) -> &str {
+    let id = id.strip_prefix("news:").unwrap_or(id);
+    id.trim_start_matches('⟨').trim_end_matches('⟩')
+}
+
+/// everything the fetcher and the rater need from the database.
+#[async_trait]
+pub trait NewsStore: Send + Sync {
+    /// insert a news whose link was never seen. fails if the link already exists
+    async fn insert(&self, news: &DbNews) -> Result<()>;
+    /// add tags to the news with this link, when another provider found it too
+    async fn merge_tags(&self, link: &str, tags: &[String]) -> Result<()>;
+    async fn seen_links(&self) -> Result<Vec<String>>;
+    /// the last week of news that still need a rating, and the older ones reset with
+    /// [`NewsStore::reset_rating`], newest first.
+    /// `languages` restricts them to news detected in one of theses languages, or whose
+    /// language couldn't be detected
+    async fn unrated(&self, languages: Option<&[String]>) -> Result<Vec<DbNews>>;
+    /// save a news after the rater went through it (rating, tags, note, translations)
+    async fn save_rating(&self, news: &DbNews) -> Result<()>;
+    /// wipe (and archive) old bodies, see [`Policy`]
+    async fn apply_retention(&self, policy: &Policy, dry_run: bool) -> Result<Vec<RuleReport>>;
+    /// apply the schema migrations the database doesn't have yet, returns their versions
+    async fn migrate(&self) -> Result<Vec<u32>>;
+    /// fails if the database schema isn't exactly the one this binary was built for
+    async fn check_schema(&self) -> Result<()>;
+
+    /// replace the last run of this source
+    async fn record_run(&self, run: &SourceRun) -> Result<()>;
+    /// the last run of every source that ran, by source name
+    async fn last_runs(&self) -> Result<Vec<SourceRun>>;
+    /// how many news [`NewsStore::unrated`] would return for `languages` without its limit
+    async fn backlog(&self, languages: Option<&[String]>) -> Result<usize>;
+    /// the best rated news since `since`, best first
+    async fn top(&self, since: DateTime<Utc>, limit: usize) -> Result<Vec<DbNews>>;
+    /// every rated news since `since`, newest first
+    async fn rated_since(&self, since: DateTime<Utc>) -> Result<Vec<DbNews>>;
+    /// forget the rating and leave [`RERATE_NOTE`], so the rater goes through the news again
+    /// whatever its age. false if there is no such news
+    async fn reset_rating(&self, id: &str) -> Result<bool>;
+    /// false if there is no such news
+    async fn set_used(&self, id: &str, used: bool) -> Result<bool>;
+    async fn set_source_enabled(&self, source: &str, enabled: bool) -> Result<()>;
+    async fn disabled_sources(&self) -> Result<Vec<String>>;
+    /// the news matching `filter`, newest first
+    async fn news(&self, filter: &NewsFilter) -> Result<Vec<DbNews>>;
+    async fn get_news(&self, id: &str) -> Result<Option<DbNews>>;
+    /// the updated news, `None` if there is no such news
+    async fn update_news(&self, id: &str, update: &NewsUpdate) -> Result<Option<DbNews>>;
+
+    /// false if there is no such user. with sqlite, which has no signup, the user is created
+    async fn set_user_activated(&self, name: &str, activated: bool) -> Result<bool>;
+    /// only the hash of the token is stored. fails if there is no such user
+    async fn create_token(&self, user: &str, hash: &str, label: &str) -> Result<()>;
+    /// false if there was no such token
+    async fn delete_token(&self, hash: &str) -> Result<bool>;
+    async fn token_user(&self, hash: &str) -> Result<Option<ApiUser>>;
+}
+
+/// open the store selected by `STORE` (`surrealdb` by default, or `sqlite`)
+pub async fn connect(config: &Config) -> Result<Arc<dyn NewsStore>> {
+    match config.store.as_deref().unwrap_or("surrealdb") {
+        "surrealdb" => Ok(Arc::new(SurrealStore::connect(config).await?)),
+        "sqlite" => {
+            let path = config
+                .sqlite_path
+                .as_deref()
+                .unwrap_or(Path::new("news.sqlite"));
+            Ok(Arc::new(SqliteStore::open(path)?))
+        }
+        other => bail!("unknown store `{other}`, expected `surrealdb` or `sqlite`"),
+    }
+}
+
+/// to call before using the store: applies the pending migrations unless `AUTO_MIGRATE=false`,
+/// then refuses to go on if the schema still isn't the expected one. returns the applied
+/// migrations
+pub async fn ensure_schema(store: &dyn NewsStore, config: &Config) -> Result<Vec<u32>> {
+    // on by default: the databases from before the migrations have the schema of the first ones,
+    // which only `DEFINE` and are no-ops there
+    let applied = if config.auto_migrate.unwrap_or(true) {
+        store.migrate().await?
+    } else {
+        Vec::new()
+    };
+    store.check_schema().await?;
+    Ok(applied)
+}
@@ -0,0 +1,625 @@
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::{Mutex, MutexGuard},
+};
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use surrealdb::sql::{Id, Thing};
+
+use super::{
+    news_key, ApiUser, NewsFilter, NewsStore, NewsUpdate, SourceRun, DEFAULT_LIMIT, RERATE_NOTE,
+};
+use crate::{
+    retention::{archive_path, write_archive, ArchivedBody, Policy, RuleReport, ARCHIVE_BATCH},
+    DbNews,
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS news (
+    id INTEGER PRIMARY KEY,
+    html_body TEXT NOT NULL,
+    text_body TEXT NOT NULL,
+    caption TEXT NOT NULL,
+    date TEXT NOT NULL,
+    link TEXT NOT NULL UNIQUE,
+    note TEXT NOT NULL DEFAULT '',
+    provider TEXT NOT NULL,
+    rating INTEGER CHECK (rating BETWEEN 0 AND 100),
+    rating_travel INTEGER CHECK (rating_travel BETWEEN 0 AND 100),
+    tags TEXT NOT NULL DEFAULT '[]',
+    title TEXT NOT NULL,
+    used INTEGER NOT NULL DEFAULT 0,
+    lang TEXT,
+    lang_confidence REAL,
+    translations TEXT NOT NULL DEFAULT '{}',
+    paywalled INTEGER NOT NULL DEFAULT 0,
+    quality_flags TEXT NOT NULL DEFAULT '[]'
+);
+CREATE INDEX IF NOT EXISTS news_date ON news (date);
+CREATE TABLE IF NOT EXISTS source_run (
+    source TEXT PRIMARY KEY,
+    started TEXT NOT NULL,
+    finished TEXT NOT NULL,
+    inserted INTEGER NOT NULL,
+    merged INTEGER NOT NULL,
+    failed INTEGER NOT NULL,
+    error TEXT
+);
+CREATE TABLE IF NOT EXISTS source (
+    name TEXT PRIMARY KEY,
+    enabled INTEGER NOT NULL DEFAULT 1
+);
+CREATE TABLE IF NOT EXISTS user (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    activated INTEGER NOT NULL DEFAULT 0,
+    created_date TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS api_token (
+    hash TEXT PRIMARY KEY,
+    user INTEGER NOT NULL REFERENCES user (id) ON DELETE CASCADE,
+    label TEXT NOT NULL DEFAULT '',
+    created_date TEXT NOT NULL
+);
+";
+
+/// `(table, column, definition)` added after the first version of [`SCHEMA`], which doesn't
+/// change the tables that already exist
+const ADDED_COLUMNS: &[(&str, &str, &str)] = &[
+    ("news", "paywalled", "INTEGER NOT NULL DEFAULT 0"),
+    ("news", "quality_flags", "TEXT NOT NULL DEFAULT '[]'"),
+];
+
+/// embedded store, to run the whole pipeline without a surrealdb server.
+/// rusqlite is blocking, but every query here is short enough to run inline.
+/// the schema is created when opening, the migrations and their versions are only for
+/// surrealdb: the columns added since are added to the files that miss them, see
+/// [`ADDED_COLUMNS`], so `migrate` and `check_schema` have nothing to do.
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+/// dates are stored as rfc3339 in UTC with a fixed precision, so they sort as strings
+fn date_to_sql(date: &DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+fn news_from_row(row: &Row) -> rusqlite::Result<DbNews> {
+    let date = date_from_sql(4, row.get(4)?)?;
+    Ok(DbNews {
+        id: Some(Thing {
+            tb: "news".into(),
+            id: Id::Number(row.get(0)?),
+        }),
+        html_body: row.get::<_, String>(1)?.into(),
+        text_body: row.get::<_, String>(2)?.into(),
+        caption: row.get::<_, String>(3)?.into(),
+        date: date.into(),
+        link: row.get::<_, String>(5)?.into(),
+        note: row.get::<_, String>(6)?.into(),
+        provider: row.get::<_, String>(7)?.into(),
+        rating: row.get(8)?,
+        rating_travel: row.get(9)?,
+        tags: serde_json::from_str(&row.get::<_, String>(10)?).unwrap_or_default(),
+        title: row.get::<_, String>(11)?.into(),
+        used: row.get(12)?,
+        lang: row.get(13)?,
+        lang_confidence: row.get(14)?,
+        translations: serde_json::from_str(&row.get::<_, String>(15)?).unwrap_or_default(),
+        paywalled: row.get(16)?,
+        quality_flags: serde_json::from_str(&row.get::<_, String>(17)?).unwrap_or_default(),
+    })
+}
+
+const COLUMNS: &str = "id, html_body, text_body, caption, date, link, note, provider, rating, rating_travel, tags, title, used, lang, lang_confidence, translations, paywalled, quality_flags";
+
+fn date_from_sql(idx: usize, date: String) -> rusqlite::Result<DateTime<Utc>> {
+    Ok(DateTime::parse_from_rfc3339(&date)
+        .map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Text, e.into())
+        })?
+        .with_timezone(&Utc))
+}
+
+/// ids are given as `news:12` or `12`. None if it can't be a sqlite id, so there is no such news
+fn parse_id(id: &str) -> Option<i64> {
+    news_key(id).parse().ok()
+}
+
+fn row_id(news: &DbNews) -> Result<i64> {
+    match news.id.as_ref().map(|t| &t.id) {
+        Some(Id::Number(id)) => Ok(*id),
+        id => Err(anyhow!("not a sqlite news id: {id:?}")),
+    }
+}
+
+impl SqliteStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let conn = Connection::open(path).with_context(|| format!("opening {path:?}"))?;
+        Self::with_connection(conn)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(conn: Connection) -> Result<Self> {
+        conn.execute_batch(SCHEMA)
+            .context("creating sqlite schema")?;
+        for (table, column, definition) in ADDED_COLUMNS {
+            let exists: bool = conn.query_row(
+                &format!("SELECT count(*) > 0 FROM pragma_table_info('{table}') WHERE name = ?1"),
+                [column],
+                |r| r.get(0),
+            )?;
+            if !exists {
+                conn.execute_batch(&format!(
+                    "ALTER TABLE {table} ADD COLUMN {column} {definition}"
+                ))
+                .with_context(|| format!("adding {table}.{column}"))?;
+            }
+        }
+        Ok(SqliteStore {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap()
+    }
+}
+
+#[async_trait]
+impl NewsStore for SqliteStore {
+    async fn insert(&self, news: &DbNews) -> Result<()> {
+        self.conn().execute(
+            "INSERT INTO news (html_body, text_body, caption, date, link, note, provider, rating, rating_travel, tags, title, used, lang, lang_confidence, translations, paywalled, quality_flags)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)",
+            params![
+                news.html_body,
+                news.text_body,
+                news.caption,
+                date_to_sql(&news.date.0),
+                news.link,
+                news.note,
+                news.provider,
+                news.rating,
+                news.rating_travel,
+                serde_json::to_string(&news.tags)?,
+                news.title,
+                news.used,
+                news.lang,
+                news.lang_confidence,
+                serde_json::to_string(&news.translations)?,
+                news.paywalled,
+                serde_json::to_string(&news.quality_flags)?,
+            ],
+        )?;
+        Ok(())
+    }
+
+    async fn merge_tags(&self, link: &str, tags: &[String]) -> Result<()> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        let current: Option<String> = tx
+            .query_row("SELECT tags FROM news WHERE link = ?1", [link], |r| {
+                r.get(0)
+            })
+            .optional()?;
+        let Some(current) = current else {
+            return Ok(());
+        };
+        let mut current: Vec<String> = serde_json::from_str(&current).unwrap_or_default();
+        for tag in tags {
+            if !current.contains(tag) {
+                current.push(tag.clone());
+            }
+        }
+        tx.execute(
+            "UPDATE news SET tags = ?1 WHERE link = ?2",
+            params![serde_json::to_string(&current)?, link],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    async fn seen_links(&self) -> Result<Vec<String>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT link FROM news")?;
+        let links = stmt
+            .query_map([], |r| r.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(links)
+    }
+
+    async fn unrated(&self, languages: Option<&[String]>) -> Result<Vec<DbNews>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {COLUMNS} FROM news
+            WHERE rating IS NULL
+            AND (date >= ?1 OR note = ?3)
+            AND note NOT LIKE '%error rating%'
+            AND (?2 IS NULL OR lang IS NULL OR lang IN (SELECT value FROM json_each(?2)))
+            ORDER BY date DESC LIMIT 500"
+        ))?;
+        let since = date_to_sql(&(Utc::now() - Duration::weeks(1)));
+        let languages = languages.map(serde_json::to_string).transpose()?;
+        let news = stmt
+            .query_map(params![since, languages, RERATE_NOTE], news_from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(news)
+    }
+
+    async fn save_rating(&self, news: &DbNews) -> Result<()> {
+        let updated = self.conn().execute(
+            "UPDATE news SET rating = ?1, rating_travel = ?2, tags = ?3, note = ?4, translations = ?5 WHERE id = ?6",
+            params![
+                news.rating,
+                news.rating_travel,
+                serde_json::to_string(&news.tags)?,
+                news.note,
+                serde_json::to_string(&news.translations)?,
+                row_id(news)?,
+            ],
+        )?;
+        if updated == 0 {
+            return Err(anyhow!("no news found"));
+        }
+        Ok(())
+    }
+
+    async fn apply_retention(&self, policy: &Policy, dry_run: bool) -> Result<Vec<RuleReport>> {
+        let mut reports: BTreeMap<&str, RuleReport> = policy
+            .rules
+            .iter()
+            .map(|r| {
+                let report = RuleReport {
+                    rule: r.name.clone(),
+                    ..Default::default()
+                };
+                (r.name.as_str(), report)
+            })
+            .collect();
+        // by batches of rowids, so the other calls get the connection in between
+        let mut after = 0;
+        loop {
+            let batch = {
+                let conn = self.conn();
+                let mut stmt = conn.prepare(&format!(
+                    "SELECT {COLUMNS} FROM news WHERE html_body != '' AND id > ?1 ORDER BY id LIMIT {ARCHIVE_BATCH}"
+                ))?;
+                let rows = stmt.query_map([after], news_from_row)?;
+                rows.collect::<rusqlite::Result<Vec<_>>>()?
+            };
+            let Some(last) = batch.last() else {
+                break;
+            };
+            after = row_id(last)?;
+            let mut archives: BTreeMap<&str, Vec<ArchivedBody>> = BTreeMap::new();
+            let mut wipe = Vec::new();
+            for news in batch {
+                let Some(rule) = policy.rule_for(&news) else {
+                    continue;
+                };
+                let report = reports.get_mut(rule.name.as_str()).unwrap();
+                report.matched += 1;
+                let Some(days) = rule.max_age_days else {
+                    continue;
+                };
+                if news.date.0 >= Utc::now() - Duration::days(days.into()) {
+                    continue;
+                }
+                report.wiped += 1;
+                wipe.push(row_id(&news)?);
+                if policy.archive_dir.is_some() && !dry_run {
+                    report.archived += 1;
+                    archives
+                        .entry(rule.name.as_str())
+                        .or_default()
+                        .push(ArchivedBody {
+                            id: news.id.clone().unwrap(),
+                            link: news.link.into(),
+                            provider: news.provider.into(),
+                            date: news.date,
+                            html_body: news.html_body.into(),
+                            text_body: news.text_body.into(),
+                        });
+                }
+            }
+            // a dry run only reports what would be wiped
+            if dry_run {
+                continue;
+            }
+            if let Some(dir) = &policy.archive_dir {
+                for rule in &policy.rules {
+                    if let Some(bodies) = archives.get(rule.name.as_str()) {
+                        write_archive(&archive_path(dir, rule)?, bodies)?;
+                    }
+                }
+            }
+            let mut conn = self.conn();
+            let tx = conn.transaction()?;
+            for id in wipe {
+                tx.execute(
+                    "UPDATE news SET html_body = '', text_body = '' WHERE id = ?1",
+                    [id],
+                )?;
+            }
+            tx.commit()?;
+        }
+        Ok(policy
+            .rules
+            .iter()
+            .filter_map(|r| reports.remove(r.name.as_str()))
+            .collect())
+    }
+
+    async fn migrate(&self) -> Result<Vec<u32>> {
+        Ok(Vec::new())
+    }
+
+    async fn check_schema(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn record_run(&self, run: &SourceRun) -> Result<()> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO source_run (source, started, finished, inserted, merged, failed, error)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                run.source,
+                date_to_sql(&run.started.0),
+                date_to_sql(&run.finished.0),
+                run.inserted,
+                run.merged,
+                run.failed,
+                run.error,
+            ],
+        )?;
+        Ok(())
+    }
+
+    async fn last_runs(&self) -> Result<Vec<SourceRun>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT source, started, finished, inserted, merged, failed, error FROM source_run ORDER BY source",
+        )?;
+        let runs = stmt
+            .query_map([], |row| {
+                Ok(SourceRun {
+                    source: row.get(0)?,
+                    started: date_from_sql(1, row.get(1)?)?.into(),
+                    finished: date_from_sql(2, row.get(2)?)?.into(),
+                    inserted: row.get(3)?,
+                    merged: row.get(4)?,
+                    failed: row.get(5)?,
+                    error: row.get(6)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(runs)
+    }
+
+    async fn backlog(&self, languages: Option<&[String]>) -> Result<usize> {
+        let since = date_to_sql(&(Utc::now() - Duration::weeks(1)));
+        let languages = languages.map(serde_json::to_string).transpose()?;
+        Ok(self.conn().query_row(
+            "SELECT count(*) FROM news
+            WHERE rating IS NULL
+            AND (date >= ?1 OR note = ?3)
+            AND note NOT LIKE '%error rating%'
+            AND (?2 IS NULL OR lang IS NULL OR lang IN (SELECT value FROM json_each(?2)))",
+            params![since, languages, RERATE_NOTE],
+            |r| r.get(0),
+        )?)
+    }
+
+    async fn top(&self, since: DateTime<Utc>, limit: usize) -> Result<Vec<DbNews>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {COLUMNS} FROM news
+            WHERE rating IS NOT NULL AND date >= ?1
+            ORDER BY rating DESC LIMIT ?2"
+        ))?;
+        let news = stmt
+            .query_map(params![date_to_sql(&since), limit], news_from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(news)
+    }
+
+    async fn rated_since(&self, since: DateTime<Utc>) -> Result<Vec<DbNews>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {COLUMNS} FROM news
+            WHERE rating IS NOT NULL AND date >= ?1
+            ORDER BY date DESC"
+        ))?;
+        let news = stmt
+            .query_map([date_to_sql(&since)], news_from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(news)
+    }
+
+    async fn reset_rating(&self, id: &str) -> Result<bool> {
+        let Some(id) = parse_id(id) else {
+            return Ok(false);
+        };
+        let updated = self.conn().execute(
+            "UPDATE news SET rating = NULL, rating_travel = NULL, note = ?1 WHERE id = ?2",
+            params![RERATE_NOTE, id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    async fn set_used(&self, id: &str, used: bool) -> Result<bool> {
+        let Some(id) = parse_id(id) else {
+            return Ok(false);
+        };
+        let updated = self
+            .conn()
+            .execute("UPDATE news SET used = ?1 WHERE id = ?2", params![used, id])?;
+        Ok(updated > 0)
+    }
+
+    async fn set_source_enabled(&self, source: &str, enabled: bool) -> Result<()> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO source (name, enabled) VALUES (?1, ?2)",
+            params![source, enabled],
+        )?;
+        Ok(())
+    }
+
+    async fn disabled_sources(&self) -> Result<Vec<String>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT name FROM source WHERE enabled = 0 ORDER BY name")?;
+        let sources = stmt
+            .query_map([], |r| r.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(sources)
+    }
+
+    async fn news(&self, filter: &NewsFilter) -> Result<Vec<DbNews>> {
+        let after_id = match &filter.after {
+            Some(cursor) => Some(
+                parse_id(&cursor.id).ok_or_else(|| anyhow!("invalid cursor id {}", cursor.id))?,
+            ),
+            None => None,
+        };
+        let tags = match filter.tags.as_slice() {
+            [] => None,
+            tags => Some(serde_json::to_string(tags)?),
+        };
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {COLUMNS} FROM news
+            WHERE (?1 IS NULL OR date >= ?1)
+            AND (?2 IS NULL OR date < ?2)
+            AND (?3 IS NULL OR provider = ?3)
+            AND (?4 IS NULL OR EXISTS (SELECT 1 FROM json_each(tags) WHERE value = ?4))
+            AND (?5 IS NULL OR NOT EXISTS (
+                SELECT 1 FROM json_each(?5) AS wanted
+                WHERE wanted.value NOT IN (SELECT value FROM json_each(news.tags))
+            ))
+            AND (?6 IS NULL OR rating >= ?6)
+            AND (?7 IS NULL OR rating <= ?7)
+            AND (?8 IS NULL OR rating_travel >= ?8)
+            AND (?9 IS NULL OR rating_travel <= ?9)
+            AND (?10 IS NULL OR used = ?10)
+            AND (?11 IS NULL OR lang = ?11)
+            AND (?12 IS NULL
+                OR instr(lower(title), ?12) > 0
+                OR instr(lower(caption), ?12) > 0
+                OR instr(lower(text_body), ?12) > 0)
+            AND (?13 IS NULL OR date < ?13 OR (date = ?13 AND id < ?14))
+            ORDER BY date DESC, id DESC LIMIT ?15"
+        ))?;
+        let news = stmt
+            .query_map(
+                params![
+                    filter.since.as_ref().map(date_to_sql),
+                    filter.until.as_ref().map(date_to_sql),
+                    filter.provider,
+                    filter.region,
+                    tags,
+                    filter.min_rating,
+                    filter.max_rating,
+                    filter.min_rating_travel,
+                    filter.max_rating_travel,
+                    filter.used,
+                    filter.lang,
+                    filter.text.as_ref().map(|t| t.to_lowercase()),
+                    filter.after.as_ref().map(|c| date_to_sql(&c.date)),
+                    after_id,
+                    filter.limit.unwrap_or(DEFAULT_LIMIT),
+                ],
+                news_from_row,
+            )?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(news)
+    }
+
+    async fn get_news(&self, id: &str) -> Result<Option<DbNews>> {
+        let Some(id) = parse_id(id) else {
+            return Ok(None);
+        };
+        Ok(self
+            .conn()
+            .query_row(
+                &format!("SELECT {COLUMNS} FROM news WHERE id = ?1"),
+                [id],
+                news_from_row,
+            )
+            .optional()?)
+    }
+
+    async fn update_news(&self, id: &str, update: &NewsUpdate) -> Result<Option<DbNews>> {
+        let Some(row) = parse_id(id) else {
+            return Ok(None);
+        };
+        let tags = update
+            .tags
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
+        let updated = self.conn().execute(
+            "UPDATE news SET note = coalesce(?1, note), used = coalesce(?2, used), tags = coalesce(?3, tags) WHERE id = ?4",
+            params![update.note, update.used, tags, row],
+        )?;
+        if updated == 0 {
+            return Ok(None);
+        }
+        self.get_news(id).await
+    }
+
+    async fn set_user_activated(&self, name: &str, activated: bool) -> Result<bool> {
+        self.conn().execute(
+            "INSERT INTO user (name, activated, created_date) VALUES (?1, ?2, ?3)
+            ON CONFLICT (name) DO UPDATE SET activated = excluded.activated",
+            params![name, activated, date_to_sql(&Utc::now())],
+        )?;
+        Ok(true)
+    }
+
+    async fn create_token(&self, user: &str, hash: &str, label: &str) -> Result<()> {
+        let conn = self.conn();
+        let user_id: Option<i64> = conn
+            .query_row("SELECT id FROM user WHERE name = ?1", [user], |r| r.get(0))
+            .optional()?;
+        let user_id = user_id.ok_or_else(|| anyhow!("no user {user}"))?;
+        conn.execute(
+            "INSERT INTO api_token (hash, user, label, created_date) VALUES (?1, ?2, ?3, ?4)",
+            params![hash, user_id, label, date_to_sql(&Utc::now())],
+        )?;
+        Ok(())
+    }
+
+    async fn delete_token(&self, hash: &str) -> Result<bool> {
+        let deleted = self
+            .conn()
+            .execute("DELETE FROM api_token WHERE hash = ?1", [hash])?;
+        Ok(deleted > 0)
+    }
+
+    async fn token_user(&self, hash: &str) -> Result<Option<ApiUser>> {
+        Ok(self
+            .conn()
+            .query_row(
+                "SELECT user.name, user.activated FROM api_token
+                JOIN user ON user.id = api_token.user
+                WHERE api_token.hash = ?1",
+                [hash],
+                |r| {
+                    Ok(ApiUser {
+                        name: r.get(0)?,
+                        activated: r.get(1)?,
+                    })
+                },
+            )
+            .optional()?)
+    }
+}
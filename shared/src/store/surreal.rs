@@ -0,0 +1,352 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use surrealdb::{
+    engine::remote::http::{Client, Http},
+    opt::auth::Root,
+    sql::{Datetime, Thing},
+    Surreal,
+};
+
+use super::{
+    news_key, ApiUser, NewsFilter, NewsStore, NewsUpdate, SourceRun, DEFAULT_LIMIT, RERATE_NOTE,
+};
+use crate::{
+    migrate,
+    retention::{Policy, RuleReport},
+    Config, DbNews,
+};
+
+/// what's left of an updated or deleted record, to know if there was one
+#[derive(Deserialize)]
+struct Updated {
+    #[allow(dead_code)]
+    id: Thing,
+}
+
+/// `update` the news `$id` with `changes`, its result is the second one of the query. `update
+/// news:id` alone would create the record, and `update news where id = ..` scans the table
+fn update_news_query(changes: &str) -> String {
+    format!(
+        "let $news = type::thing('news', $id);
+if (select value id from only $news) {{ update $news {changes} return after }} else {{ [] }};"
+    )
+}
+
+pub struct SurrealStore<C: surrealdb::Connection> {
+    pub db: Surreal<C>,
+}
+
+impl SurrealStore<Client> {
+    pub async fn connect(config: &Config) -> Result<Self> {
+        let db = Surreal::new::<Http>(&config.surrealdb_host).await?;
+        db.signin(Root {
+            username: config.db_user.as_deref().unwrap_or_default(),
+            password: config.db_password.as_deref().unwrap_or_default(),
+        })
+        .await
+        .context("connecting to surrealdb")?;
+        db.use_ns("news").use_db("news").await?;
+        Ok(SurrealStore { db })
+    }
+}
+
+impl<C: surrealdb::Connection> SurrealStore<C> {
+    pub fn new(db: Surreal<C>) -> Self {
+        SurrealStore { db }
+    }
+}
+
+#[async_trait]
+impl<C: surrealdb::Connection> NewsStore for SurrealStore<C> {
+    async fn insert(&self, news: &DbNews) -> Result<()> {
+        let _: Vec<DbNews> = self.db.create("news").content(news).await?;
+        Ok(())
+    }
+
+    async fn merge_tags(&self, link: &str, tags: &[String]) -> Result<()> {
+        self.db
+            .query("update news set tags = array::union(tags, $newtags) where link = $link return none")
+            .bind(("newtags", tags))
+            .bind(("link", link))
+            .await?
+            .check()?;
+        Ok(())
+    }
+
+    async fn seen_links(&self) -> Result<Vec<String>> {
+        Ok(self
+            .db
+            .query("select value link from news parallel")
+            .await?
+            .take(0)
+            .unwrap_or_default())
+    }
+
+    async fn unrated(&self, languages: Option<&[String]>) -> Result<Vec<DbNews>> {
+        let lang_filter = match languages {
+            Some(_) => "AND (lang = NONE OR lang INSIDE $languages)",
+            None => "",
+        };
+        let db_news: Vec<DbNews> = self
+            .db
+            .query(format!(
+                "return select * from news
+where rating == none
+AND (date >= time::now() - 1w OR note = $rerate)
+AND !string::contains(note, 'error rating')
+{lang_filter}
+ORDER BY date DESC limit 500"
+            ))
+            .bind(("languages", languages))
+            .bind(("rerate", RERATE_NOTE))
+            .await?
+            .take(0)?;
+        Ok(db_news)
+    }
+
+    async fn save_rating(&self, news: &DbNews) -> Result<()> {
+        let id = news.id.clone().ok_or(anyhow!("news without id"))?;
+        self.db
+            .update::<Option<DbNews>>(("news", id))
+            .content(news)
+            .await
+            .context("surrealdb error")?
+            .ok_or(anyhow!("no news found"))?;
+        Ok(())
+    }
+
+    async fn apply_retention(&self, policy: &Policy, dry_run: bool) -> Result<Vec<RuleReport>> {
+        policy.apply(&self.db, dry_run).await
+    }
+
+    async fn migrate(&self) -> Result<Vec<u32>> {
+        migrate::migrate(&self.db).await
+    }
+
+    async fn check_schema(&self) -> Result<()> {
+        migrate::status(&self.db).await?.check()
+    }
+
+    async fn record_run(&self, run: &SourceRun) -> Result<()> {
+        self.db
+            .query("update type::thing('source_run', $run.source) content $run return none")
+            .bind(("run", run))
+            .await?
+            .check()?;
+        Ok(())
+    }
+
+    async fn last_runs(&self) -> Result<Vec<SourceRun>> {
+        Ok(self
+            .db
+            .query("select * omit id from source_run order by source")
+            .await?
+            .take(0)?)
+    }
+
+    async fn backlog(&self, languages: Option<&[String]>) -> Result<usize> {
+        let lang_filter = match languages {
+            Some(_) => "AND (lang = NONE OR lang INSIDE $languages)",
+            None => "",
+        };
+        let count: Option<usize> = self
+            .db
+            .query(format!(
+                "return count(select id from news
+where rating == none
+AND (date >= time::now() - 1w OR note = $rerate)
+AND !string::contains(note, 'error rating')
+{lang_filter})"
+            ))
+            .bind(("languages", languages))
+            .bind(("rerate", RERATE_NOTE))
+            .await?
+            .take(0)?;
+        Ok(count.unwrap_or_default())
+    }
+
+    async fn top(&self, since: DateTime<Utc>, limit: usize) -> Result<Vec<DbNews>> {
+        Ok(self
+            .db
+            .query("select * from news where rating != none AND date >= $since order by rating desc limit $limit")
+            .bind(("since", Datetime::from(since)))
+            .bind(("limit", limit))
+            .await?
+            .take(0)?)
+    }
+
+    async fn rated_since(&self, since: DateTime<Utc>) -> Result<Vec<DbNews>> {
+        Ok(self
+            .db
+            .query("select * from news where rating != none AND date >= $since order by date desc")
+            .bind(("since", Datetime::from(since)))
+            .await?
+            .take(0)?)
+    }
+
+    async fn reset_rating(&self, id: &str) -> Result<bool> {
+        let updated: Vec<DbNews> = self
+            .db
+            .query(update_news_query(
+                "set rating = none, rating_travel = none, note = $rerate",
+            ))
+            .bind(("id", news_key(id)))
+            .bind(("rerate", RERATE_NOTE))
+            .await?
+            .take(1)?;
+        Ok(!updated.is_empty())
+    }
+
+    async fn set_used(&self, id: &str, used: bool) -> Result<bool> {
+        let updated: Vec<DbNews> = self
+            .db
+            .query(update_news_query("set used = $used"))
+            .bind(("id", news_key(id)))
+            .bind(("used", used))
+            .await?
+            .take(1)?;
+        Ok(!updated.is_empty())
+    }
+
+    async fn set_source_enabled(&self, source: &str, enabled: bool) -> Result<()> {
+        self.db
+            .query("update type::thing('source', $source) set enabled = $enabled return none")
+            .bind(("source", source))
+            .bind(("enabled", enabled))
+            .await?
+            .check()?;
+        Ok(())
+    }
+
+    async fn disabled_sources(&self) -> Result<Vec<String>> {
+        Ok(self
+            .db
+            .query("select value meta::id(id) from source where enabled = false")
+            .await?
+            .take(0)?)
+    }
+
+    async fn news(&self, filter: &NewsFilter) -> Result<Vec<DbNews>> {
+        let mut conditions = vec!["true"];
+        for (set, condition) in [
+            (filter.since.is_some(), "date >= $since"),
+            (filter.until.is_some(), "date < $until"),
+            (filter.provider.is_some(), "provider = $provider"),
+            (filter.region.is_some(), "tags CONTAINS $region"),
+            (!filter.tags.is_empty(), "tags CONTAINSALL $tags"),
+            (filter.min_rating.is_some(), "rating >= $min_rating"),
+            (filter.max_rating.is_some(), "rating <= $max_rating"),
+            (filter.min_rating_travel.is_some(), "rating_travel >= $min_rating_travel"),
+            (filter.max_rating_travel.is_some(), "rating_travel <= $max_rating_travel"),
+            (filter.used.is_some(), "used = $used"),
+            (filter.lang.is_some(), "lang = $lang"),
+            (
+                filter.text.is_some(),
+                "(string::contains(string::lowercase(title), $text)
+                OR string::contains(string::lowercase(caption), $text)
+                OR string::contains(string::lowercase(text_body), $text))",
+            ),
+            (
+                filter.after.is_some(),
+                "(date < $after_date OR (date = $after_date AND id < type::thing('news', $after_id)))",
+            ),
+        ] {
+            if set {
+                conditions.push(condition);
+            }
+        }
+        Ok(self
+            .db
+            .query(format!(
+                "select * from news where {} order by date desc, id desc limit $limit",
+                conditions.join(" AND ")
+            ))
+            .bind(("since", filter.since.map(Datetime::from)))
+            .bind(("until", filter.until.map(Datetime::from)))
+            .bind(("provider", &filter.provider))
+            .bind(("region", &filter.region))
+            .bind(("tags", &filter.tags))
+            .bind(("min_rating", filter.min_rating))
+            .bind(("max_rating", filter.max_rating))
+            .bind(("min_rating_travel", filter.min_rating_travel))
+            .bind(("max_rating_travel", filter.max_rating_travel))
+            .bind(("used", filter.used))
+            .bind(("lang", &filter.lang))
+            .bind(("text", filter.text.as_ref().map(|t| t.to_lowercase())))
+            .bind((
+                "after_date",
+                filter.after.as_ref().map(|c| Datetime::from(c.date)),
+            ))
+            .bind(("after_id", filter.after.as_ref().map(|c| &c.id)))
+            .bind(("limit", filter.limit.unwrap_or(DEFAULT_LIMIT)))
+            .await?
+            .take(0)?)
+    }
+
+    async fn get_news(&self, id: &str) -> Result<Option<DbNews>> {
+        Ok(self
+            .db
+            .query("select * from only type::thing('news', $id)")
+            .bind(("id", news_key(id)))
+            .await?
+            .take(0)?)
+    }
+
+    async fn update_news(&self, id: &str, update: &NewsUpdate) -> Result<Option<DbNews>> {
+        let updated: Vec<DbNews> = self
+            .db
+            .query(update_news_query("merge $update"))
+            .bind(("id", news_key(id)))
+            .bind(("update", update))
+            .await?
+            .take(1)?;
+        Ok(updated.into_iter().next())
+    }
+
+    async fn set_user_activated(&self, name: &str, activated: bool) -> Result<bool> {
+        let updated: Vec<Updated> = self
+            .db
+            .query("update user set activated = $activated where name = $name return id")
+            .bind(("name", name))
+            .bind(("activated", activated))
+            .await?
+            .take(0)?;
+        Ok(!updated.is_empty())
+    }
+
+    async fn create_token(&self, user: &str, hash: &str, label: &str) -> Result<()> {
+        self.db
+            .query(
+                "let $user = (select value id from only user where name = $name limit 1);
+if !$user { throw 'no user ' + $name };
+create api_token content { user: $user, hash: $hash, label: $label } return none;",
+            )
+            .bind(("name", user))
+            .bind(("hash", hash))
+            .bind(("label", label))
+            .await?
+            .check()?;
+        Ok(())
+    }
+
+    async fn delete_token(&self, hash: &str) -> Result<bool> {
+        let deleted: Vec<Updated> = self
+            .db
+            .query("delete api_token where hash = $hash return before")
+            .bind(("hash", hash))
+            .await?
+            .take(0)?;
+        Ok(!deleted.is_empty())
+    }
+
+    async fn token_user(&self, hash: &str) -> Result<Option<ApiUser>> {
+        Ok(self
+            .db
+            .query("select user.name as name, user.activated as activated from only api_token where hash = $hash limit 1")
+            .bind(("hash", hash))
+            .await?
+            .take(0)?)
+    }
+}
@@ -0,0 +1,106 @@
+use anyhow::{anyhow, Context, Result};
+use async_openai::{
+    config::OpenAIConfig,
+    types::{
+        ChatCompletionRequestSystemMessage, ChatCompletionRequestUserMessage,
+        CreateChatCompletionRequestArgs, ReasoningEffort, ResponseFormat,
+    },
+    Client as ChatClient,
+};
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+use crate::{lang, metrics, DbNews};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Translation {
+    pub title: String,
+    pub caption: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+}
+
+impl DbNews {
+    /// translate title and caption (and the text body if `with_body`) into every `targets` language
+    /// that isn't the article's own language. returns the languages that were added.
+    pub async fn translate(
+        &mut self,
+        client: &ChatClient<OpenAIConfig>,
+        targets: &[String],
+        with_body: bool,
+    ) -> Result<Vec<String>> {
+        let source = self.lang.clone().or_else(|| {
+            lang::detect(&self.text_body)
+                .or_else(|| lang::detect(&self.title))
+                .map(|d| d.lang)
+        });
+        let mut done = Vec::new();
+        for target in targets {
+            if source.as_deref() == Some(target.as_str()) || self.translations.contains_key(target)
+            {
+                continue;
+            }
+            let translation = translate_one(client, self, source.as_deref(), target, with_body)
+                .await
+                .with_context(|| format!("translating to {target}"))?;
+            self.translations.insert(target.clone(), translation);
+            done.push(target.clone());
+        }
+        Ok(done)
+    }
+}
+
+async fn translate_one(
+    client: &ChatClient<OpenAIConfig>,
+    news: &DbNews,
+    source: Option<&str>,
+    target: &str,
+    with_body: bool,
+) -> Result<Translation> {
+    let input = Translation {
+        title: news.title.to_string(),
+        caption: news.caption.to_string(),
+        body: with_body.then(|| news.text_body.to_string()),
+    };
+    let from = source.map(|s| format!(" from `{s}`")).unwrap_or_default();
+    let prompt = format!(
+        "translate this news article{from} to `{target}`. keep the meaning and the tone, don't summarize. answer with a json object with exactly the same keys as the input."
+    );
+    let conv = vec![
+        ChatCompletionRequestSystemMessage {
+            content: prompt.into(),
+            ..Default::default()
+        }
+        .into(),
+        ChatCompletionRequestUserMessage {
+            content: serde_json::to_string(&input)?.into(),
+            ..Default::default()
+        }
+        .into(),
+    ];
+    let request = CreateChatCompletionRequestArgs::default()
+        .model("gpt-5-nano")
+        .max_completion_tokens(if with_body { 8000u32 } else { 1000u32 })
+        .reasoning_effort(ReasoningEffort::Low)
+        .response_format(ResponseFormat::JsonObject)
+        .messages(conv)
+        .build()
+        .unwrap();
+    let started = Instant::now();
+    let response = client
+        .chat()
+        .create(request)
+        .await
+        .context("created openai request")?;
+    metrics::observe_llm("translate", started, response.usage.as_ref());
+    let content = response
+        .choices
+        .first()
+        .and_then(|c| c.message.content.clone())
+        .ok_or(anyhow!("no content in response: {response:?}"))?;
+    let mut translation: Translation = serde_json::from_str(&content).context(content.clone())?;
+    if !with_body {
+        translation.body = None;
+    }
+    Ok(translation)
+}
@@ -1,13 +1,13 @@
-use std::path::PathBuf;
+use std::{fs, path::PathBuf};
 
-use shared::config::Config;
+use shared::config::{Config, Loader};
 
 #[test]
 fn test_load_config_default_path() {
     let config = Config::load("tests/mock_env").unwrap();
-    assert_eq!(config.db_user, "news");
-    assert_eq!(config.db_password, "arstneoi");
-    assert_eq!(config.openai_api_key, "KEYY");
+    assert_eq!(config.db_user.as_deref(), Some("news"));
+    assert_eq!(config.db_password.as_deref(), Some("arstneoi"));
+    assert_eq!(config.openai_api_key.as_deref(), Some("KEYY"));
     assert_eq!(config.article_parser_url, "http://localhost:8080");
     assert_eq!(config.surrealdb_host, "localhost:8000");
     assert_eq!(config.parallel_rating, 10);
@@ -23,3 +23,131 @@ fn test_load_config_invalid_path() {
     println!("{config:?}");
     config.unwrap();
 }
+
+fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+    vars.iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+#[test]
+fn test_layers_override_each_other() {
+    let config = Loader::new(Some("rater"))
+        .config_file("tests/mock_config.toml")
+        .env_vars(vars(&[
+            ("DB_PASSWORD_FILE", "tests/mock_secret"),
+            ("PARALLEL_RATING", "4"),
+            ("UNRELATED", "ignored"),
+        ]))
+        .set("translate_body", true)
+        .load()
+        .unwrap();
+    // defaults
+    assert_eq!(config.article_parser_url, "http://127.0.0.1:8080");
+    // top of the file
+    assert_eq!(config.db_user.as_deref(), Some("news"));
+    assert_eq!(config.rater_languages, Some(vec!["fr".into(), "en".into()]));
+    // [rater] section, not [fetcher]
+    assert_eq!(config.openai_api_key.as_deref(), Some("sk-from-file"));
+    assert_eq!(config.chrome_concurrent, None);
+    // env over the file, secret file
+    assert_eq!(config.parallel_rating, 4);
+    assert_eq!(config.db_password.as_deref(), Some("hunter2"));
+    // command line
+    assert_eq!(config.translate_body, Some(true));
+
+    let fetcher = Loader::new(Some("fetcher"))
+        .config_file("tests/mock_config.toml")
+        .env_vars(vars(&[("DB_PASSWORD", "x")]))
+        .load()
+        .unwrap();
+    assert_eq!(fetcher.chrome_concurrent, Some(2));
+    assert_eq!(fetcher.parallel_rating, 10);
+}
+
+#[test]
+fn test_redacted() {
+    let config = Loader::new(Some("rater"))
+        .config_file("tests/mock_config.toml")
+        .env_vars(vars(&[("DB_PASSWORD_FILE", "tests/mock_secret")]))
+        .load()
+        .unwrap();
+    let printed = config.redacted_toml().unwrap();
+    assert!(!printed.contains("hunter2"), "{printed}");
+    assert!(!printed.contains("sk-from-file"), "{printed}");
+    assert!(printed.contains("db_password = \"*******\""), "{printed}");
+    assert!(printed.contains("parallel_rating = 10"), "{printed}");
+    assert!(!format!("{config:?}").contains("hunter2"));
+}
+
+#[test]
+fn test_validation() {
+    let err = Loader::new(Some("rater"))
+        .env_vars(vars(&[
+            ("STORE", "sqlite"),
+            ("TELEGRAM_ID", "12"),
+            ("RATER_LANGUAGES", "fr,english"),
+            ("LOG_FORMAT", "logfmt"),
+            ("RATER_PAYWALLED", "ignore"),
+            ("NOTIFY_WINDOW", "0"),
+        ]))
+        .load()
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("TELEGRAM_TOKEN and TELEGRAM_ID"), "{err}");
+    assert!(err.contains("`english`"), "{err}");
+    assert!(err.contains("LOG_FORMAT"), "{err}");
+    assert!(err.contains("RATER_PAYWALLED"), "{err}");
+    assert!(err.contains("NOTIFY_WINDOW"), "{err}");
+    assert!(err.contains("OPENAI_API_KEY"), "{err}");
+    assert!(!err.contains("DB_USER"), "{err}");
+
+    // the fetcher doesn't need the openai key
+    Loader::new(Some("fetcher"))
+        .env_vars(vars(&[("STORE", "sqlite")]))
+        .load()
+        .unwrap();
+    let err = Loader::new(Some("fetcher"))
+        .env_vars(vars(&[]))
+        .load()
+        .unwrap_err();
+    assert!(err.to_string().contains("DB_USER"));
+}
+
+#[test]
+fn test_invalid_settings() {
+    let dir = std::env::temp_dir().join("news-scraper-config-test");
+    fs::create_dir_all(&dir).unwrap();
+    let unknown = dir.join("unknown.toml");
+    fs::write(&unknown, "store = \"sqlite\"\nchrome_concurent = 3\n").unwrap();
+    let err = Loader::new(Some("fetcher"))
+        .config_file(&unknown)
+        .env_vars(vars(&[]))
+        .load()
+        .unwrap_err();
+    assert!(format!("{err:#}").contains("unknown setting `chrome_concurent`"));
+
+    let section = dir.join("section.toml");
+    fs::write(&section, "[digest]\nstore = \"sqlite\"\n").unwrap();
+    assert!(Loader::new(Some("fetcher"))
+        .config_file(&section)
+        .env_vars(vars(&[]))
+        .load()
+        .is_err());
+
+    let both = Loader::new(Some("fetcher"))
+        .env_vars(vars(&[
+            ("STORE", "sqlite"),
+            ("DB_PASSWORD", "a"),
+            ("DB_PASSWORD_FILE", "tests/mock_secret"),
+        ]))
+        .load()
+        .unwrap_err();
+    assert!(format!("{both:#}").contains("both"));
+
+    assert!(Loader::new(Some("fetcher"))
+        .env_vars(vars(&[("STORE", "sqlite")]))
+        .set("chrome_concurrent", "many")
+        .load()
+        .is_err());
+}
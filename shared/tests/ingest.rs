@@ -0,0 +1,69 @@
+use std::sync::RwLock;
+
+use chrono::Local;
+use shared::{
+    ingest::{canonical_link, ingest_checked, region_of, Ingested},
+    store::{NewsStore, SqliteStore},
+    News,
+};
+
+#[test]
+fn test_canonical_link() {
+    for (link, canonical) in [
+        ("https://a.fr/1", "https://a.fr/1"),
+        (" https://a.fr/1?id=2\n", "https://a.fr/1?id=2"),
+        ("https://a.fr/1#comments", "https://a.fr/1"),
+        (
+            "https://a.fr/1?utm_source=rss&utm_medium=feed",
+            "https://a.fr/1",
+        ),
+        ("https://a.fr/1?id=2&fbclid=abc#top", "https://a.fr/1?id=2"),
+        // untouched, even if the url crate would write it differently
+        ("https://A.fr", "https://A.fr"),
+        ("not a link", "not a link"),
+    ] {
+        assert_eq!(canonical_link(link), canonical, "{link}");
+    }
+}
+
+#[tokio::test]
+async fn test_rejected_news_are_not_seen() {
+    let store = SqliteStore::open_in_memory().unwrap();
+    let seen = RwLock::new(Vec::new());
+    let news = |provider: &str| News {
+        title: "title".into(),
+        link: "https://a.fr/1?utm_source=rss".into(),
+        provider: provider.into(),
+        body: "<p>body</p>".into(),
+        date: Local::now(),
+        ..Default::default()
+    };
+
+    let ingested = ingest_checked(&store, &seen, news("fr::google"), |n| {
+        assert!(n.text_body.contains("body"));
+        false
+    })
+    .await;
+    assert_eq!(ingested.unwrap(), Ingested::Rejected);
+    assert!(seen.read().unwrap().is_empty());
+    assert!(store.seen_links().await.unwrap().is_empty());
+
+    let ingested = ingest_checked(&store, &seen, news("lme::geo"), |_| true).await;
+    assert_eq!(ingested.unwrap(), Ingested::Inserted);
+    // a link already saved only gets the tags of the next source, it isn't checked again
+    let ingested = ingest_checked(&store, &seen, news("fr::google"), |_| {
+        panic!("checked a known link")
+    })
+    .await;
+    assert_eq!(ingested.unwrap(), Ingested::Merged);
+    assert_eq!(store.seen_links().await.unwrap(), ["https://a.fr/1"]);
+    // both sources tagged it with their region
+    let saved = store.unrated(None).await.unwrap();
+    assert_eq!(saved[0].tags, ["lme", "fr"]);
+}
+
+#[test]
+fn test_region_of() {
+    assert_eq!(region_of("fr::google"), "fr");
+    assert_eq!(region_of("imported"), "imported");
+}
@@ -0,0 +1,18 @@
+use shared::lang;
+
+#[test]
+fn test_detect() {
+    let fr = lang::detect(
+        "Les bénévoles du refuge ont soigné plus de cent hérissons blessés cette année.",
+    )
+    .unwrap();
+    assert_eq!(fr.lang, "fr");
+    assert!(fr.confidence > 0.5);
+    let en = lang::detect(
+        "The volunteers of the shelter cared for more than a hundred injured hedgehogs this year.",
+    )
+    .unwrap();
+    assert_eq!(en.lang, "en");
+    assert_eq!(lang::detect(""), None);
+    assert_eq!(lang::detect("42 !"), None);
+}
@@ -0,0 +1,53 @@
+use std::sync::{Arc, Mutex};
+
+use shared::logging::{fmt_layer, traces_url, Format};
+use tracing_subscriber::layer::SubscriberExt;
+
+#[test]
+fn test_json_logs_have_the_span_fields() {
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let writer = buffer.clone();
+    let subscriber = tracing_subscriber::registry()
+        .with(fmt_layer(Format::Json, move || WriteTo(writer.clone())));
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("article", provider = "fr::google", link = "https://a.fr/1");
+        let _entered = span.enter();
+        tracing::warn!(error = "timeout", "saving failed one time");
+    });
+
+    let output = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+    let line: serde_json::Value = serde_json::from_str(output.trim()).unwrap();
+    assert_eq!(line["level"], "WARN");
+    assert_eq!(line["message"], "saving failed one time");
+    assert_eq!(line["error"], "timeout");
+    let span = &line["spans"][0];
+    assert_eq!(span["name"], "article");
+    assert_eq!(span["provider"], "fr::google");
+    assert_eq!(span["link"], "https://a.fr/1");
+}
+
+#[test]
+fn test_format_and_traces_url() {
+    assert_eq!(Format::parse(None).unwrap(), Format::Text);
+    assert_eq!(Format::parse(Some("json")).unwrap(), Format::Json);
+    assert!(Format::parse(Some("logfmt")).is_err());
+    for endpoint in [
+        "http://127.0.0.1:4318",
+        "http://127.0.0.1:4318/",
+        "http://127.0.0.1:4318/v1/traces",
+    ] {
+        assert_eq!(traces_url(endpoint), "http://127.0.0.1:4318/v1/traces");
+    }
+}
+
+struct WriteTo(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for WriteTo {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
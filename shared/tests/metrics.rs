@@ -0,0 +1,64 @@
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+};
+
+use anyhow::anyhow;
+use shared::metrics::{self, llm_error_class};
+
+#[test]
+fn test_llm_error_class() {
+    for (error, class) in [
+        ("created openai request: 502 Bad Gateway", "unavailable"),
+        ("created openai request: Rate limit reached", "rate_limited"),
+        ("created openai request: operation timed out", "timeout"),
+        ("invalid response: `I'm sorry, I can't`", "refused"),
+        ("invalid response: `80;`", "invalid_response"),
+        ("created openai request: invalid api key", "request"),
+        ("something else", "other"),
+    ] {
+        assert_eq!(llm_error_class(&anyhow!("{error}")), class, "{error}");
+    }
+}
+
+#[test]
+fn test_serve_and_push() {
+    metrics::RATINGS.with_label_values(&["rated"]).inc_by(3);
+    metrics::RATER_BACKLOG.set(12);
+    assert!(metrics::render().contains("rater_ratings_total{result=\"rated\"} 3"));
+
+    let addr = metrics::serve("127.0.0.1:0").unwrap();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+    assert!(response.contains("rater_backlog 12"));
+
+    // a pushgateway answering 200 to the first request
+    let gateway = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", gateway.local_addr().unwrap());
+    let received = thread::spawn(move || {
+        let (mut stream, _) = gateway.accept().unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0; 4096];
+        while !String::from_utf8_lossy(&request).contains("rater_backlog 12") {
+            let read = stream.read(&mut buf).unwrap();
+            assert!(read > 0);
+            request.extend_from_slice(&buf[..read]);
+        }
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+            .unwrap();
+        String::from_utf8(request).unwrap()
+    });
+    metrics::push(&url, "rater").unwrap();
+    let request = received.join().unwrap();
+    assert!(
+        request.starts_with("PUT /metrics/job/rater HTTP/1.1"),
+        "{request}"
+    );
+}
@@ -0,0 +1,86 @@
+use std::fs;
+
+use shared::{
+    migrate::{self, Applied, Status, MIGRATIONS},
+    store::{NewsStore, SurrealStore},
+};
+use surrealdb::{engine::local::Mem, Surreal};
+
+#[test]
+fn test_every_migration_file_is_embedded() {
+    let mut files: Vec<String> = fs::read_dir("../migrations")
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    files.sort();
+    let embedded: Vec<String> = MIGRATIONS
+        .iter()
+        .map(|m| format!("{:04}_{}.surql", m.version, m.name))
+        .collect();
+    assert_eq!(files, embedded);
+    for (i, m) in MIGRATIONS.iter().enumerate() {
+        assert_eq!(m.version as usize, i + 1);
+    }
+}
+
+#[test]
+fn test_status_check() {
+    let latest = MIGRATIONS.last().unwrap();
+    let applied = |version, name: &str| Applied {
+        version,
+        name: name.into(),
+    };
+    let up_to_date = Status {
+        applied: MIGRATIONS
+            .iter()
+            .map(|m| applied(m.version, m.name))
+            .collect(),
+        pending: vec![],
+    };
+    assert!(up_to_date.check().is_ok());
+
+    let behind = Status {
+        applied: vec![applied(1, "initial")],
+        pending: vec![2],
+    };
+    assert!(behind.check_not_ahead().is_ok());
+    assert!(behind.check().is_err());
+
+    let ahead = Status {
+        applied: vec![applied(latest.version + 1, "from_the_future")],
+        pending: vec![],
+    };
+    let err = ahead.check_not_ahead().unwrap_err().to_string();
+    assert!(err.contains("ahead of this binary"), "{err}");
+
+    let renamed = Status {
+        applied: vec![applied(1, "something_else")],
+        pending: vec![],
+    };
+    assert!(renamed.check_not_ahead().is_err());
+}
+
+#[tokio::test]
+async fn test_migrate_in_memory() {
+    let db = Surreal::new::<Mem>(()).await.unwrap();
+    db.use_ns("news").use_db("news").await.unwrap();
+    let store = SurrealStore::new(db);
+    assert!(store.check_schema().await.is_err());
+
+    let applied = store.migrate().await.unwrap();
+    assert_eq!(applied.len(), MIGRATIONS.len());
+    assert!(store.migrate().await.unwrap().is_empty());
+    store.check_schema().await.unwrap();
+
+    // a newer binary migrated the database
+    store
+        .db
+        .query("CREATE migrations:999 CONTENT { version: 999, name: 'from_the_future' }")
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+    assert!(store.check_schema().await.is_err());
+    assert!(store.migrate().await.is_err());
+    assert!(migrate::status(&store.db).await.unwrap().pending.is_empty());
+}
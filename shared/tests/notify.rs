@@ -0,0 +1,183 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use shared::notify::{
+    fingerprint, Aggregator, Message, Notifier, NotifyFile, Recorder, Route, Router, Severity,
+    Webhook,
+};
+use shared::Notify;
+
+fn route(recorder: &Recorder, min_severity: Severity, binaries: Option<&[&str]>) -> Route {
+    Route::new(
+        Arc::new(recorder.clone()),
+        min_severity,
+        binaries.map(|b| b.iter().map(|s| s.to_string()).collect()),
+    )
+}
+
+fn message(severity: Severity, text: &str) -> Message {
+    Message {
+        severity,
+        binary: "fetcher".into(),
+        text: text.into(),
+        html: None,
+    }
+}
+
+#[tokio::test]
+async fn test_routing() {
+    let everything = Recorder::default();
+    let errors = Recorder::default();
+    let rater = Recorder::default();
+    let router = Router::new(vec![
+        route(&everything, Severity::Info, None),
+        route(&errors, Severity::Error, None),
+        route(&rater, Severity::Info, Some(&["rater"])),
+    ]);
+    let fetcher = Notify::spawn(router, "fetcher", Duration::from_secs(60));
+    fetcher.info("started");
+    fetcher.warning("slow source");
+    fetcher.error("db: down");
+    fetcher.flush().await;
+
+    assert_eq!(everything.messages().len(), 3);
+    assert_eq!(
+        errors.messages(),
+        vec![message(Severity::Error, "db: down")]
+    );
+    assert_eq!(errors.messages()[0].to_string(), "fetcher: db: down");
+    assert!(rater.messages().is_empty());
+}
+
+#[test]
+fn test_example_file() {
+    let file = NotifyFile::load("../notify.example.json").unwrap();
+    assert_eq!(file.channels.len(), 5);
+    assert_eq!(file.channels[0].min_severity, Severity::Warning);
+    assert_eq!(file.channels[3].min_severity, Severity::Info);
+    assert_eq!(file.channels[4].binaries, Some(vec!["rater".into()]));
+    file.router().unwrap();
+}
+
+#[test]
+fn test_unknown_backend() {
+    let err = serde_json::from_str::<NotifyFile>(r#"{ "channels": [{ "type": "pigeon" }] }"#);
+    assert!(err.is_err());
+}
+
+#[tokio::test]
+async fn test_webhook_posts_json() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/alerts", listener.local_addr().unwrap());
+    let server = thread::spawn(move || {
+        let (mut socket, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(socket.try_clone().unwrap());
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if let Some(v) = line.to_lowercase().strip_prefix("content-length:") {
+                length = v.trim().parse().unwrap();
+            }
+            if line == "\r\n" {
+                break;
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        socket
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+            .unwrap();
+        serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+    });
+
+    let message = Message {
+        severity: Severity::Warning,
+        binary: "rater".into(),
+        text: "rating failed".into(),
+        html: None,
+    };
+    Webhook::new(url).send(&message).await.unwrap();
+    assert_eq!(
+        server.join().unwrap(),
+        serde_json::json!({ "severity": "warning", "binary": "rater", "text": "rating failed" })
+    );
+}
+
+#[test]
+fn test_fingerprint() {
+    assert_eq!(
+        fingerprint("recv: be::rtbf: https://rtbf.be/article/123 timed out after 6s\nCaused by: x"),
+        "recv: be::rtbf: <url> timed out after #"
+    );
+    assert_eq!(
+        fingerprint("news:abc123 https://a.fr/1 rating failed: invalid response"),
+        fingerprint("news:zz9 https://b.fr/2 rating failed: invalid response")
+    );
+    assert_ne!(fingerprint("db: down"), fingerprint("recv: down"));
+    assert_eq!(
+        fingerprint("recv: quebec::24heures: https://a.ca/1 timed out"),
+        "recv: quebec::24heures: <url> timed out"
+    );
+    assert_ne!(
+        fingerprint("recv: africa::tv5monde: timed out"),
+        fingerprint("recv: africa::tv6monde: timed out")
+    );
+}
+
+#[test]
+fn test_digest() {
+    let mut aggregator = Aggregator::default();
+    for i in 0..23 {
+        aggregator.push(message(
+            Severity::Error,
+            &format!("recv: be::rtbf: https://rtbf.be/{i}: fetch_article timeout"),
+        ));
+    }
+    aggregator.push(message(Severity::Error, "db: connection refused"));
+    aggregator.push(message(Severity::Warning, "slow source"));
+
+    let mut digest = aggregator.drain();
+    assert!(aggregator.is_empty());
+    assert_eq!(digest.len(), 2);
+    // sorted by severity, a single message is sent as is
+    assert_eq!(digest.remove(0), message(Severity::Warning, "slow source"));
+    assert_eq!(
+        digest[0].text,
+        "24 errors:\n23× recv: be::rtbf: https://rtbf.be/0: fetch_article timeout\n1× db: connection refused"
+    );
+}
+
+#[tokio::test]
+async fn test_notify_aggregates_until_flush() {
+    let recorder = Recorder::default();
+    let router = Router::new(vec![route(&recorder, Severity::Info, None)]);
+    let notify = Notify::spawn(router, "fetcher", Duration::from_secs(60 * 60));
+    // the first tick of the window fires right away
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    for i in 0..5 {
+        notify.error(format!("db: news:{i} already exists"));
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(recorder.messages().is_empty());
+    notify.flush().await;
+    let messages = recorder.messages();
+    assert_eq!(messages.len(), 1);
+    assert!(messages[0].text.starts_with("5 errors:\n5× db: news:0"));
+}
+
+#[tokio::test]
+async fn test_rate_limit() {
+    let recorder = Recorder::default();
+    let router = Router::new(vec![route(&recorder, Severity::Info, None).max_per_hour(2)]);
+    for text in ["a", "b", "c", "d"] {
+        router.dispatch(&message(Severity::Error, text)).await;
+    }
+    let sent: Vec<String> = recorder.messages().into_iter().map(|m| m.text).collect();
+    assert_eq!(sent, vec!["a", "b"]);
+}
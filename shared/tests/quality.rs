@@ -0,0 +1,97 @@
+use shared::quality::{self, assess, Paywalled, Quality};
+
+#[test]
+fn test_assess() {
+    let article = "le refuge a soigné plus de cent hérissons cette année ".repeat(20);
+    assert_eq!(
+        assess(&format!("<p>{article}</p>"), &article, None),
+        Quality::default()
+    );
+    assert_eq!(
+        assess(&format!("<p>{article}</p>"), &article, Some(true)),
+        Quality::default()
+    );
+
+    let teaser = "Le refuge a ouvert ses portes. Cet article est réservé aux abonnés.";
+    let quality = assess(&format!("<p>{teaser}</p>"), teaser, None);
+    assert!(quality.paywalled);
+    assert_eq!(
+        quality.flags,
+        [quality::SUBSCRIBER_PHRASE, quality::SHORT_BODY]
+    );
+
+    let quality = assess(
+        &format!("<div class=\"Paywall-Teaser\"><p>{teaser}</p></div>"),
+        teaser,
+        Some(false),
+    );
+    assert!(quality.paywalled);
+    assert_eq!(
+        quality.flags,
+        [
+            quality::NOT_FREE,
+            quality::PAYWALL_MARKER,
+            quality::SUBSCRIBER_PHRASE,
+            quality::SHORT_BODY
+        ]
+    );
+    let quality = assess(
+        &format!("<div class=\"Paywall-Teaser\"><p>{article}</p></div>"),
+        &article,
+        Some(false),
+    );
+    assert_eq!(quality.flags, [quality::NOT_FREE]);
+
+    // the markup of a paywall around a complete article, or its word in the text
+    assert_eq!(
+        assess(
+            &format!("<div id=\"no-paywall\"><p>{article} paywall</p></div>"),
+            &article,
+            None
+        ),
+        Quality::default()
+    );
+    let quality = assess(
+        "<p>Le paywall du journal.</p>",
+        "Le paywall du journal.",
+        None,
+    );
+    assert_eq!(quality.flags, [quality::SHORT_BODY]);
+
+    // short, but nothing says it's a paywall
+    let quality = assess("<p>Une brève.</p>", "Une brève.", None);
+    assert!(!quality.paywalled);
+    assert_eq!(quality.flags, [quality::SHORT_BODY]);
+}
+
+#[test]
+fn test_complete_article_quoting_a_phrase() {
+    let start = "le journal a changé de formule et son éditorial explique pourquoi ".repeat(10);
+    let end = "les lecteurs ont salué la décision lors de la réunion publique ".repeat(10);
+    let article = format!(
+        "{start} « il vous reste trois articles gratuits, la suite est réservée aux abonnés », \
+        affichait le site jusqu'ici. {end}"
+    );
+    assert_eq!(
+        assess(&format!("<p>{article}</p>"), &article, None),
+        Quality::default()
+    );
+
+    // the same phrase closing a long body isn't enough either
+    let article = format!("{start}{end} Pour lire la suite, abonnez-vous.");
+    assert_eq!(
+        assess(&format!("<p>{article}</p>"), &article, None),
+        Quality::default()
+    );
+}
+
+#[test]
+fn test_paywalled_mode() {
+    assert_eq!(Paywalled::parse(None).unwrap(), Paywalled::Rate);
+    assert_eq!(Paywalled::parse(Some("skip")).unwrap(), Paywalled::Skip);
+    assert_eq!(
+        Paywalled::parse(Some("downweight")).unwrap(),
+        Paywalled::Downweight
+    );
+    assert!(Paywalled::parse(Some("ignore")).is_err());
+}
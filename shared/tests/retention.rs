@@ -0,0 +1,43 @@
+use shared::retention::Policy;
+
+#[test]
+fn test_default_policy_wipes_after_60_days() {
+    let policy = Policy::default();
+    assert_eq!(policy.rules.len(), 1);
+    assert_eq!(policy.rules[0].max_age_days, Some(60));
+    let (clauses, vars) = policy.where_clauses();
+    assert_eq!(clauses, vec!["(true) AND string::len(html_body) > 0"]);
+    assert!(vars.is_empty());
+}
+
+#[test]
+fn test_rules_exclude_previous_ones() {
+    let policy: Policy = serde_json::from_str(
+        r#"{
+            "archive_dir": "/tmp/archive",
+            "rules": [
+                { "name": "used", "used": true },
+                { "name": "good", "min_rating": 80 },
+                { "name": "quebec", "tags": ["quebec"], "providers": ["quebec::lapresse"], "max_age_days": 30 }
+            ]
+        }"#,
+    )
+    .unwrap();
+    let (clauses, vars) = policy.where_clauses();
+    assert_eq!(
+        clauses[0],
+        "(used = $r0_used) AND string::len(html_body) > 0"
+    );
+    assert_eq!(
+        clauses[2],
+        "(provider INSIDE $r2_providers AND tags CONTAINSANY $r2_tags) AND !(used = $r0_used) AND !(rating >= $r1_min_rating) AND string::len(html_body) > 0"
+    );
+    assert_eq!(vars["r1_min_rating"], 80);
+    assert_eq!(vars["r2_tags"], serde_json::json!(["quebec"]));
+}
+
+#[test]
+#[should_panic]
+fn test_unknown_rule_field() {
+    serde_json::from_str::<Policy>(r#"{ "rules": [{ "name": "x", "max_age": 3 }] }"#).unwrap();
+}
@@ -0,0 +1,330 @@
+use chrono::{Duration, Utc};
+use shared::{
+    retention::Policy,
+    store::{Cursor, NewsFilter, NewsStore, NewsUpdate, SourceRun, SqliteStore, RERATE_NOTE},
+    DbNews,
+};
+
+fn news(link: &str, days_ago: i64) -> DbNews {
+    DbNews {
+        title: "title".into(),
+        link: link.to_string().into(),
+        provider: "fr::test".into(),
+        html_body: "<p>body</p>".into(),
+        text_body: "body".into(),
+        date: (Utc::now() - Duration::days(days_ago)).into(),
+        tags: vec!["fr".into()],
+        lang: Some("fr".into()),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_insert_and_merge() {
+    let store = SqliteStore::open_in_memory().unwrap();
+    store.insert(&news("https://a.fr/1", 0)).await.unwrap();
+    assert!(store.insert(&news("https://a.fr/1", 0)).await.is_err());
+    store
+        .merge_tags("https://a.fr/1", &["lme".into(), "fr".into()])
+        .await
+        .unwrap();
+    assert_eq!(store.seen_links().await.unwrap(), vec!["https://a.fr/1"]);
+    let unrated = store.unrated(None).await.unwrap();
+    assert_eq!(unrated.len(), 1);
+    assert_eq!(unrated[0].tags, vec!["fr", "lme"]);
+}
+
+#[tokio::test]
+async fn test_unrated_and_save_rating() {
+    let store = SqliteStore::open_in_memory().unwrap();
+    store.insert(&news("https://a.fr/1", 1)).await.unwrap();
+    store.insert(&news("https://a.fr/old", 10)).await.unwrap();
+    assert!(store
+        .unrated(Some(&["en".into()]))
+        .await
+        .unwrap()
+        .is_empty());
+
+    let mut unrated = store.unrated(Some(&["fr".into()])).await.unwrap();
+    assert_eq!(unrated.len(), 1);
+    let rated = &mut unrated[0];
+    rated.rating = Some(80);
+    rated.rating_travel = Some(10);
+    rated.tags.push("nature".into());
+    store.save_rating(rated).await.unwrap();
+    assert!(store.unrated(None).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_unrated_keeps_undetected_languages() {
+    let store = SqliteStore::open_in_memory().unwrap();
+    store.insert(&news("https://a.fr/1", 0)).await.unwrap();
+    let mut undetected = news("https://a.fr/2", 1);
+    undetected.lang = None;
+    store.insert(&undetected).await.unwrap();
+
+    let unrated = store.unrated(Some(&["en".into()])).await.unwrap();
+    assert_eq!(unrated.len(), 1);
+    assert_eq!(unrated[0].link, "https://a.fr/2");
+    assert_eq!(store.unrated(Some(&["fr".into()])).await.unwrap().len(), 2);
+    // the backlog counts what the rater will rate
+    assert_eq!(store.backlog(Some(&["en".into()])).await.unwrap(), 1);
+    assert_eq!(store.backlog(None).await.unwrap(), 2);
+}
+
+#[tokio::test]
+async fn test_reset_rating_of_old_news() {
+    let store = SqliteStore::open_in_memory().unwrap();
+    let mut old = news("https://a.fr/old", 30);
+    old.rating = Some(20);
+    old.note = "rating failed: 'error rating'".into();
+    store.insert(&old).await.unwrap();
+    assert_eq!(store.backlog(None).await.unwrap(), 0);
+
+    assert!(store.reset_rating("news:1").await.unwrap());
+    assert!(!store.reset_rating("news:2").await.unwrap());
+    assert_eq!(store.backlog(None).await.unwrap(), 1);
+    let unrated = store.unrated(None).await.unwrap();
+    assert_eq!(unrated.len(), 1);
+    assert_eq!(unrated[0].note, RERATE_NOTE);
+}
+
+#[tokio::test]
+async fn test_quality_columns_added_to_old_files() {
+    let path = std::env::temp_dir().join(format!(
+        "news-scraper-quality-{}.sqlite",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    // the news table as it was before the quality columns
+    rusqlite::Connection::open(&path)
+        .unwrap()
+        .execute_batch(
+            "CREATE TABLE news (
+                id INTEGER PRIMARY KEY, html_body TEXT NOT NULL, text_body TEXT NOT NULL,
+                caption TEXT NOT NULL, date TEXT NOT NULL, link TEXT NOT NULL UNIQUE,
+                note TEXT NOT NULL DEFAULT '', provider TEXT NOT NULL, rating INTEGER,
+                rating_travel INTEGER, tags TEXT NOT NULL DEFAULT '[]', title TEXT NOT NULL,
+                used INTEGER NOT NULL DEFAULT 0, lang TEXT, lang_confidence REAL,
+                translations TEXT NOT NULL DEFAULT '{}'
+            );",
+        )
+        .unwrap();
+    let store = SqliteStore::open(&path).unwrap();
+    let mut paywalled = news("https://a.fr/1", 0);
+    paywalled.paywalled = true;
+    paywalled.quality_flags = vec!["subscriber_phrase".into(), "short_body".into()];
+    store.insert(&paywalled).await.unwrap();
+    drop(store);
+
+    // opening it again doesn't add them twice
+    let store = SqliteStore::open(&path).unwrap();
+    let unrated = store.unrated(None).await.unwrap();
+    assert!(unrated[0].paywalled);
+    assert_eq!(
+        unrated[0].quality_flags,
+        ["subscriber_phrase", "short_body"]
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_retention() {
+    let store = SqliteStore::open_in_memory().unwrap();
+    store.insert(&news("https://a.fr/new", 1)).await.unwrap();
+    store.insert(&news("https://a.fr/old", 90)).await.unwrap();
+    let mut kept = news("https://a.fr/used", 90);
+    kept.used = true;
+    store.insert(&kept).await.unwrap();
+    let policy: Policy = serde_json::from_str(
+        r#"{ "rules": [{ "name": "used", "used": true }, { "name": "default", "max_age_days": 60 }] }"#,
+    )
+    .unwrap();
+
+    let dry = store.apply_retention(&policy, true).await.unwrap();
+    assert_eq!((dry[0].matched, dry[0].wiped), (1, 0));
+    assert_eq!((dry[1].matched, dry[1].wiped), (2, 1));
+
+    store.apply_retention(&policy, false).await.unwrap();
+    let after = store.apply_retention(&policy, true).await.unwrap();
+    assert_eq!((after[1].matched, after[1].wiped), (1, 0));
+}
+
+#[tokio::test]
+async fn test_retention_in_batches() {
+    let store = SqliteStore::open_in_memory().unwrap();
+    for i in 0..1200 {
+        let days_ago = if i % 2 == 0 { 90 } else { 1 };
+        store
+            .insert(&news(&format!("https://a.fr/{i}"), days_ago))
+            .await
+            .unwrap();
+    }
+    let policy = Policy::default();
+    let report = store.apply_retention(&policy, false).await.unwrap();
+    assert_eq!((report[0].matched, report[0].wiped), (1200, 600));
+    let after = store.apply_retention(&policy, true).await.unwrap();
+    assert_eq!((after[0].matched, after[0].wiped), (600, 0));
+}
+
+#[tokio::test]
+async fn test_archive_stays_in_its_directory() {
+    let root = std::env::temp_dir().join(format!("news-scraper-archive-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    let dir = root.join("archive");
+    let store = SqliteStore::open_in_memory().unwrap();
+    store.insert(&news("https://a.fr/old", 90)).await.unwrap();
+    let policy: Policy = serde_json::from_value(serde_json::json!({
+        "archive_dir": dir,
+        "rules": [{ "name": "../../old/news", "max_age_days": 60 }]
+    }))
+    .unwrap();
+
+    let report = store.apply_retention(&policy, false).await.unwrap();
+    assert_eq!(report[0].archived, 1);
+    let files: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|f| f.unwrap().file_name().into_string().unwrap())
+        .collect();
+    assert_eq!(files.len(), 1);
+    assert!(files[0].ends_with("-______old_news.jsonl.zst"), "{files:?}");
+    assert_eq!(std::fs::read_dir(&root).unwrap().count(), 1);
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_source_runs() {
+    let store = SqliteStore::open_in_memory().unwrap();
+    let mut run = SourceRun {
+        source: "fr::test".into(),
+        started: (Utc::now() - Duration::minutes(2)).into(),
+        finished: Utc::now().into(),
+        inserted: 3,
+        merged: 1,
+        failed: 0,
+        error: None,
+    };
+    store.record_run(&run).await.unwrap();
+    run.error = Some("timeout".into());
+    store.record_run(&run).await.unwrap();
+    let runs = store.last_runs().await.unwrap();
+    assert_eq!(runs.len(), 1);
+    assert_eq!(runs[0].error.as_deref(), Some("timeout"));
+    assert_eq!(runs[0].inserted, 3);
+
+    store.set_source_enabled("fr::test", false).await.unwrap();
+    store.set_source_enabled("fr::other", true).await.unwrap();
+    assert_eq!(store.disabled_sources().await.unwrap(), vec!["fr::test"]);
+}
+
+#[tokio::test]
+async fn test_news_filter() {
+    let store = SqliteStore::open_in_memory().unwrap();
+    for (link, days_ago, tag, rating) in [
+        ("https://a.fr/1", 0, "fr", Some(80)),
+        ("https://a.fr/2", 1, "fr", Some(40)),
+        ("https://a.fr/3", 3, "fr", Some(90)),
+        ("https://a.be/1", 0, "be", Some(95)),
+        ("https://a.fr/4", 0, "fr", None),
+    ] {
+        let mut news = news(link, days_ago);
+        news.tags = vec![tag.into()];
+        news.rating = rating;
+        store.insert(&news).await.unwrap();
+    }
+    let links = |news: Vec<DbNews>| news.into_iter().map(|n| n.link).collect::<Vec<_>>();
+
+    let filter = NewsFilter {
+        region: Some("fr".into()),
+        min_rating: Some(50),
+        ..Default::default()
+    };
+    let news = store.news(&filter).await.unwrap();
+    assert_eq!(links(news), ["https://a.fr/1", "https://a.fr/3"]);
+
+    let filter = NewsFilter {
+        since: Some(Utc::now() - Duration::days(2)),
+        limit: Some(2),
+        ..Default::default()
+    };
+    let news = store.news(&filter).await.unwrap();
+    assert_eq!(news.len(), 2);
+    assert!(news[0].date >= news[1].date);
+}
+
+#[tokio::test]
+async fn test_news_cursor_and_update() {
+    let store = SqliteStore::open_in_memory().unwrap();
+    // the same date for all, the id breaks the tie
+    let date = Utc::now();
+    for i in 0..5 {
+        let mut news = news(&format!("https://a.fr/{i}"), 0);
+        news.date = date.into();
+        news.title = format!("Title {i}").into();
+        store.insert(&news).await.unwrap();
+    }
+
+    let mut filter = NewsFilter {
+        limit: Some(2),
+        ..Default::default()
+    };
+    let mut titles = vec![];
+    loop {
+        let page = store.news(&filter).await.unwrap();
+        titles.extend(page.iter().map(|n| n.title.to_string()));
+        match page.last().and_then(Cursor::after) {
+            Some(cursor) if page.len() == 2 => {
+                let cursor: Cursor = cursor.to_string().parse().unwrap();
+                filter.after = Some(cursor);
+            }
+            _ => break,
+        }
+    }
+    assert_eq!(
+        titles,
+        ["Title 4", "Title 3", "Title 2", "Title 1", "Title 0"]
+    );
+
+    let update = NewsUpdate {
+        used: Some(true),
+        tags: Some(vec!["fr".into(), "cats".into()]),
+        ..Default::default()
+    };
+    let updated = store.update_news("news:2", &update).await.unwrap().unwrap();
+    assert!(updated.used);
+    assert_eq!(updated.note, "");
+    assert!(store.update_news("42", &update).await.unwrap().is_none());
+    assert!(store.get_news("42").await.unwrap().is_none());
+
+    let filter = NewsFilter {
+        tags: vec!["cats".into(), "fr".into()],
+        used: Some(true),
+        text: Some("TITLE 1".into()),
+        ..Default::default()
+    };
+    let news = store.news(&filter).await.unwrap();
+    assert_eq!(news.len(), 1);
+    assert_eq!(
+        store.get_news("2").await.unwrap().unwrap().title,
+        news[0].title
+    );
+}
+
+#[tokio::test]
+async fn test_api_tokens() {
+    let store = SqliteStore::open_in_memory().unwrap();
+    assert!(store.create_token("alice", "hash", "").await.is_err());
+    assert!(store.set_user_activated("alice", false).await.unwrap());
+    store.create_token("alice", "hash", "laptop").await.unwrap();
+    let user = store.token_user("hash").await.unwrap().unwrap();
+    assert_eq!(user.name, "alice");
+    assert!(!user.activated);
+
+    store.set_user_activated("alice", true).await.unwrap();
+    assert!(store.token_user("hash").await.unwrap().unwrap().activated);
+    assert!(store.token_user("other").await.unwrap().is_none());
+
+    assert!(store.delete_token("hash").await.unwrap());
+    assert!(!store.delete_token("hash").await.unwrap());
+    assert!(store.token_user("hash").await.unwrap().is_none());
+}
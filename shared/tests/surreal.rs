@@ -0,0 +1,243 @@
+use std::sync::RwLock;
+
+use async_openai::{config::OpenAIConfig, Client as ChatClient};
+use chrono::{Duration, Local, Utc};
+use shared::{
+    ingest::{ingest, Ingested},
+    retention::Policy,
+    store::{NewsStore, SurrealStore},
+    DbNews, News,
+};
+use surrealdb::{
+    engine::local::{Db, Mem},
+    Surreal,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+/// a fresh in-memory database with every migration applied
+async fn memory_store() -> SurrealStore<Db> {
+    let db = Surreal::new::<Mem>(()).await.unwrap();
+    db.use_ns("news").use_db("news").await.unwrap();
+    let store = SurrealStore::new(db);
+    store.migrate().await.unwrap();
+    store
+}
+
+fn news(link: &str, provider: &str, days_ago: i64) -> News {
+    News {
+        provider: provider.into(),
+        date: Local::now() - Duration::days(days_ago),
+        title: "Un refuge pour les hérissons ouvre ses portes".into(),
+        caption: "les bénévoles ont soigné plus de cent animaux".into(),
+        body: "<p>Le refuge a ouvert ses portes cette semaine, et les bénévoles ont déjà soigné plus de cent hérissons blessés.</p><script>alert(1)</script>".into(),
+        link: link.into(),
+        tags: vec![provider.split_once("::").unwrap().0.into()],
+        ..Default::default()
+    }
+}
+
+/// answers every request like the chat completions api would, with `content` as the message
+async fn mock_llm(content: &'static str) -> ChatClient<OpenAIConfig> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 4096];
+            // read the headers, then as much body as content-length says
+            loop {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some((headers, body)) = text.split_once("\r\n\r\n") {
+                    let length = headers
+                        .lines()
+                        .find_map(|l| {
+                            l.to_lowercase()
+                                .strip_prefix("content-length:")
+                                .map(|v| v.trim().parse::<usize>().unwrap())
+                        })
+                        .unwrap_or(0);
+                    if body.len() >= length {
+                        break;
+                    }
+                }
+                if n == 0 {
+                    break;
+                }
+            }
+            let response = serde_json::json!({
+                "id": "chatcmpl-test",
+                "object": "chat.completion",
+                "created": 0,
+                "model": "gpt-5-nano",
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": content },
+                    "finish_reason": "stop"
+                }]
+            })
+            .to_string();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{response}",
+                response.len()
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        }
+    });
+    ChatClient::with_config(
+        OpenAIConfig::default()
+            .with_api_key("test")
+            .with_api_base(format!("http://{addr}/v1")),
+    )
+}
+
+#[tokio::test]
+async fn test_link_is_unique() {
+    let store = memory_store().await;
+    let db_news = DbNews {
+        title: "title".into(),
+        link: "https://example.com/a".into(),
+        provider: "fr::test".into(),
+        date: Utc::now().into(),
+        ..Default::default()
+    };
+    store.insert(&db_news).await.unwrap();
+    assert!(store.insert(&db_news).await.is_err());
+    assert_eq!(store.seen_links().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_ingest_inserts_then_merges_tags() {
+    let store = memory_store().await;
+    let seen = RwLock::new(store.seen_links().await.unwrap());
+    let link = "https://example.com/herissons";
+
+    let ingested = ingest(&store, &seen, news(link, "fr::google", 0)).await;
+    assert_eq!(ingested.unwrap(), Ingested::Inserted);
+    let ingested = ingest(&store, &seen, news(link, "lme::geo", 0)).await;
+    assert_eq!(ingested.unwrap(), Ingested::Merged);
+    // a second merge of the same tags must not duplicate them
+    let ingested = ingest(&store, &seen, news(link, "lme::geo", 0)).await;
+    assert_eq!(ingested.unwrap(), Ingested::Merged);
+
+    let stored = store.unrated(None).await.unwrap();
+    assert_eq!(stored.len(), 1);
+    let stored = &stored[0];
+    assert_eq!(stored.tags, vec!["fr", "lme"]);
+    assert_eq!(stored.provider, "fr::google");
+    assert!(!stored.html_body.contains("script"));
+    assert!(stored.text_body.starts_with("Le refuge a ouvert"));
+    assert_eq!(stored.lang.as_deref(), Some("fr"));
+    assert_eq!(seen.read().unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn test_unrated_selection() {
+    let store = memory_store().await;
+    let seen = RwLock::new(Vec::new());
+    for (link, days_ago) in [
+        ("https://example.com/new", 0),
+        ("https://example.com/old", 8),
+    ] {
+        ingest(&store, &seen, news(link, "fr::google", days_ago))
+            .await
+            .unwrap();
+    }
+    let mut failed = store.unrated(None).await.unwrap().remove(0);
+    assert_eq!(failed.link, "https://example.com/new");
+    failed.note = "error rating: timeout".into();
+    store.save_rating(&failed).await.unwrap();
+    ingest(
+        &store,
+        &seen,
+        news("https://example.com/other", "fr::google", 1),
+    )
+    .await
+    .unwrap();
+
+    let unrated = store.unrated(None).await.unwrap();
+    assert_eq!(unrated.len(), 1);
+    assert_eq!(unrated[0].link, "https://example.com/other");
+    assert_eq!(store.unrated(Some(&["fr".into()])).await.unwrap().len(), 1);
+    assert!(store
+        .unrated(Some(&["en".into()]))
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_rate_and_save() {
+    let store = memory_store().await;
+    let seen = RwLock::new(Vec::new());
+    ingest(
+        &store,
+        &seen,
+        news("https://example.com/herissons", "fr::google", 0),
+    )
+    .await
+    .unwrap();
+    let openai = mock_llm("82,15;animals, nature").await;
+
+    let mut news = store.unrated(None).await.unwrap().remove(0);
+    let (rating, rating_travel, tags) = news.rate(&openai, "rate this").await.unwrap();
+    assert_eq!((rating, rating_travel), (82, 15));
+    assert_eq!(tags, vec!["animals", "nature"]);
+    store.save_rating(&news).await.unwrap();
+
+    assert!(store.unrated(None).await.unwrap().is_empty());
+    let saved: Option<DbNews> = store.db.select(news.id.clone().unwrap()).await.unwrap();
+    let saved = saved.unwrap();
+    assert_eq!(saved.rating, Some(82));
+    assert_eq!(saved.rating_travel, Some(15));
+    assert_eq!(saved.tags, vec!["fr", "animals", "nature"]);
+
+    // rated again, without the same tags twice
+    let key = news.id.clone().unwrap().id.to_raw();
+    assert!(store.reset_rating(&key).await.unwrap());
+    assert!(!store.reset_rating("nope").await.unwrap());
+    let mut news = store.unrated(None).await.unwrap().remove(0);
+    news.rate(&openai, "rate this").await.unwrap();
+    assert_eq!(news.tags, vec!["fr", "animals", "nature"]);
+}
+
+#[tokio::test]
+async fn test_invalid_rating_is_rejected_by_the_schema() {
+    let store = memory_store().await;
+    let seen = RwLock::new(Vec::new());
+    ingest(
+        &store,
+        &seen,
+        news("https://example.com/herissons", "fr::google", 0),
+    )
+    .await
+    .unwrap();
+    let mut news = store.unrated(None).await.unwrap().remove(0);
+    news.rating = Some(150);
+    assert!(store.save_rating(&news).await.is_err());
+}
+
+#[tokio::test]
+async fn test_retention_wipes_old_bodies() {
+    let store = memory_store().await;
+    let seen = RwLock::new(Vec::new());
+    for (link, days_ago) in [
+        ("https://example.com/new", 1),
+        ("https://example.com/old", 90),
+    ] {
+        ingest(&store, &seen, news(link, "fr::google", days_ago))
+            .await
+            .unwrap();
+    }
+    let policy = Policy::default();
+    let reports = store.apply_retention(&policy, true).await.unwrap();
+    assert_eq!((reports[0].matched, reports[0].wiped), (2, 1));
+    store.apply_retention(&policy, false).await.unwrap();
+    let reports = store.apply_retention(&policy, true).await.unwrap();
+    assert_eq!((reports[0].matched, reports[0].wiped), (1, 0));
+}
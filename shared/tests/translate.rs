@@ -0,0 +1,94 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    thread,
+};
+
+use async_openai::{config::OpenAIConfig, Client};
+use shared::{translate::Translation, DbNews};
+
+/// answers one chat completion with `content`, returns the request it got
+fn openai(content: &str) -> (String, thread::JoinHandle<serde_json::Value>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/v1", listener.local_addr().unwrap());
+    let response = serde_json::json!({
+        "id": "chatcmpl-1",
+        "object": "chat.completion",
+        "created": 0,
+        "model": "gpt-5-nano",
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": content },
+            "finish_reason": "stop"
+        }],
+        "usage": { "prompt_tokens": 10, "completion_tokens": 10, "total_tokens": 20 }
+    })
+    .to_string();
+    let server = thread::spawn(move || {
+        let (mut socket, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(socket.try_clone().unwrap());
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if let Some(v) = line.to_lowercase().strip_prefix("content-length:") {
+                length = v.trim().parse().unwrap();
+            }
+            if line == "\r\n" {
+                break;
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        write!(
+            socket,
+            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{response}",
+            response.len()
+        )
+        .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    });
+    (url, server)
+}
+
+#[tokio::test]
+async fn test_translate_skips_the_own_and_known_languages() {
+    let (url, server) = openai(r#"{ "title": "Hedgehogs", "caption": "A shelter", "body": "x" }"#);
+    let client = Client::with_config(OpenAIConfig::new().with_api_base(url).with_api_key("k"));
+    let known = Translation {
+        title: "Igel".into(),
+        caption: "Ein Tierheim".into(),
+        body: None,
+    };
+    let mut news = DbNews {
+        title: "Hérissons".into(),
+        caption: "Un refuge".into(),
+        lang: Some("fr".into()),
+        translations: [("de".to_string(), known.clone())].into(),
+        ..Default::default()
+    };
+    let targets = ["fr", "de", "en"].map(String::from);
+    let added = news.translate(&client, &targets, false).await.unwrap();
+
+    assert_eq!(added, ["en"]);
+    assert_eq!(news.translations["de"], known);
+    // the body isn't asked for, and dropped when the model sends it anyway
+    assert_eq!(
+        news.translations["en"],
+        Translation {
+            title: "Hedgehogs".into(),
+            caption: "A shelter".into(),
+            body: None,
+        }
+    );
+    let request = server.join().unwrap();
+    let input = request["messages"][1]["content"].as_str().unwrap();
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(input).unwrap(),
+        serde_json::json!({ "title": "Hérissons", "caption": "Un refuge" })
+    );
+    assert!(request["messages"][0]["content"]
+        .as_str()
+        .unwrap()
+        .contains("from `fr` to `en`"));
+}